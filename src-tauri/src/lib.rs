@@ -1,7 +1,7 @@
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use sqlx::{postgres::PgPoolOptions, Column, Row, TypeInfo};
+use sqlx::{postgres::PgPoolOptions, Row};
 use std::sync::{Arc, Mutex};
 use sysinfo::{Disks, System};
 use tauri::{
@@ -12,6 +12,18 @@ use tauri::{
 use tauri_plugin_autostart::MacosLauncher;
 use uuid::Uuid;
 
+mod mongo;
+mod mysql;
+mod postgres;
+mod redis;
+mod sqlite;
+
+use mongo::MongoSessions;
+use mysql::MySqlSessions;
+use postgres::PgSessions;
+use redis::RedisSessions;
+use sqlite::SqliteDatabases;
+
 #[cfg(target_os = "macos")]
 use cocoa::appkit::{NSApp, NSApplication, NSApplicationActivationPolicy, NSImage};
 #[cfg(target_os = "macos")]
@@ -84,181 +96,6 @@ fn get_system_stats() -> SystemStats {
     }
 }
 
-// ============================================================================
-// PostgreSQL
-// ============================================================================
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct PgConnection {
-    pub id: String,
-    pub name: String,
-    pub host: String,
-    pub port: u16,
-    pub database: String,
-    pub user: String,
-    pub password: String,
-}
-
-#[derive(Debug, Serialize)]
-pub struct QueryResult {
-    pub columns: Vec<String>,
-    pub rows: Vec<Vec<serde_json::Value>>,
-    pub row_count: usize,
-}
-
-#[derive(Debug, Serialize)]
-pub struct TableInfo {
-    pub schema: String,
-    pub name: String,
-    pub table_type: String,
-}
-
-#[tauri::command]
-async fn pg_test_connection(connection: PgConnection) -> Result<String, String> {
-    let url = format!(
-        "postgres://{}:{}@{}:{}/{}",
-        connection.user, connection.password, connection.host, connection.port, connection.database
-    );
-
-    let pool = PgPoolOptions::new()
-        .max_connections(1)
-        .connect(&url)
-        .await
-        .map_err(|e| e.to_string())?;
-
-    sqlx::query("SELECT 1")
-        .execute(&pool)
-        .await
-        .map_err(|e| e.to_string())?;
-
-    pool.close().await;
-
-    Ok("Connection successful!".to_string())
-}
-
-#[tauri::command]
-async fn pg_get_tables(connection: PgConnection) -> Result<Vec<TableInfo>, String> {
-    let url = format!(
-        "postgres://{}:{}@{}:{}/{}",
-        connection.user, connection.password, connection.host, connection.port, connection.database
-    );
-
-    let pool = PgPoolOptions::new()
-        .max_connections(1)
-        .connect(&url)
-        .await
-        .map_err(|e| e.to_string())?;
-
-    let rows = sqlx::query(
-        r#"
-        SELECT table_schema, table_name, table_type
-        FROM information_schema.tables
-        WHERE table_schema NOT IN ('pg_catalog', 'information_schema')
-        ORDER BY table_schema, table_name
-        "#,
-    )
-    .fetch_all(&pool)
-    .await
-    .map_err(|e| e.to_string())?;
-
-    let tables: Vec<TableInfo> = rows
-        .iter()
-        .map(|row| TableInfo {
-            schema: row.get("table_schema"),
-            name: row.get("table_name"),
-            table_type: row.get("table_type"),
-        })
-        .collect();
-
-    pool.close().await;
-
-    Ok(tables)
-}
-
-#[tauri::command]
-async fn pg_execute_query(connection: PgConnection, query: String) -> Result<QueryResult, String> {
-    let url = format!(
-        "postgres://{}:{}@{}:{}/{}",
-        connection.user, connection.password, connection.host, connection.port, connection.database
-    );
-
-    let pool = PgPoolOptions::new()
-        .max_connections(1)
-        .connect(&url)
-        .await
-        .map_err(|e| e.to_string())?;
-
-    let rows = sqlx::query(&query)
-        .fetch_all(&pool)
-        .await
-        .map_err(|e| e.to_string())?;
-
-    let columns: Vec<String> = if !rows.is_empty() {
-        rows[0]
-            .columns()
-            .iter()
-            .map(|c| c.name().to_string())
-            .collect()
-    } else {
-        vec![]
-    };
-
-    let result_rows: Vec<Vec<serde_json::Value>> = rows
-        .iter()
-        .map(|row| {
-            row.columns()
-                .iter()
-                .enumerate()
-                .map(|(i, col)| {
-                    let type_name = col.type_info().name();
-                    match type_name {
-                        "INT2" | "INT4" | "INT8" => {
-                            if let Ok(v) = row.try_get::<i64, _>(i) {
-                                serde_json::Value::Number(v.into())
-                            } else {
-                                serde_json::Value::Null
-                            }
-                        }
-                        "FLOAT4" | "FLOAT8" | "NUMERIC" => {
-                            if let Ok(v) = row.try_get::<f64, _>(i) {
-                                serde_json::Number::from_f64(v)
-                                    .map(serde_json::Value::Number)
-                                    .unwrap_or(serde_json::Value::Null)
-                            } else {
-                                serde_json::Value::Null
-                            }
-                        }
-                        "BOOL" => {
-                            if let Ok(v) = row.try_get::<bool, _>(i) {
-                                serde_json::Value::Bool(v)
-                            } else {
-                                serde_json::Value::Null
-                            }
-                        }
-                        _ => {
-                            if let Ok(v) = row.try_get::<String, _>(i) {
-                                serde_json::Value::String(v)
-                            } else {
-                                serde_json::Value::Null
-                            }
-                        }
-                    }
-                })
-                .collect()
-        })
-        .collect();
-
-    let row_count = result_rows.len();
-
-    pool.close().await;
-
-    Ok(QueryResult {
-        columns,
-        rows: result_rows,
-        row_count,
-    })
-}
-
 // ============================================================================
 // App Sync (PostgreSQL)
 // ============================================================================
@@ -1097,8 +934,11 @@ fn set_stopwatch_tray(app: tauri::AppHandle, time: Option<String>) -> Result<(),
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    postgres::init_notice_capture();
+
     let mut builder = tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_store::Builder::default().build())
         .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_autostart::init(
@@ -1113,11 +953,81 @@ pub fn run() {
     }
 
     builder
+        .manage(PgSessions::default())
+        .manage(MySqlSessions::default())
+        .manage(RedisSessions::default())
+        .manage(MongoSessions::default())
+        .manage(SqliteDatabases::default())
         .invoke_handler(tauri::generate_handler![
             get_system_stats,
-            pg_test_connection,
-            pg_get_tables,
-            pg_execute_query,
+            // PostgreSQL
+            postgres::connections::pg_list_connections,
+            postgres::connections::pg_save_connection,
+            postgres::connections::pg_delete_connection,
+            postgres::conninfo::pg_parse_connection_string,
+            postgres::conninfo::pg_connection_from_environment,
+            postgres::conninfo::pg_connection_uri,
+            postgres::services::pg_import_service_connections,
+            postgres::pg_test_connection,
+            postgres::pg_open_session,
+            postgres::pg_close_session,
+            postgres::pg_list_sessions,
+            postgres::pg_get_tables,
+            postgres::schema::pg_get_schemas,
+            postgres::schema::pg_describe_table,
+            postgres::schema::pg_describe_schema,
+            postgres::diff::pg_diff_schemas,
+            postgres::diagram::pg_er_diagram,
+            postgres::diagram::pg_export_er_diagram,
+            postgres::pg_execute_query,
+            postgres::pg_stream_query,
+            postgres::pg_cancel_query,
+            postgres::history::pg_search_history,
+            postgres::history::pg_rerun_history_entry,
+            postgres::history::pg_clear_history,
+            postgres::snippets::pg_list_snippets,
+            postgres::snippets::pg_save_snippet,
+            postgres::snippets::pg_delete_snippet,
+            postgres::snippets::pg_execute_snippet,
+            postgres::edit::pg_preview_table_changes,
+            postgres::edit::pg_apply_table_changes,
+            postgres::explain::pg_explain,
+            postgres::export::pg_export_query,
+            postgres::import::pg_preview_import,
+            postgres::import::pg_import_file,
+            postgres::script::pg_execute_script,
+            postgres::transaction::pg_begin_transaction,
+            postgres::transaction::pg_commit_transaction,
+            postgres::transaction::pg_rollback_transaction,
+            // Redis
+            redis::redis_list_connections,
+            redis::redis_save_connection,
+            redis::redis_delete_connection,
+            redis::redis_test_connection,
+            redis::redis_scan_keys,
+            redis::redis_get_value,
+            redis::redis_set_ttl,
+            redis::redis_execute_command,
+            // MongoDB
+            mongo::mongo_list_connections,
+            mongo::mongo_save_connection,
+            mongo::mongo_delete_connection,
+            mongo::mongo_test_connection,
+            mongo::mongo_list_databases,
+            mongo::mongo_list_collections,
+            mongo::mongo_find,
+            mongo::mongo_aggregate,
+            mongo::mongo_list_indexes,
+            mongo::mongo_collection_stats,
+            // SQLite
+            sqlite::sqlite_open,
+            sqlite::sqlite_close,
+            sqlite::sqlite_list_databases,
+            sqlite::sqlite_get_tables,
+            sqlite::sqlite_list_indexes,
+            sqlite::sqlite_describe_table,
+            sqlite::sqlite_execute_query,
+            sqlite::sqlite_pragma,
             update_tray_title,
             set_stopwatch_tray,
             refresh_tray_menu,
@@ -1144,6 +1054,15 @@ pub fn run() {
             #[cfg(desktop)]
             setup_tray(app.handle())?;
 
+            app.manage(postgres::SavedConnections::load(app.path().app_data_dir()?));
+            app.manage(postgres::QueryHistory::load(app.path().app_data_dir()?));
+            app.manage(postgres::SnippetLibrary::load(app.path().app_data_dir()?));
+            app.manage(redis::SavedRedisConnections::load(app.path().app_data_dir()?));
+            app.manage(mongo::SavedMongoConnections::load(app.path().app_data_dir()?));
+            postgres::spawn_idle_reaper::<sqlx::PgConnection>(app.handle());
+            postgres::spawn_idle_reaper::<sqlx::MySqlConnection>(app.handle());
+            postgres::spawn_service_watcher(app.handle());
+
             // Set up popover window on macOS
             #[cfg(target_os = "macos")]
             {
@@ -1210,6 +1129,14 @@ pub fn run() {
         .build(tauri::generate_context!())
         .expect("error while building tauri application")
         .run(|app, event| {
+            // Close database sessions cleanly before the process goes away
+            if let tauri::RunEvent::Exit = event {
+                tauri::async_runtime::block_on(app.state::<PgSessions>().close_all());
+                tauri::async_runtime::block_on(app.state::<MySqlSessions>().close_all());
+                tauri::async_runtime::block_on(app.state::<MongoSessions>().close_all());
+                app.state::<RedisSessions>().close_all();
+            }
+
             if let tauri::RunEvent::Reopen { has_visible_windows, .. } = event {
                 if !has_visible_windows {
                     if let Some(window) = app.get_webview_window("main") {
@@ -0,0 +1,141 @@
+//! MongoDB documents to and from Extended JSON.
+//!
+//! Queries are typed as Extended JSON so they can hold BSON types plain JSON lacks, e.g.
+//! `{"_id": {"$oid": "..."}}` or `{"at": {"$gte": {"$date": "2024-01-01T00:00:00Z"}}}`.
+//! Documents come back as relaxed Extended JSON, which keeps ordinary numbers, strings and
+//! booleans readable and tags only the other types.
+
+use mongodb::bson::{Bson, Document};
+use serde_json::Value;
+
+// Integers outside this range lose precision in JavaScript, so they keep their `$numberLong` form
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+/// Parse an Extended JSON object; blank text is an empty document. `what` names the
+/// input in errors, e.g. `filter`.
+pub fn parse_document(text: Option<&str>, what: &str) -> Result<Document, String> {
+    let text = text.map(str::trim).unwrap_or_default();
+    if text.is_empty() {
+        return Ok(Document::new());
+    }
+
+    match parse_bson(text, what)? {
+        Bson::Document(document) => Ok(document),
+        _ => Err(format!("The {} must be a JSON object", what)),
+    }
+}
+
+/// Parse an Extended JSON array of aggregation stages
+pub fn parse_pipeline(text: &str) -> Result<Vec<Document>, String> {
+    let Bson::Array(stages) = parse_bson(text, "pipeline")? else {
+        return Err("The pipeline must be a JSON array of stages".to_string());
+    };
+
+    stages
+        .into_iter()
+        .enumerate()
+        .map(|(i, stage)| match stage {
+            Bson::Document(document) => Ok(document),
+            _ => Err(format!("Pipeline stage {} must be a JSON object", i + 1)),
+        })
+        .collect()
+}
+
+fn parse_bson(text: &str, what: &str) -> Result<Bson, String> {
+    let value: Value = serde_json::from_str(text).map_err(|e| format!("Invalid {}: {}", what, e))?;
+    Bson::try_from(value).map_err(|e| format!("Invalid {}: {}", what, e))
+}
+
+/// A document as relaxed Extended JSON, fields in their stored order
+pub fn document_to_json(document: Document) -> Value {
+    bson_to_json(Bson::Document(document))
+}
+
+fn bson_to_json(value: Bson) -> Value {
+    match value {
+        Bson::Document(document) => Value::Object(document.into_iter().map(|(k, v)| (k, bson_to_json(v))).collect()),
+        Bson::Array(items) => Value::Array(items.into_iter().map(bson_to_json).collect()),
+        Bson::Int64(n) if !(-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&n) => value.into_canonical_extjson(),
+        other => other.into_relaxed_extjson(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::oid::ObjectId;
+    use mongodb::bson::{doc, DateTime};
+
+    #[test]
+    fn parses_extended_json_documents() {
+        let filter = parse_document(
+            Some(r#" {"_id": {"$oid": "65a1b2c3d4e5f60718293a4b"}, "at": {"$gte": {"$date": "2024-01-01T00:00:00Z"}}} "#),
+            "filter",
+        )
+        .unwrap();
+        assert_eq!(
+            filter,
+            doc! {
+                "_id": ObjectId::parse_str("65a1b2c3d4e5f60718293a4b").unwrap(),
+                "at": { "$gte": DateTime::parse_rfc3339_str("2024-01-01T00:00:00Z").unwrap() },
+            }
+        );
+
+        assert_eq!(parse_document(None, "filter").unwrap(), Document::new());
+        assert_eq!(parse_document(Some("  "), "filter").unwrap(), Document::new());
+        assert_eq!(parse_document(Some("[1]"), "filter").unwrap_err(), "The filter must be a JSON object");
+        assert!(parse_document(Some("{\"a\":"), "sort").unwrap_err().starts_with("Invalid sort: "));
+    }
+
+    #[test]
+    fn keeps_the_order_of_sort_keys() {
+        let sort = parse_document(Some(r#"{"b": 1, "a": -1}"#), "sort").unwrap();
+        assert_eq!(sort.keys().collect::<Vec<_>>(), ["b", "a"]);
+    }
+
+    #[test]
+    fn parses_pipelines_stage_by_stage() {
+        let pipeline = parse_pipeline(r#"[{"$match": {"n": {"$numberLong": "5"}}}, {"$sort": {"z": 1, "y": 1}}]"#).unwrap();
+        assert_eq!(pipeline, vec![doc! { "$match": { "n": 5_i64 } }, doc! { "$sort": { "z": 1, "y": 1 } }]);
+        assert_eq!(pipeline[1].get_document("$sort").unwrap().keys().collect::<Vec<_>>(), ["z", "y"]);
+
+        assert_eq!(parse_pipeline("{}").unwrap_err(), "The pipeline must be a JSON array of stages");
+        assert_eq!(parse_pipeline("[{}, 2]").unwrap_err(), "Pipeline stage 2 must be a JSON object");
+        assert!(parse_pipeline("[").unwrap_err().starts_with("Invalid pipeline: "));
+    }
+
+    #[test]
+    fn writes_documents_as_relaxed_extended_json() {
+        let id = ObjectId::parse_str("65a1b2c3d4e5f60718293a4b").unwrap();
+        let document = doc! {
+            "z": 1,
+            "_id": id,
+            "small": 42_i64,
+            "large": 1_i64 << 60,
+            "negative": -(1_i64 << 60),
+            "ratio": 0.5,
+            "tags": ["a", { "b": true }, null],
+        };
+
+        let json = document_to_json(document);
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "z": 1,
+                "_id": { "$oid": "65a1b2c3d4e5f60718293a4b" },
+                "small": 42,
+                "large": { "$numberLong": "1152921504606846976" },
+                "negative": { "$numberLong": "-1152921504606846976" },
+                "ratio": 0.5,
+                "tags": ["a", { "b": true }, null],
+            })
+        );
+        let keys: Vec<&String> = json.as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["z", "_id", "small", "large", "negative", "ratio", "tags"]);
+        assert_eq!(bson_to_json(Bson::Int64(MAX_SAFE_INTEGER)), serde_json::json!(MAX_SAFE_INTEGER));
+        assert_eq!(
+            bson_to_json(Bson::Int64(MAX_SAFE_INTEGER + 1)),
+            serde_json::json!({ "$numberLong": "9007199254740992" })
+        );
+    }
+}
@@ -0,0 +1,524 @@
+use futures_util::TryStreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::{ClientOptions, ServerAddress, Tls, TlsOptions};
+use mongodb::results::CollectionType;
+use mongodb::{Client, Cursor};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use tauri::State;
+
+use crate::postgres::{ConnectionStore, StoredConnection};
+
+mod json;
+
+// Also how long to wait for a usable server before a command fails
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Documents read by `mongo_find` and `mongo_aggregate` unless the caller asks for another number
+const DEFAULT_MAX_DOCUMENTS: usize = 1_000;
+
+/// A saved MongoDB deployment, stored the same way as a `PgConnection`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MongoConnection {
+    pub id: String,
+    pub name: String,
+    /// Server host, or the cluster's DNS name when `srv` is set
+    pub host: String,
+    pub port: u16,
+    /// Look up the servers through DNS SRV records, as a `mongodb+srv://` URI does; `port`
+    /// is unused and TLS is on unless the records say otherwise
+    #[serde(default)]
+    pub srv: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Only ever sent by the webview when saving or testing; never serialized back
+    #[serde(default, skip_serializing)]
+    pub password: String,
+    /// Database the user is defined in; `admin` when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_source: Option<String>,
+    /// Replica set name to require of the servers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replica_set: Option<String>,
+    /// Talk to `host` alone instead of discovering the rest of its replica set
+    #[serde(default)]
+    pub direct_connection: bool,
+    /// Folder path in the connection list, e.g. `prod/eu`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub tls: bool,
+    /// CA certificate (PEM) to verify the servers with instead of the system roots
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_ca_file: Option<String>,
+    /// Client certificate and its key in one PEM file, for mutual TLS or X.509 authentication
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_cert_key_file: Option<String>,
+}
+
+impl StoredConnection for MongoConnection {
+    const KEYRING_SERVICE: &'static str = "devtoolssuite-mongo";
+    const CONNECTIONS_FILE: &'static str = "mongo-connections.json";
+    const SECRETS_FILE: &'static str = "mongo-secrets.enc";
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn password_mut(&mut self) -> &mut String {
+        &mut self.password
+    }
+}
+
+/// Saved MongoDB connections
+pub type SavedMongoConnections = ConnectionStore<MongoConnection>;
+
+/// Clients keyed by `MongoConnection.id`, kept in Tauri managed state.
+/// Each client keeps its own connection pool to the deployment.
+#[derive(Default)]
+pub struct MongoSessions {
+    // (connection fingerprint, client)
+    clients: std::sync::Mutex<HashMap<String, (String, Client)>>,
+}
+
+impl MongoSessions {
+    /// The client for `connection`, creating it first if there is none or its settings changed
+    async fn acquire(&self, connection: &MongoConnection) -> Result<Client, String> {
+        let fingerprint = connection_fingerprint(connection);
+        if let Some((open_fingerprint, client)) = self.clients.lock().unwrap().get(&connection.id) {
+            if *open_fingerprint == fingerprint {
+                return Ok(client.clone());
+            }
+        }
+
+        let client = Client::with_options(client_options(connection).await?).map_err(|e| e.to_string())?;
+        self.clients
+            .lock()
+            .unwrap()
+            .insert(connection.id.clone(), (fingerprint, client.clone()));
+        Ok(client)
+    }
+
+    pub fn close(&self, connection_id: &str) {
+        self.clients.lock().unwrap().remove(connection_id);
+    }
+
+    /// Shut every client down without waiting for operations still running, e.g. when the app exits
+    pub async fn close_all(&self) {
+        let clients: Vec<Client> = self.clients.lock().unwrap().drain().map(|(_, (_, client))| client).collect();
+        for client in clients {
+            client.shutdown().immediate(true).await;
+        }
+    }
+}
+
+fn connection_fingerprint(connection: &MongoConnection) -> String {
+    format!(
+        "{}\u{0}{}\u{0}{}\u{0}{:?}\u{0}{}\u{0}{:?}\u{0}{:?}\u{0}{}\u{0}{}\u{0}{:?}\u{0}{:?}",
+        connection.host,
+        connection.port,
+        connection.srv,
+        connection.username,
+        connection.password,
+        connection.auth_source,
+        connection.replica_set,
+        connection.direct_connection,
+        connection.tls,
+        connection.tls_ca_file,
+        connection.tls_cert_key_file,
+    )
+}
+
+/// Build the client options field by field, so no value needs URL escaping
+async fn client_options(connection: &MongoConnection) -> Result<ClientOptions, String> {
+    let mut options = if connection.srv {
+        ClientOptions::parse(format!("mongodb+srv://{}/", connection.host))
+            .await
+            .map_err(|e| e.to_string())?
+    } else {
+        let mut options = ClientOptions::default();
+        options.hosts = vec![ServerAddress::Tcp {
+            host: connection.host.clone(),
+            port: Some(connection.port),
+        }];
+        options
+    };
+
+    options.app_name = Some("Dev Tools Suite".to_string());
+    options.connect_timeout = Some(CONNECT_TIMEOUT);
+    options.server_selection_timeout = Some(CONNECT_TIMEOUT);
+    options.direct_connection = connection.direct_connection.then_some(true);
+    if let Some(replica_set) = connection.replica_set.clone().filter(|r| !r.is_empty()) {
+        options.repl_set_name = Some(replica_set);
+    }
+
+    if let Some(username) = connection.username.clone().filter(|u| !u.is_empty()) {
+        let mut credential = options.credential.take().unwrap_or_default();
+        credential.username = Some(username);
+        credential.password = (!connection.password.is_empty()).then(|| connection.password.clone());
+        if let Some(source) = connection.auth_source.clone().filter(|s| !s.is_empty()) {
+            credential.source = Some(source);
+        }
+        options.credential = Some(credential);
+    }
+
+    if connection.tls {
+        let certificates = [&connection.tls_ca_file, &connection.tls_cert_key_file];
+        for path in certificates.into_iter().flatten() {
+            if !std::path::Path::new(path).is_file() {
+                return Err(format!("Certificate file not found: {}", path));
+            }
+        }
+
+        let tls = TlsOptions::builder()
+            .ca_file_path(connection.tls_ca_file.as_ref().map(PathBuf::from))
+            .cert_key_file_path(connection.tls_cert_key_file.as_ref().map(PathBuf::from))
+            .build();
+        options.tls = Some(Tls::Enabled(tls));
+    }
+
+    Ok(options)
+}
+
+/// The saved connection's client
+async fn session(
+    sessions: &MongoSessions,
+    connections: &SavedMongoConnections,
+    connection_id: &str,
+) -> Result<Client, String> {
+    let connection = connections.get(connection_id).await?;
+    sessions.acquire(&connection).await
+}
+
+#[derive(Debug, Serialize)]
+pub struct MongoDatabaseInfo {
+    pub name: String,
+    pub size_on_disk: u64,
+    pub empty: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MongoCollectionInfo {
+    pub name: String,
+    /// `collection`, `view` or `timeseries`
+    pub collection_type: CollectionType,
+    pub read_only: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MongoIndex {
+    pub name: String,
+    /// Indexed fields in index order with their direction or kind, e.g. `{"a": 1, "b": "text"}`
+    pub keys: serde_json::Value,
+    pub unique: bool,
+    pub sparse: bool,
+    /// Hidden from the query planner
+    pub hidden: bool,
+    /// TTL index: documents are removed this many seconds after the indexed date
+    pub expire_after_secs: Option<u64>,
+    /// Only documents matching this filter are indexed
+    pub partial_filter: Option<serde_json::Value>,
+}
+
+/// Storage statistics of a collection, summed over its shards
+#[derive(Debug, Serialize, Default)]
+pub struct MongoCollectionStats {
+    pub count: u64,
+    /// Uncompressed size of the documents, in bytes
+    pub size: u64,
+    pub avg_obj_size: u64,
+    /// Space allocated on disk for the documents, in bytes
+    pub storage_size: u64,
+    pub total_index_size: u64,
+    /// Size of each index in bytes, in the order the server lists them
+    pub index_sizes: Vec<(String, u64)>,
+    pub capped: bool,
+}
+
+/// A find query as typed in the query editor. Documents are Extended JSON text.
+#[derive(Debug, Deserialize, Default)]
+pub struct MongoFindQuery {
+    pub filter: Option<String>,
+    pub projection: Option<String>,
+    pub sort: Option<String>,
+    pub skip: Option<u64>,
+    pub limit: Option<u64>,
+}
+
+/// Per-query knobs shared by `mongo_find` and `mongo_aggregate`
+#[derive(Debug, Deserialize, Default)]
+pub struct MongoQueryOptions {
+    /// Stop reading after this many documents (default 1 000)
+    pub max_documents: Option<usize>,
+    /// Have the server stop the query if it runs longer than this
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MongoQueryResult {
+    /// Documents as relaxed Extended JSON
+    pub documents: Vec<serde_json::Value>,
+    /// The query returned more documents than the cap; only the first ones were read
+    pub truncated: bool,
+}
+
+/// Read documents from a cursor up to `max_documents`
+async fn read_documents(mut cursor: Cursor<Document>, max_documents: usize) -> Result<MongoQueryResult, String> {
+    let mut documents = Vec::new();
+    while let Some(document) = cursor.try_next().await.map_err(|e| e.to_string())? {
+        if documents.len() == max_documents {
+            return Ok(MongoQueryResult { documents, truncated: true });
+        }
+        documents.push(json::document_to_json(document));
+    }
+
+    Ok(MongoQueryResult { documents, truncated: false })
+}
+
+#[tauri::command]
+pub async fn mongo_list_connections(
+    connections: State<'_, SavedMongoConnections>,
+) -> Result<Vec<MongoConnection>, String> {
+    Ok(connections.list().await)
+}
+
+/// Add or update a saved connection. An empty `password` keeps the stored one.
+/// Returns the connection as saved, without its password. An open client is
+/// replaced on next use if its settings changed.
+#[tauri::command]
+pub async fn mongo_save_connection(
+    connections: State<'_, SavedMongoConnections>,
+    connection: MongoConnection,
+) -> Result<MongoConnection, String> {
+    connections.save(connection).await
+}
+
+/// Forget a saved connection and its password, closing its client
+#[tauri::command]
+pub async fn mongo_delete_connection(
+    sessions: State<'_, MongoSessions>,
+    connections: State<'_, SavedMongoConnections>,
+    connection_id: String,
+) -> Result<bool, String> {
+    sessions.close(&connection_id);
+    connections.delete(&connection_id).await
+}
+
+/// Connect with unsaved settings and ping the deployment. An empty `password` uses the saved one.
+#[tauri::command]
+pub async fn mongo_test_connection(
+    connections: State<'_, SavedMongoConnections>,
+    mut connection: MongoConnection,
+) -> Result<String, String> {
+    if connection.password.is_empty() {
+        if let Ok(saved) = connections.get(&connection.id).await {
+            connection.password = saved.password;
+        }
+    }
+
+    let client = Client::with_options(client_options(&connection).await?).map_err(|e| e.to_string())?;
+    let ping = client.database("admin").run_command(doc! { "ping": 1 }).await;
+    client.shutdown().await;
+    ping.map_err(|e| e.to_string())?;
+
+    Ok("Connection successful!".to_string())
+}
+
+/// Databases the user may read, by name
+#[tauri::command]
+pub async fn mongo_list_databases(
+    sessions: State<'_, MongoSessions>,
+    connections: State<'_, SavedMongoConnections>,
+    connection_id: String,
+) -> Result<Vec<MongoDatabaseInfo>, String> {
+    let client = session(&sessions, &connections, &connection_id).await?;
+    let mut databases: Vec<MongoDatabaseInfo> = client
+        .list_databases()
+        .authorized_databases(true)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|database| MongoDatabaseInfo {
+            name: database.name,
+            size_on_disk: database.size_on_disk,
+            empty: database.empty,
+        })
+        .collect();
+
+    databases.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(databases)
+}
+
+/// Collections and views of a database, by name
+#[tauri::command]
+pub async fn mongo_list_collections(
+    sessions: State<'_, MongoSessions>,
+    connections: State<'_, SavedMongoConnections>,
+    connection_id: String,
+    database: String,
+) -> Result<Vec<MongoCollectionInfo>, String> {
+    let client = session(&sessions, &connections, &connection_id).await?;
+    let mut collections: Vec<MongoCollectionInfo> = client
+        .database(&database)
+        .list_collections()
+        .await
+        .map_err(|e| e.to_string())?
+        .map_ok(|collection| MongoCollectionInfo {
+            name: collection.name,
+            collection_type: collection.collection_type,
+            read_only: collection.info.read_only,
+        })
+        .try_collect()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    collections.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(collections)
+}
+
+/// Run a find query. `limit` in the query is the query's own; at most `max_documents`
+/// of the documents it returns are read.
+#[tauri::command]
+pub async fn mongo_find(
+    sessions: State<'_, MongoSessions>,
+    connections: State<'_, SavedMongoConnections>,
+    connection_id: String,
+    database: String,
+    collection: String,
+    query: MongoFindQuery,
+    options: Option<MongoQueryOptions>,
+) -> Result<MongoQueryResult, String> {
+    let options = options.unwrap_or_default();
+    let filter = json::parse_document(query.filter.as_deref(), "filter")?;
+    let projection = json::parse_document(query.projection.as_deref(), "projection")?;
+    let sort = json::parse_document(query.sort.as_deref(), "sort")?;
+
+    let client = session(&sessions, &connections, &connection_id).await?;
+    let collection = client.database(&database).collection::<Document>(&collection);
+    let mut find = collection.find(filter);
+    if !projection.is_empty() {
+        find = find.projection(projection);
+    }
+    if !sort.is_empty() {
+        find = find.sort(sort);
+    }
+    if let Some(skip) = query.skip {
+        find = find.skip(skip);
+    }
+    if let Some(limit) = query.limit {
+        find = find.limit(limit as i64);
+    }
+    if let Some(ms) = options.timeout_ms {
+        find = find.max_time(Duration::from_millis(ms));
+    }
+
+    let cursor = find.await.map_err(|e| e.to_string())?;
+    read_documents(cursor, options.max_documents.unwrap_or(DEFAULT_MAX_DOCUMENTS)).await
+}
+
+/// Run an aggregation pipeline given as an Extended JSON array of stages
+#[tauri::command]
+pub async fn mongo_aggregate(
+    sessions: State<'_, MongoSessions>,
+    connections: State<'_, SavedMongoConnections>,
+    connection_id: String,
+    database: String,
+    collection: String,
+    pipeline: String,
+    options: Option<MongoQueryOptions>,
+) -> Result<MongoQueryResult, String> {
+    let options = options.unwrap_or_default();
+    let pipeline = json::parse_pipeline(&pipeline)?;
+
+    let client = session(&sessions, &connections, &connection_id).await?;
+    let collection = client.database(&database).collection::<Document>(&collection);
+    let mut aggregate = collection.aggregate(pipeline);
+    if let Some(ms) = options.timeout_ms {
+        aggregate = aggregate.max_time(Duration::from_millis(ms));
+    }
+
+    let cursor = aggregate.await.map_err(|e| e.to_string())?;
+    read_documents(cursor, options.max_documents.unwrap_or(DEFAULT_MAX_DOCUMENTS)).await
+}
+
+#[tauri::command]
+pub async fn mongo_list_indexes(
+    sessions: State<'_, MongoSessions>,
+    connections: State<'_, SavedMongoConnections>,
+    connection_id: String,
+    database: String,
+    collection: String,
+) -> Result<Vec<MongoIndex>, String> {
+    let client = session(&sessions, &connections, &connection_id).await?;
+    client
+        .database(&database)
+        .collection::<Document>(&collection)
+        .list_indexes()
+        .await
+        .map_err(|e| e.to_string())?
+        .map_ok(|index| {
+            let options = index.options.unwrap_or_default();
+            MongoIndex {
+                name: options.name.unwrap_or_default(),
+                keys: json::document_to_json(index.keys),
+                unique: options.unique.unwrap_or(false),
+                sparse: options.sparse.unwrap_or(false),
+                hidden: options.hidden.unwrap_or(false),
+                expire_after_secs: options.expire_after.map(|d| d.as_secs()),
+                partial_filter: options.partial_filter_expression.map(json::document_to_json),
+            }
+        })
+        .try_collect()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Document count and sizes of a collection, from `$collStats` (views have none)
+#[tauri::command]
+pub async fn mongo_collection_stats(
+    sessions: State<'_, MongoSessions>,
+    connections: State<'_, SavedMongoConnections>,
+    connection_id: String,
+    database: String,
+    collection: String,
+) -> Result<MongoCollectionStats, String> {
+    let client = session(&sessions, &connections, &connection_id).await?;
+    let mut shards = client
+        .database(&database)
+        .collection::<Document>(&collection)
+        .aggregate([doc! { "$collStats": { "storageStats": {} } }])
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut stats = MongoCollectionStats::default();
+    while let Some(shard) = shards.try_next().await.map_err(|e| e.to_string())? {
+        let storage = shard.get_document("storageStats").map_err(|e| e.to_string())?;
+        stats.count += number(storage.get("count"));
+        stats.size += number(storage.get("size"));
+        stats.storage_size += number(storage.get("storageSize"));
+        stats.total_index_size += number(storage.get("totalIndexSize"));
+        stats.capped |= storage.get_bool("capped").unwrap_or(false);
+
+        for (name, size) in storage.get_document("indexSizes").into_iter().flatten() {
+            match stats.index_sizes.iter_mut().find(|(n, _)| n == name) {
+                Some((_, total)) => *total += number(Some(size)),
+                None => stats.index_sizes.push((name.clone(), number(Some(size)))),
+            }
+        }
+    }
+
+    stats.avg_obj_size = stats.size.checked_div(stats.count).unwrap_or(0);
+    Ok(stats)
+}
+
+/// A statistic the server may send as any numeric type
+fn number(value: Option<&Bson>) -> u64 {
+    match value {
+        Some(Bson::Int32(n)) => *n as u64,
+        Some(Bson::Int64(n)) => *n as u64,
+        Some(Bson::Double(n)) => *n as u64,
+        _ => 0,
+    }
+}
@@ -0,0 +1,97 @@
+//! Decoding of MySQL and MariaDB values into JSON.
+//!
+//! Plain queries go over the text protocol, where every value arrives as its text form
+//! (temporal types included), so only numbers, JSON and binary strings need converting.
+
+use serde_json::Value;
+use sqlx::mysql::{MySqlColumn, MySqlRow, MySqlValueRef};
+use sqlx::{Column, Decode, MySql, Row, TypeInfo, ValueRef};
+use std::fmt::Write;
+
+// Integers outside this range lose precision in JavaScript, so they are sent as strings
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+/// MySQL type name used to tag a column, e.g. `INT`, `BIGINT UNSIGNED` or `VARBINARY`
+pub fn type_name(column: &MySqlColumn) -> String {
+    column.type_info().name().to_string()
+}
+
+/// Decode every column of a row into JSON
+pub fn row_to_json(row: &MySqlRow) -> Vec<Value> {
+    (0..row.len())
+        .map(|i| match row.try_get_raw(i) {
+            Ok(value) => decode_value(value),
+            Err(_) => Value::Null,
+        })
+        .collect()
+}
+
+fn decode_value(value: MySqlValueRef<'_>) -> Value {
+    if value.is_null() {
+        return Value::Null;
+    }
+
+    let type_name = value.type_info().name().to_string();
+    let decoded = match type_name.as_str() {
+        "BOOLEAN" | "TINYINT" | "SMALLINT" | "MEDIUMINT" | "INT" | "BIGINT" | "YEAR" => {
+            <i64 as Decode<MySql>>::decode(value.clone()).ok().map(int_value)
+        }
+        "TINYINT UNSIGNED" | "SMALLINT UNSIGNED" | "MEDIUMINT UNSIGNED" | "INT UNSIGNED" | "BIGINT UNSIGNED" => {
+            <u64 as Decode<MySql>>::decode(value.clone()).ok().map(uint_value)
+        }
+        "FLOAT" | "DOUBLE" => <f64 as Decode<MySql>>::decode(value.clone()).ok().map(float_value),
+        // Sent over the wire as big-endian bytes even in the text protocol
+        "BIT" => <&[u8] as Decode<MySql>>::decode(value.clone())
+            .ok()
+            .filter(|bytes| bytes.len() <= 8)
+            .map(|bytes| uint_value(bytes.iter().fold(0, |n, &b| (n << 8) | u64::from(b)))),
+        "JSON" => <&str as Decode<MySql>>::decode(value.clone())
+            .ok()
+            .map(|text| serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()))),
+        "BINARY" | "VARBINARY" | "TINYBLOB" | "BLOB" | "MEDIUMBLOB" | "LONGBLOB" | "GEOMETRY" => {
+            <&[u8] as Decode<MySql>>::decode(value.clone())
+                .ok()
+                .map(|bytes| Value::String(hex_bytes(bytes)))
+        }
+        // DECIMAL keeps its exact digits; dates and times stay in MySQL's own text format
+        _ => <&str as Decode<MySql>>::decode(value.clone())
+            .ok()
+            .map(|text| Value::String(text.to_string())),
+    };
+
+    decoded.unwrap_or_else(|| match <&[u8] as Decode<MySql>>::decode(value) {
+        Ok(bytes) => Value::String(hex_bytes(bytes)),
+        Err(_) => Value::Null,
+    })
+}
+
+fn int_value(v: i64) -> Value {
+    if (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&v) {
+        Value::from(v)
+    } else {
+        Value::String(v.to_string())
+    }
+}
+
+fn uint_value(v: u64) -> Value {
+    if v <= MAX_SAFE_INTEGER as u64 {
+        Value::from(v)
+    } else {
+        Value::String(v.to_string())
+    }
+}
+
+/// JSON has no NaN or Infinity, so those are sent as strings
+fn float_value(v: f64) -> Value {
+    serde_json::Number::from_f64(v).map_or_else(|| Value::String(v.to_string()), Value::Number)
+}
+
+/// `\x`-prefixed hex, as the Postgres client shows `bytea`
+fn hex_bytes(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(2 + bytes.len() * 2);
+    s.push_str("\\x");
+    for b in bytes {
+        let _ = write!(s, "{:02x}", b);
+    }
+    s
+}
@@ -0,0 +1,161 @@
+use futures_util::future::BoxFuture;
+use serde_json::Value;
+use sqlx::mysql::{MySqlConnectOptions, MySqlDatabaseError, MySqlSslMode};
+use sqlx::{ConnectOptions, Executor, Row};
+
+use crate::postgres::driver::{Driver, DriverKind};
+use crate::postgres::{PgConnection, Sessions, SslMode, TableInfo, Tunnel};
+
+mod decode;
+
+/// Sessions of MySQL and MariaDB connections
+pub type MySqlSessions = Sessions<sqlx::MySqlConnection>;
+
+// Server error numbers for a statement stopped by KILL QUERY or by max_execution_time
+const ER_QUERY_INTERRUPTED: u16 = 1317;
+const ER_QUERY_TIMEOUT: u16 = 3024;
+
+/// libpq's modes mapped onto MySQL's; `allow` has no equivalent and falls back to `preferred`
+fn ssl_mode(mode: SslMode) -> MySqlSslMode {
+    match mode {
+        SslMode::Disable => MySqlSslMode::Disabled,
+        SslMode::Allow | SslMode::Prefer => MySqlSslMode::Preferred,
+        SslMode::Require => MySqlSslMode::Required,
+        SslMode::VerifyCa => MySqlSslMode::VerifyCa,
+        SslMode::VerifyFull => MySqlSslMode::VerifyIdentity,
+    }
+}
+
+/// Build connect options field by field, like the Postgres ones. An empty database
+/// connects without a default database, as the mysql client does.
+fn connect_options(connection: &PgConnection, tunnel: Option<&Tunnel>) -> Result<MySqlConnectOptions, String> {
+    let mut options = MySqlConnectOptions::new()
+        .host(&connection.host)
+        .port(connection.port)
+        .username(&connection.user)
+        .ssl_mode(ssl_mode(connection.ssl_mode));
+
+    if let Some(tunnel) = tunnel {
+        options = options.host("127.0.0.1").port(tunnel.local_port);
+    }
+    if !connection.password.is_empty() {
+        options = options.password(&connection.password);
+    }
+    if !connection.database.is_empty() {
+        options = options.database(&connection.database);
+    }
+
+    let certificates = [&connection.ssl_root_cert, &connection.ssl_client_cert, &connection.ssl_client_key];
+    for path in certificates.into_iter().flatten() {
+        if !std::path::Path::new(path).is_file() {
+            return Err(format!("Certificate file not found: {}", path));
+        }
+    }
+
+    if let Some(path) = &connection.ssl_root_cert {
+        options = options.ssl_ca(path);
+    }
+
+    match (&connection.ssl_client_cert, &connection.ssl_client_key) {
+        (Some(cert), Some(key)) => options = options.ssl_client_cert(cert).ssl_client_key(key),
+        (None, None) => {}
+        _ => return Err("A client certificate and its key must be given together".to_string()),
+    }
+
+    Ok(options)
+}
+
+impl Driver for sqlx::MySqlConnection {
+    const KIND: DriverKind = DriverKind::Mysql;
+
+    type Statement<'q> = &'q str;
+
+    fn statements(sql: &str) -> Vec<&str> {
+        vec![sql]
+    }
+
+    fn statement(sql: &str) -> &str {
+        sql
+    }
+
+    fn open<'a>(connection: &'a PgConnection, tunnel: Option<&'a Tunnel>) -> BoxFuture<'a, Result<Self, String>> {
+        Box::pin(async move {
+            let mut conn = connect_options(connection, tunnel)?
+                .connect()
+                .await
+                .map_err(|e| e.to_string())?;
+
+            // The counterpart of Postgres' default_transaction_read_only
+            if connection.read_only {
+                conn.execute("SET SESSION TRANSACTION READ ONLY")
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+
+            Ok(conn)
+        })
+    }
+
+    fn server_info(&mut self) -> BoxFuture<'_, Result<(u64, String), sqlx::Error>> {
+        Box::pin(async move {
+            let row = sqlx::query("SELECT CONNECTION_ID() AS id, VERSION() AS version")
+                .fetch_one(self)
+                .await?;
+            Ok((row.try_get("id")?, row.try_get("version")?))
+        })
+    }
+
+    fn cancel_backend(&mut self, backend_id: u64) -> BoxFuture<'_, Result<bool, sqlx::Error>> {
+        Box::pin(async move {
+            sqlx::query("KILL QUERY ?").bind(backend_id).execute(self).await?;
+            Ok(true)
+        })
+    }
+
+    fn is_cancelled(error: &sqlx::Error) -> bool {
+        error
+            .as_database_error()
+            .and_then(|e| e.try_downcast_ref::<MySqlDatabaseError>())
+            .is_some_and(|e| matches!(e.number(), ER_QUERY_INTERRUPTED | ER_QUERY_TIMEOUT))
+    }
+
+    fn list_tables(&mut self) -> BoxFuture<'_, Result<Vec<TableInfo>, sqlx::Error>> {
+        Box::pin(async move {
+            // information_schema columns can come back as binary strings, hence the casts
+            let rows = sqlx::query(
+                r#"
+                SELECT CAST(table_schema AS CHAR) AS table_schema,
+                       CAST(table_name AS CHAR) AS table_name,
+                       CAST(table_type AS CHAR) AS table_type
+                FROM information_schema.tables
+                WHERE table_schema NOT IN ('mysql', 'information_schema', 'performance_schema', 'sys')
+                ORDER BY table_schema, table_name
+                "#,
+            )
+            .fetch_all(self)
+            .await?;
+
+            rows.iter()
+                .map(|row| {
+                    Ok(TableInfo {
+                        schema: row.try_get("table_schema")?,
+                        name: row.try_get("table_name")?,
+                        table_type: row.try_get("table_type")?,
+                    })
+                })
+                .collect()
+        })
+    }
+
+    fn rows_affected(done: &sqlx::mysql::MySqlQueryResult) -> u64 {
+        done.rows_affected()
+    }
+
+    fn type_name(column: &sqlx::mysql::MySqlColumn) -> String {
+        decode::type_name(column)
+    }
+
+    fn row_to_json(row: &sqlx::mysql::MySqlRow) -> Vec<Value> {
+        decode::row_to_json(row)
+    }
+}
@@ -0,0 +1,275 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tauri::State;
+use tokio::sync::Mutex;
+
+use super::{PgConnection, PgSessions};
+use crate::mysql::MySqlSessions;
+
+/// A connection kept in a `ConnectionStore`. Its password is stored apart from it, so the
+/// type must leave the password out when serialized.
+pub trait StoredConnection: Serialize + DeserializeOwned + Clone + Send {
+    /// Service name under which passwords are kept in the OS keyring, one entry per connection id
+    const KEYRING_SERVICE: &'static str;
+    /// Saved connections without their passwords, in the app data directory
+    const CONNECTIONS_FILE: &'static str;
+    /// Passwords for when no OS keyring is available, encrypted with the app's encryption password
+    const SECRETS_FILE: &'static str;
+
+    fn id(&self) -> &str;
+    fn password_mut(&mut self) -> &mut String;
+}
+
+impl StoredConnection for PgConnection {
+    const KEYRING_SERVICE: &'static str = "devtoolssuite-postgres";
+    const CONNECTIONS_FILE: &'static str = "pg-connections.json";
+    const SECRETS_FILE: &'static str = "pg-secrets.enc";
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn password_mut(&mut self) -> &mut String {
+        &mut self.password
+    }
+}
+
+/// Saved Postgres and MySQL connections
+pub type SavedConnections = ConnectionStore<PgConnection>;
+
+/// Saved connections owned by the backend, kept in Tauri managed state.
+/// The webview only ever sees connections without their password and refers to them by id.
+pub struct ConnectionStore<C> {
+    dir: PathBuf,
+    connections: Mutex<Vec<C>>,
+    // Passwords already read from the keyring, so it is only asked once per connection
+    passwords: Mutex<HashMap<String, String>>,
+}
+
+impl<C: StoredConnection> ConnectionStore<C> {
+    /// Read the saved connections from `dir`, starting empty if there are none yet
+    pub fn load(dir: PathBuf) -> Self {
+        let connections = std::fs::read_to_string(dir.join(C::CONNECTIONS_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+
+        ConnectionStore {
+            dir,
+            connections: Mutex::new(connections),
+            passwords: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The saved connection with its password, ready to connect with
+    pub async fn get(&self, connection_id: &str) -> Result<C, String> {
+        let mut connection = self
+            .connections
+            .lock()
+            .await
+            .iter()
+            .find(|c| c.id() == connection_id)
+            .cloned()
+            .ok_or_else(|| format!("No saved connection with id {}", connection_id))?;
+
+        let mut passwords = self.passwords.lock().await;
+        *connection.password_mut() = match passwords.get(connection_id) {
+            Some(password) => password.clone(),
+            None => {
+                let password = self.read_secret(connection_id).await?;
+                // A password in the locked fallback file may turn up once the key is set, so look again then
+                if password.is_some() || !self.secrets_locked() {
+                    passwords.insert(connection_id.to_string(), password.clone().unwrap_or_default());
+                }
+                password.unwrap_or_default()
+            }
+        };
+        Ok(connection)
+    }
+
+    pub async fn list(&self) -> Vec<C> {
+        self.connections.lock().await.clone()
+    }
+
+    /// Add or update a connection. An empty password keeps the one already stored.
+    pub async fn save(&self, mut connection: C) -> Result<C, String> {
+        let mut connections = self.connections.lock().await;
+
+        let password = std::mem::take(connection.password_mut());
+        if !password.is_empty() {
+            self.write_secret(connection.id(), Some(password.clone())).await?;
+            self.passwords.lock().await.insert(connection.id().to_string(), password);
+        }
+
+        match connections.iter_mut().find(|c| c.id() == connection.id()) {
+            Some(existing) => *existing = connection.clone(),
+            None => connections.push(connection.clone()),
+        }
+        self.write_connections(&connections)?;
+
+        Ok(connection)
+    }
+
+    pub async fn delete(&self, connection_id: &str) -> Result<bool, String> {
+        let mut connections = self.connections.lock().await;
+
+        let before = connections.len();
+        connections.retain(|c| c.id() != connection_id);
+        if connections.len() == before {
+            return Ok(false);
+        }
+
+        self.write_connections(&connections)?;
+        self.passwords.lock().await.remove(connection_id);
+        self.write_secret(connection_id, None).await?;
+        Ok(true)
+    }
+
+    fn write_connections(&self, connections: &[C]) -> Result<(), String> {
+        // Passwords are never serialized, so this file holds no secrets
+        let json = serde_json::to_string_pretty(connections).map_err(|e| e.to_string())?;
+        std::fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        std::fs::write(self.dir.join(C::CONNECTIONS_FILE), json).map_err(|e| e.to_string())
+    }
+
+    /// Look the password up in the OS keyring, then in the encrypted fallback file
+    async fn read_secret(&self, connection_id: &str) -> Result<Option<String>, String> {
+        let id = connection_id.to_string();
+        match keyring_call(move || keyring::Entry::new(C::KEYRING_SERVICE, &id)?.get_password()).await {
+            Ok(password) => Ok(Some(password)),
+            Err(keyring::Error::NoEntry) => self.read_fallback_secret(connection_id),
+            Err(e) if keyring_unavailable(&e) => self.read_fallback_secret(connection_id),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// The password in the encrypted fallback file. While the file is locked no password is known;
+    /// a file that doesn't decrypt with the key is still an error.
+    fn read_fallback_secret(&self, connection_id: &str) -> Result<Option<String>, String> {
+        if self.secrets_locked() {
+            return Ok(None);
+        }
+        Ok(self.read_secrets_file()?.remove(connection_id))
+    }
+
+    /// The fallback file exists but the encryption key, derived from the password the user
+    /// enters, isn't set yet
+    fn secrets_locked(&self) -> bool {
+        !crate::icloud_has_encryption_key() && self.dir.join(C::SECRETS_FILE).exists()
+    }
+
+    /// Store (or with `None`, remove) a password, in the OS keyring when there is one
+    async fn write_secret(&self, connection_id: &str, password: Option<String>) -> Result<(), String> {
+        let id = connection_id.to_string();
+        let value = password.clone();
+        let stored = keyring_call(move || {
+            let entry = keyring::Entry::new(C::KEYRING_SERVICE, &id)?;
+            match &value {
+                Some(password) => entry.set_password(password),
+                None => match entry.delete_credential() {
+                    Err(keyring::Error::NoEntry) => Ok(()),
+                    result => result,
+                },
+            }
+        })
+        .await;
+
+        match stored {
+            Ok(()) => {
+                // Drop any copy left in the fallback file from a time the keyring was unavailable
+                if let Ok(mut secrets) = self.read_secrets_file() {
+                    if secrets.remove(connection_id).is_some() {
+                        self.write_secrets_file(&secrets)?;
+                    }
+                }
+                Ok(())
+            }
+            Err(e) if keyring_unavailable(&e) => {
+                let mut secrets = self.read_secrets_file()?;
+                match password {
+                    Some(password) => secrets.insert(connection_id.to_string(), password),
+                    None => secrets.remove(connection_id),
+                };
+                self.write_secrets_file(&secrets)
+            }
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn read_secrets_file(&self) -> Result<HashMap<String, String>, String> {
+        let path = self.dir.join(C::SECRETS_FILE);
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+
+        let encoded = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        let encrypted = BASE64.decode(encoded.trim().as_bytes()).map_err(|e| e.to_string())?;
+        let key = crate::get_encryption_key()?;
+        let decrypted = crate::decrypt_data(&encrypted, &key)?;
+        serde_json::from_slice(&decrypted).map_err(|e| e.to_string())
+    }
+
+    fn write_secrets_file(&self, secrets: &HashMap<String, String>) -> Result<(), String> {
+        let path = self.dir.join(C::SECRETS_FILE);
+        if secrets.is_empty() {
+            return match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+                _ => Ok(()),
+            };
+        }
+
+        let key = crate::get_encryption_key()?;
+        let json = serde_json::to_vec(secrets).map_err(|e| e.to_string())?;
+        let encrypted = crate::encrypt_data(&json, &key)?;
+        std::fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        std::fs::write(&path, BASE64.encode(encrypted)).map_err(|e| e.to_string())
+    }
+}
+
+/// Run a keyring call on a blocking thread; platform keyrings may block on D-Bus or a user prompt
+async fn keyring_call<T, F>(call: F) -> keyring::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> keyring::Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(call)
+        .await
+        .unwrap_or_else(|e| Err(keyring::Error::PlatformFailure(Box::new(e))))
+}
+
+/// No usable OS keyring, e.g. no Secret Service running on Linux
+fn keyring_unavailable(error: &keyring::Error) -> bool {
+    matches!(error, keyring::Error::NoStorageAccess(_) | keyring::Error::PlatformFailure(_))
+}
+
+#[tauri::command]
+pub async fn pg_list_connections(connections: State<'_, SavedConnections>) -> Result<Vec<PgConnection>, String> {
+    Ok(connections.list().await)
+}
+
+/// Add or update a saved connection. An empty `password` keeps the stored one.
+/// Returns the connection as saved, without its password. An open session is
+/// replaced on next use if its settings changed.
+#[tauri::command]
+pub async fn pg_save_connection(
+    connections: State<'_, SavedConnections>,
+    connection: PgConnection,
+) -> Result<PgConnection, String> {
+    connections.save(connection).await
+}
+
+/// Forget a saved connection and its password, closing its session
+#[tauri::command]
+pub async fn pg_delete_connection(
+    sessions: State<'_, PgSessions>,
+    mysql_sessions: State<'_, MySqlSessions>,
+    connections: State<'_, SavedConnections>,
+    connection_id: String,
+) -> Result<bool, String> {
+    sessions.close(&connection_id).await;
+    mysql_sessions.close(&connection_id).await;
+    connections.delete(&connection_id).await
+}
@@ -0,0 +1,1030 @@
+//! Decoding of PostgreSQL values into JSON.
+//!
+//! Values are decoded straight from the wire format instead of going through typed
+//! `try_get` calls, so every built-in type (plus arrays, composites, ranges, enums and
+//! domains) can be represented without enabling extra sqlx features.
+
+use serde_json::{json, Map, Value};
+use sqlx::postgres::{PgRow, PgTypeInfo, PgTypeKind, PgValueFormat, PgValueRef};
+use sqlx::{Row, TypeInfo, ValueRef};
+use std::fmt::Write;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+// Integers outside this range lose precision in JavaScript, so they are sent as strings
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+// Postgres counts dates and timestamps from 2000-01-01
+const PG_EPOCH_UNIX_DAYS: i64 = 10_957;
+const USECS_PER_DAY: i64 = 86_400_000_000;
+
+mod oid {
+    pub const BOOL: u32 = 16;
+    pub const BYTEA: u32 = 17;
+    pub const CHAR: u32 = 18;
+    pub const NAME: u32 = 19;
+    pub const INT8: u32 = 20;
+    pub const INT2: u32 = 21;
+    pub const INT2VECTOR: u32 = 22;
+    pub const INT4: u32 = 23;
+    pub const REGPROC: u32 = 24;
+    pub const TEXT: u32 = 25;
+    pub const OID: u32 = 26;
+    pub const TID: u32 = 27;
+    pub const XID: u32 = 28;
+    pub const CID: u32 = 29;
+    pub const OIDVECTOR: u32 = 30;
+    pub const JSON: u32 = 114;
+    pub const XML: u32 = 142;
+    pub const POINT: u32 = 600;
+    pub const LSEG: u32 = 601;
+    pub const PATH: u32 = 602;
+    pub const BOX: u32 = 603;
+    pub const POLYGON: u32 = 604;
+    pub const LINE: u32 = 628;
+    pub const CIDR: u32 = 650;
+    pub const FLOAT4: u32 = 700;
+    pub const FLOAT8: u32 = 701;
+    pub const UNKNOWN: u32 = 705;
+    pub const CIRCLE: u32 = 718;
+    pub const MACADDR8: u32 = 774;
+    pub const MONEY: u32 = 790;
+    pub const MACADDR: u32 = 829;
+    pub const INET: u32 = 869;
+    pub const BPCHAR: u32 = 1042;
+    pub const VARCHAR: u32 = 1043;
+    pub const DATE: u32 = 1082;
+    pub const TIME: u32 = 1083;
+    pub const TIMESTAMP: u32 = 1114;
+    pub const TIMESTAMPTZ: u32 = 1184;
+    pub const INTERVAL: u32 = 1186;
+    pub const TIMETZ: u32 = 1266;
+    pub const BIT: u32 = 1560;
+    pub const VARBIT: u32 = 1562;
+    pub const NUMERIC: u32 = 1700;
+    pub const REFCURSOR: u32 = 1790;
+    pub const REGPROCEDURE: u32 = 2202;
+    pub const REGOPER: u32 = 2203;
+    pub const REGOPERATOR: u32 = 2204;
+    pub const REGCLASS: u32 = 2205;
+    pub const REGTYPE: u32 = 2206;
+    pub const RECORD: u32 = 2249;
+    pub const CSTRING: u32 = 2275;
+    pub const UUID: u32 = 2950;
+    pub const TXID_SNAPSHOT: u32 = 2970;
+    pub const PG_LSN: u32 = 3220;
+    pub const TSVECTOR: u32 = 3614;
+    pub const REGCONFIG: u32 = 3734;
+    pub const REGDICTIONARY: u32 = 3769;
+    pub const JSONB: u32 = 3802;
+    pub const INT4RANGE: u32 = 3904;
+    pub const NUMRANGE: u32 = 3906;
+    pub const TSRANGE: u32 = 3908;
+    pub const TSTZRANGE: u32 = 3910;
+    pub const DATERANGE: u32 = 3912;
+    pub const INT8RANGE: u32 = 3926;
+    pub const JSONPATH: u32 = 4072;
+    pub const REGNAMESPACE: u32 = 4089;
+    pub const REGROLE: u32 = 4096;
+    pub const REGCOLLATION: u32 = 4191;
+    pub const INT4MULTIRANGE: u32 = 4451;
+    pub const NUMMULTIRANGE: u32 = 4532;
+    pub const TSMULTIRANGE: u32 = 4533;
+    pub const TSTZMULTIRANGE: u32 = 4534;
+    pub const DATEMULTIRANGE: u32 = 4535;
+    pub const INT8MULTIRANGE: u32 = 4536;
+    pub const PG_SNAPSHOT: u32 = 5038;
+    pub const XID8: u32 = 5069;
+
+    /// Built-in array types (the element type is carried in the array payload itself)
+    pub const ARRAYS: &[u32] = &[
+        143, 199, 629, 651, 719, 775, 791, 1000, 1001, 1002, 1003, 1005, 1006, 1007, 1008, 1009,
+        1010, 1011, 1012, 1013, 1014, 1015, 1016, 1017, 1018, 1019, 1020, 1021, 1022, 1027, 1028,
+        1034, 1040, 1041, 1115, 1182, 1183, 1185, 1187, 1231, 1263, 1270, 1561, 1563, 2201, 2207,
+        2208, 2209, 2210, 2211, 2287, 2949, 2951, 3221, 3643, 3645, 3735, 3770, 3807, 3905, 3907,
+        3909, 3911, 3913, 3927, 4073, 4090, 4097, 4192, 6150, 6151, 6152, 6153, 6155, 6157, 271,
+        5039,
+    ];
+}
+
+/// What we know about the type of a value being decoded
+#[derive(Clone, Copy)]
+enum Ty<'a> {
+    /// Fully resolved type info from the statement metadata
+    Info(&'a PgTypeInfo),
+    /// Only the OID is known (e.g. fields of an anonymous record)
+    Oid(u32),
+}
+
+impl Ty<'_> {
+    fn oid(&self) -> u32 {
+        match self {
+            Ty::Info(info) => info.oid().map(|o| o.0).unwrap_or(0),
+            Ty::Oid(oid) => *oid,
+        }
+    }
+}
+
+/// Decode every column of a row into JSON
+pub fn row_to_json(row: &PgRow) -> Vec<Value> {
+    (0..row.len())
+        .map(|i| match row.try_get_raw(i) {
+            Ok(value) => decode_value(value),
+            Err(_) => Value::Null,
+        })
+        .collect()
+}
+
+/// Postgres type name used to tag a column, e.g. `INT4`, `TIMESTAMPTZ[]` or `mood`
+pub fn type_name(info: &PgTypeInfo) -> String {
+    info.name().to_string()
+}
+
+pub fn decode_value(value: PgValueRef<'_>) -> Value {
+    if value.is_null() {
+        return Value::Null;
+    }
+
+    let info = value.type_info().into_owned();
+
+    match value.format() {
+        PgValueFormat::Binary => match value.as_bytes() {
+            Ok(bytes) => decode_binary(Ty::Info(&info), bytes).unwrap_or_else(|| bytes_fallback(bytes)),
+            Err(_) => Value::Null,
+        },
+        PgValueFormat::Text => match value.as_str() {
+            Ok(text) => decode_text(info.oid().map(|o| o.0).unwrap_or(0), text),
+            Err(_) => Value::Null,
+        },
+    }
+}
+
+/// Text format only shows up for the simple query protocol; keep scalars typed, rest as strings
+fn decode_text(oid: u32, text: &str) -> Value {
+    match oid {
+        oid::BOOL => Value::Bool(text == "t"),
+        oid::INT2 | oid::INT4 | oid::INT8 | oid::OID | oid::XID | oid::CID | oid::XID8 => text
+            .parse::<i64>()
+            .map(int_value)
+            .unwrap_or_else(|_| Value::String(text.to_string())),
+        oid::FLOAT4 | oid::FLOAT8 => text
+            .parse::<f64>()
+            .map(float_value)
+            .unwrap_or_else(|_| Value::String(text.to_string())),
+        oid::JSON | oid::JSONB => {
+            serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()))
+        }
+        _ => Value::String(text.to_string()),
+    }
+}
+
+fn decode_binary(ty: Ty<'_>, bytes: &[u8]) -> Option<Value> {
+    if let Ty::Info(info) = ty {
+        match info.kind() {
+            PgTypeKind::Array(elem) => return decode_array(Some(elem), bytes),
+            PgTypeKind::Enum(_) => return Some(Value::String(utf8(bytes)?)),
+            PgTypeKind::Domain(base) => return decode_binary(Ty::Info(base), bytes),
+            PgTypeKind::Range(elem) => return decode_range(Ty::Info(elem), bytes),
+            PgTypeKind::Composite(fields) => return decode_composite(Some(fields), bytes),
+            PgTypeKind::Simple | PgTypeKind::Pseudo => {}
+        }
+
+        // Common extension types that have no fixed OID
+        match info.name() {
+            "citext" => return Some(Value::String(utf8(bytes)?)),
+            "hstore" => return decode_hstore(bytes),
+            _ => {}
+        }
+    }
+
+    let mut r = Reader::new(bytes);
+
+    let value = match ty.oid() {
+        oid::BOOL => Value::Bool(r.u8()? != 0),
+        oid::INT2 => Value::from(r.i16()?),
+        oid::INT4 => Value::from(r.i32()?),
+        oid::INT8 => int_value(r.i64()?),
+        oid::OID | oid::XID | oid::CID | oid::REGPROC | oid::REGPROCEDURE | oid::REGOPER
+        | oid::REGOPERATOR | oid::REGCLASS | oid::REGTYPE | oid::REGCONFIG | oid::REGDICTIONARY
+        | oid::REGNAMESPACE | oid::REGROLE | oid::REGCOLLATION => Value::from(r.u32()?),
+        oid::XID8 => uint_value(r.u64()?),
+        oid::FLOAT4 => float_value(r.f32()? as f64),
+        oid::FLOAT8 => float_value(r.f64()?),
+        oid::NUMERIC => Value::String(numeric_to_string(&mut r)?),
+        oid::MONEY => Value::String(money_to_string(r.i64()?)),
+        oid::TEXT | oid::VARCHAR | oid::BPCHAR | oid::NAME | oid::XML | oid::UNKNOWN
+        | oid::REFCURSOR | oid::CSTRING => Value::String(utf8(bytes)?),
+        oid::CHAR => Value::String((r.u8()? as char).to_string()),
+        oid::BYTEA => Value::String(hex_bytes(bytes)),
+        oid::UUID => Value::String(uuid::Uuid::from_slice(bytes).ok()?.to_string()),
+        oid::JSON => serde_json::from_slice(bytes).ok()?,
+        oid::JSONB => {
+            // Version byte followed by the JSON text
+            if r.u8()? != 1 {
+                return None;
+            }
+            serde_json::from_slice(r.rest()).ok()?
+        }
+        oid::JSONPATH => {
+            if r.u8()? != 1 {
+                return None;
+            }
+            Value::String(utf8(r.rest())?)
+        }
+        oid::DATE => Value::String(format_date(r.i32()?)),
+        oid::TIME => Value::String(format_time(r.i64()?)),
+        oid::TIMETZ => {
+            let time = r.i64()?;
+            let zone = r.i32()?;
+            Value::String(format!("{}{}", format_time(time), format_offset(-zone)))
+        }
+        oid::TIMESTAMP => Value::String(format_timestamp(r.i64()?, false)),
+        oid::TIMESTAMPTZ => Value::String(format_timestamp(r.i64()?, true)),
+        oid::INTERVAL => {
+            let micros = r.i64()?;
+            let days = r.i32()?;
+            let months = r.i32()?;
+            Value::String(format_interval(months, days, micros))
+        }
+        oid::INET | oid::CIDR => Value::String(format_inet(&mut r)?),
+        oid::MACADDR | oid::MACADDR8 => Value::String(
+            bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(":"),
+        ),
+        oid::BIT | oid::VARBIT => {
+            let len = r.i32()? as usize;
+            let data = r.rest();
+            let bits: String = (0..len)
+                .map(|i| if data.get(i / 8)? & (0x80 >> (i % 8)) != 0 { Some('1') } else { Some('0') })
+                .collect::<Option<String>>()?;
+            Value::String(bits)
+        }
+        oid::PG_LSN => {
+            let lsn = r.u64()?;
+            Value::String(format!("{:X}/{:X}", lsn >> 32, lsn & 0xFFFF_FFFF))
+        }
+        oid::TID => {
+            let block = r.u32()?;
+            let offset = r.u16()?;
+            Value::String(format!("({},{})", block, offset))
+        }
+        oid::TXID_SNAPSHOT | oid::PG_SNAPSHOT => {
+            let count = r.i32()?;
+            let xmin = r.u64()?;
+            let xmax = r.u64()?;
+            let xips = (0..count).map(|_| r.u64().map(|x| x.to_string())).collect::<Option<Vec<_>>>()?;
+            Value::String(format!("{}:{}:{}", xmin, xmax, xips.join(",")))
+        }
+        oid::TSVECTOR => Value::String(format_tsvector(&mut r)?),
+        oid::POINT => Value::String(format_point(&mut r)?),
+        oid::LSEG => Value::String(format!("[{},{}]", format_point(&mut r)?, format_point(&mut r)?)),
+        oid::BOX => Value::String(format!("{},{}", format_point(&mut r)?, format_point(&mut r)?)),
+        oid::LINE => Value::String(format!("{{{},{},{}}}", r.f64()?, r.f64()?, r.f64()?)),
+        oid::CIRCLE => Value::String(format!("<{},{}>", format_point(&mut r)?, r.f64()?)),
+        oid::PATH => {
+            let closed = r.u8()? != 0;
+            let points = format_points(&mut r)?;
+            if closed {
+                Value::String(format!("({})", points))
+            } else {
+                Value::String(format!("[{}]", points))
+            }
+        }
+        oid::POLYGON => Value::String(format!("({})", format_points(&mut r)?)),
+        oid::INT4RANGE => decode_range(Ty::Oid(oid::INT4), bytes)?,
+        oid::INT8RANGE => decode_range(Ty::Oid(oid::INT8), bytes)?,
+        oid::NUMRANGE => decode_range(Ty::Oid(oid::NUMERIC), bytes)?,
+        oid::TSRANGE => decode_range(Ty::Oid(oid::TIMESTAMP), bytes)?,
+        oid::TSTZRANGE => decode_range(Ty::Oid(oid::TIMESTAMPTZ), bytes)?,
+        oid::DATERANGE => decode_range(Ty::Oid(oid::DATE), bytes)?,
+        oid::INT4MULTIRANGE => decode_multirange(Ty::Oid(oid::INT4), bytes)?,
+        oid::INT8MULTIRANGE => decode_multirange(Ty::Oid(oid::INT8), bytes)?,
+        oid::NUMMULTIRANGE => decode_multirange(Ty::Oid(oid::NUMERIC), bytes)?,
+        oid::TSMULTIRANGE => decode_multirange(Ty::Oid(oid::TIMESTAMP), bytes)?,
+        oid::TSTZMULTIRANGE => decode_multirange(Ty::Oid(oid::TIMESTAMPTZ), bytes)?,
+        oid::DATEMULTIRANGE => decode_multirange(Ty::Oid(oid::DATE), bytes)?,
+        oid::INT2VECTOR | oid::OIDVECTOR => decode_array(None, bytes)?,
+        oid::RECORD => decode_composite(None, bytes)?,
+        other if oid::ARRAYS.contains(&other) => decode_array(None, bytes)?,
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+/// Decode a value nested inside an array, record or range
+fn decode_nested(ty: Ty<'_>, bytes: Option<&[u8]>) -> Value {
+    match bytes {
+        Some(bytes) => decode_binary(ty, bytes).unwrap_or_else(|| bytes_fallback(bytes)),
+        None => Value::Null,
+    }
+}
+
+fn decode_array(elem: Option<&PgTypeInfo>, bytes: &[u8]) -> Option<Value> {
+    let mut r = Reader::new(bytes);
+    let ndim = r.i32()?;
+    let _has_nulls = r.i32()?;
+    let elem_oid = r.u32()?;
+
+    if ndim <= 0 {
+        return Some(Value::Array(vec![]));
+    }
+
+    let mut dims = Vec::with_capacity(ndim as usize);
+    for _ in 0..ndim {
+        let len = r.i32()?;
+        let _lower_bound = r.i32()?;
+        dims.push(len.max(0) as usize);
+    }
+
+    let ty = match elem {
+        Some(info) => Ty::Info(info),
+        None => Ty::Oid(elem_oid),
+    };
+
+    read_array_dim(&mut r, ty, &dims)
+}
+
+fn read_array_dim(r: &mut Reader<'_>, ty: Ty<'_>, dims: &[usize]) -> Option<Value> {
+    let (len, inner) = dims.split_first()?;
+    let mut items = Vec::with_capacity(*len);
+
+    for _ in 0..*len {
+        if inner.is_empty() {
+            let bytes = r.sized()?;
+            items.push(decode_nested(ty, bytes));
+        } else {
+            items.push(read_array_dim(r, ty, inner)?);
+        }
+    }
+
+    Some(Value::Array(items))
+}
+
+/// Named composites become objects; anonymous records become arrays
+fn decode_composite(fields: Option<&[(String, PgTypeInfo)]>, bytes: &[u8]) -> Option<Value> {
+    let mut r = Reader::new(bytes);
+    let count = r.i32()?.max(0) as usize;
+
+    match fields {
+        Some(fields) => {
+            let mut object = Map::new();
+            for i in 0..count {
+                let field_oid = r.u32()?;
+                let bytes = r.sized()?;
+                match fields.get(i) {
+                    Some((name, info)) => object.insert(name.clone(), decode_nested(Ty::Info(info), bytes)),
+                    None => object.insert(format!("f{}", i + 1), decode_nested(Ty::Oid(field_oid), bytes)),
+                };
+            }
+            Some(Value::Object(object))
+        }
+        None => {
+            let mut items = Vec::with_capacity(count);
+            for _ in 0..count {
+                let field_oid = r.u32()?;
+                let bytes = r.sized()?;
+                items.push(decode_nested(Ty::Oid(field_oid), bytes));
+            }
+            Some(Value::Array(items))
+        }
+    }
+}
+
+fn decode_range(elem: Ty<'_>, bytes: &[u8]) -> Option<Value> {
+    const EMPTY: u8 = 0x01;
+    const LB_INC: u8 = 0x02;
+    const UB_INC: u8 = 0x04;
+    const LB_INF: u8 = 0x08;
+    const UB_INF: u8 = 0x10;
+
+    let mut r = Reader::new(bytes);
+    let flags = r.u8()?;
+
+    if flags & EMPTY != 0 {
+        return Some(json!({ "empty": true }));
+    }
+
+    let lower = if flags & LB_INF == 0 { decode_nested(elem, r.sized()?) } else { Value::Null };
+    let upper = if flags & UB_INF == 0 { decode_nested(elem, r.sized()?) } else { Value::Null };
+
+    Some(json!({
+        "lower": lower,
+        "upper": upper,
+        "lower_inclusive": flags & LB_INC != 0,
+        "upper_inclusive": flags & UB_INC != 0,
+    }))
+}
+
+fn decode_multirange(elem: Ty<'_>, bytes: &[u8]) -> Option<Value> {
+    let mut r = Reader::new(bytes);
+    let count = r.i32()?.max(0) as usize;
+    let mut ranges = Vec::with_capacity(count);
+    for _ in 0..count {
+        ranges.push(decode_range(elem, r.sized()??)?);
+    }
+    Some(Value::Array(ranges))
+}
+
+fn decode_hstore(bytes: &[u8]) -> Option<Value> {
+    let mut r = Reader::new(bytes);
+    let count = r.i32()?.max(0) as usize;
+    let mut object = Map::new();
+    for _ in 0..count {
+        let key = utf8(r.sized()??)?;
+        let value = match r.sized()? {
+            Some(v) => Value::String(utf8(v)?),
+            None => Value::Null,
+        };
+        object.insert(key, value);
+    }
+    Some(Value::Object(object))
+}
+
+fn numeric_to_string(r: &mut Reader<'_>) -> Option<String> {
+    const NEG: u16 = 0x4000;
+    const NAN: u16 = 0xC000;
+    const PINF: u16 = 0xD000;
+    const NINF: u16 = 0xF000;
+
+    let ndigits = r.i16()?.max(0) as usize;
+    let weight = r.i16()? as i32;
+    let sign = r.u16()?;
+    let dscale = r.u16()? as usize;
+
+    match sign {
+        NAN => return Some("NaN".to_string()),
+        PINF => return Some("Infinity".to_string()),
+        NINF => return Some("-Infinity".to_string()),
+        _ => {}
+    }
+
+    let digits = (0..ndigits).map(|_| r.i16()).collect::<Option<Vec<i16>>>()?;
+    // Base-10000 digit at position `i` (0 is the most significant group)
+    let digit = |i: i32| -> i16 {
+        if i < 0 {
+            0
+        } else {
+            digits.get(i as usize).copied().unwrap_or(0)
+        }
+    };
+
+    let mut s = String::new();
+    if sign == NEG {
+        s.push('-');
+    }
+
+    if weight < 0 {
+        s.push('0');
+    } else {
+        for i in 0..=weight {
+            if i == 0 {
+                let _ = write!(s, "{}", digit(i));
+            } else {
+                let _ = write!(s, "{:04}", digit(i));
+            }
+        }
+    }
+
+    if dscale > 0 {
+        let mut frac = String::new();
+        let mut i = weight + 1;
+        while frac.len() < dscale {
+            let _ = write!(frac, "{:04}", digit(i));
+            i += 1;
+        }
+        frac.truncate(dscale);
+        s.push('.');
+        s.push_str(&frac);
+    }
+
+    Some(s)
+}
+
+fn money_to_string(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    let abs = cents.unsigned_abs();
+    format!("{}{}.{:02}", sign, abs / 100, abs % 100)
+}
+
+fn format_inet(r: &mut Reader<'_>) -> Option<String> {
+    let family = r.u8()?;
+    let bits = r.u8()?;
+    let is_cidr = r.u8()? != 0;
+    let len = r.u8()? as usize;
+    let addr = r.take(len)?;
+
+    let (text, max_bits) = match family {
+        2 => (Ipv4Addr::from(<[u8; 4]>::try_from(addr).ok()?).to_string(), 32),
+        3 => (Ipv6Addr::from(<[u8; 16]>::try_from(addr).ok()?).to_string(), 128),
+        _ => return None,
+    };
+
+    if is_cidr || bits != max_bits {
+        Some(format!("{}/{}", text, bits))
+    } else {
+        Some(text)
+    }
+}
+
+fn format_tsvector(r: &mut Reader<'_>) -> Option<String> {
+    let count = r.i32()?.max(0);
+    let mut lexemes = Vec::with_capacity(count as usize);
+
+    for _ in 0..count {
+        let len = r.buf.iter().position(|b| *b == 0)?;
+        let word = utf8(r.take(len)?)?;
+        r.u8()?;
+
+        let mut lexeme = format!("'{}'", word.replace('\'', "''"));
+        let positions = r.u16()?;
+        for i in 0..positions {
+            let entry = r.u16()?;
+            let weight = match entry >> 14 {
+                3 => "A",
+                2 => "B",
+                1 => "C",
+                _ => "",
+            };
+            let sep = if i == 0 { ':' } else { ',' };
+            let _ = write!(lexeme, "{}{}{}", sep, entry & 0x3FFF, weight);
+        }
+        lexemes.push(lexeme);
+    }
+
+    Some(lexemes.join(" "))
+}
+
+fn format_point(r: &mut Reader<'_>) -> Option<String> {
+    Some(format!("({},{})", r.f64()?, r.f64()?))
+}
+
+fn format_points(r: &mut Reader<'_>) -> Option<String> {
+    let count = r.i32()?.max(0);
+    let points = (0..count).map(|_| format_point(r)).collect::<Option<Vec<_>>>()?;
+    Some(points.join(","))
+}
+
+/// Convert days since 1970-01-01 to a (year, month, day) civil date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn format_civil(days: i64) -> (String, bool) {
+    let (year, month, day) = civil_from_days(PG_EPOCH_UNIX_DAYS + days);
+    if year <= 0 {
+        // Postgres has no year zero: year 0 is 1 BC
+        (format!("{:04}-{:02}-{:02}", 1 - year, month, day), true)
+    } else {
+        (format!("{:04}-{:02}-{:02}", year, month, day), false)
+    }
+}
+
+fn format_date(days: i32) -> String {
+    match days {
+        i32::MAX => "infinity".to_string(),
+        i32::MIN => "-infinity".to_string(),
+        _ => {
+            let (date, bc) = format_civil(days as i64);
+            if bc {
+                format!("{} BC", date)
+            } else {
+                date
+            }
+        }
+    }
+}
+
+fn format_time(micros: i64) -> String {
+    let secs = micros / 1_000_000;
+    let frac = micros % 1_000_000;
+    let mut s = format!("{:02}:{:02}:{:02}", secs / 3600, (secs % 3600) / 60, secs % 60);
+    if frac != 0 {
+        let digits = format!("{:06}", frac);
+        s.push('.');
+        s.push_str(digits.trim_end_matches('0'));
+    }
+    s
+}
+
+fn format_offset(offset_secs: i32) -> String {
+    let sign = if offset_secs < 0 { '-' } else { '+' };
+    let abs = offset_secs.unsigned_abs();
+    let mut s = format!("{}{:02}:{:02}", sign, abs / 3600, (abs % 3600) / 60);
+    let secs = abs % 60;
+    if secs != 0 {
+        let _ = write!(s, ":{:02}", secs);
+    }
+    s
+}
+
+fn format_timestamp(micros: i64, utc: bool) -> String {
+    match micros {
+        i64::MAX => return "infinity".to_string(),
+        i64::MIN => return "-infinity".to_string(),
+        _ => {}
+    }
+
+    let days = micros.div_euclid(USECS_PER_DAY);
+    let time = micros.rem_euclid(USECS_PER_DAY);
+    let (date, bc) = format_civil(days);
+
+    let mut s = format!("{}T{}", date, format_time(time));
+    if utc {
+        s.push('Z');
+    }
+    if bc {
+        s.push_str(" BC");
+    }
+    s
+}
+
+/// Format an interval the way psql does with the default `postgres` IntervalStyle
+fn format_interval(months: i32, days: i32, micros: i64) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    let mut has_negative = false;
+
+    let mut push = |value: i64, singular: &str, plural: &str| {
+        if value != 0 {
+            let sign = if has_negative && value > 0 { "+" } else { "" };
+            let unit = if value == 1 { singular } else { plural };
+            parts.push(format!("{}{} {}", sign, value, unit));
+            has_negative |= value < 0;
+        }
+    };
+
+    push((months / 12) as i64, "year", "years");
+    push((months % 12) as i64, "mon", "mons");
+    push(days as i64, "day", "days");
+
+    if micros != 0 || parts.is_empty() {
+        let sign = if micros < 0 {
+            "-"
+        } else if has_negative {
+            "+"
+        } else {
+            ""
+        };
+        parts.push(format!("{}{}", sign, format_time(micros.unsigned_abs() as i64)));
+    }
+
+    parts.join(" ")
+}
+
+fn int_value(v: i64) -> Value {
+    if (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&v) {
+        Value::from(v)
+    } else {
+        Value::String(v.to_string())
+    }
+}
+
+fn uint_value(v: u64) -> Value {
+    if v <= MAX_SAFE_INTEGER as u64 {
+        Value::from(v)
+    } else {
+        Value::String(v.to_string())
+    }
+}
+
+/// JSON has no NaN or Infinity, so those are sent as strings
+fn float_value(v: f64) -> Value {
+    match serde_json::Number::from_f64(v) {
+        Some(n) => Value::Number(n),
+        None if v.is_nan() => Value::String("NaN".to_string()),
+        None if v > 0.0 => Value::String("Infinity".to_string()),
+        None => Value::String("-Infinity".to_string()),
+    }
+}
+
+fn utf8(bytes: &[u8]) -> Option<String> {
+    std::str::from_utf8(bytes).ok().map(str::to_string)
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(2 + bytes.len() * 2);
+    s.push_str("\\x");
+    for b in bytes {
+        let _ = write!(s, "{:02x}", b);
+    }
+    s
+}
+
+/// Unknown binary payloads: show as text when printable, otherwise as hex
+fn bytes_fallback(bytes: &[u8]) -> Value {
+    match std::str::from_utf8(bytes) {
+        Ok(text) if !text.chars().any(|c| c.is_control() && !c.is_whitespace()) => {
+            Value::String(text.to_string())
+        }
+        _ => Value::String(hex_bytes(bytes)),
+    }
+}
+
+/// Big-endian reader over a binary wire value
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.buf.len() < n {
+            return None;
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Some(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.buf)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.array()?))
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        Some(i16::from_be_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.array()?))
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        Some(i64::from_be_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        Some(f32::from_be_bytes(self.array()?))
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        Some(f64::from_be_bytes(self.array()?))
+    }
+
+    /// Length-prefixed value; `Some(None)` for SQL NULL (length -1)
+    fn sized(&mut self) -> Option<Option<&'a [u8]>> {
+        let len = self.i32()?;
+        if len < 0 {
+            Some(None)
+        } else {
+            Some(Some(self.take(len as usize)?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(oid: u32, bytes: &[u8]) -> Value {
+        decode_binary(Ty::Oid(oid), bytes).expect("decodable value")
+    }
+
+    /// Length-prefixed field as it appears inside arrays, records and ranges
+    fn sized(bytes: &[u8]) -> Vec<u8> {
+        let mut out = (bytes.len() as i32).to_be_bytes().to_vec();
+        out.extend_from_slice(bytes);
+        out
+    }
+
+    fn numeric(weight: i16, sign: u16, dscale: u16, digits: &[i16]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend((digits.len() as i16).to_be_bytes());
+        out.extend(weight.to_be_bytes());
+        out.extend(sign.to_be_bytes());
+        out.extend(dscale.to_be_bytes());
+        for digit in digits {
+            out.extend(digit.to_be_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn integers_and_floats() {
+        assert_eq!(decode(oid::BOOL, &[1]), json!(true));
+        assert_eq!(decode(oid::INT2, &(-7i16).to_be_bytes()), json!(-7));
+        assert_eq!(decode(oid::INT4, &5i32.to_be_bytes()), json!(5));
+        assert_eq!(decode(oid::INT8, &42i64.to_be_bytes()), json!(42));
+        // Beyond 2^53 JavaScript loses precision, so the value is sent as a string
+        assert_eq!(decode(oid::INT8, &i64::MAX.to_be_bytes()), json!("9223372036854775807"));
+        assert_eq!(decode(oid::OID, &4_000_000_000u32.to_be_bytes()), json!(4_000_000_000u32));
+        assert_eq!(decode(oid::FLOAT8, &1.5f64.to_be_bytes()), json!(1.5));
+        assert_eq!(decode(oid::FLOAT4, &f32::NAN.to_be_bytes()), json!("NaN"));
+        assert_eq!(decode(oid::FLOAT8, &f64::NEG_INFINITY.to_be_bytes()), json!("-Infinity"));
+        assert!(decode_binary(Ty::Oid(oid::INT4), &[0, 1]).is_none());
+    }
+
+    #[test]
+    fn numerics() {
+        assert_eq!(decode(oid::NUMERIC, &numeric(0, 0, 0, &[42])), json!("42"));
+        assert_eq!(decode(oid::NUMERIC, &numeric(1, 0, 2, &[12, 3456, 7800])), json!("123456.78"));
+        assert_eq!(decode(oid::NUMERIC, &numeric(-1, 0x4000, 3, &[50])), json!("-0.005"));
+        assert_eq!(decode(oid::NUMERIC, &numeric(1, 0, 0, &[1])), json!("10000"));
+        assert_eq!(decode(oid::NUMERIC, &numeric(0, 0, 0, &[])), json!("0"));
+        assert_eq!(decode(oid::NUMERIC, &numeric(0, 0xC000, 0, &[])), json!("NaN"));
+        assert_eq!(decode(oid::NUMERIC, &numeric(0, 0xF000, 0, &[])), json!("-Infinity"));
+        assert_eq!(decode(oid::MONEY, &(-1234i64).to_be_bytes()), json!("-12.34"));
+    }
+
+    #[test]
+    fn text_like_values() {
+        assert_eq!(decode(oid::TEXT, "héllo".as_bytes()), json!("héllo"));
+        assert_eq!(decode(oid::CHAR, b"x"), json!("x"));
+        assert_eq!(decode(oid::BYTEA, &[0xde, 0xad, 0x01]), json!("\\xdead01"));
+        assert_eq!(
+            decode(oid::UUID, &[0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 1, 2, 3, 4, 5, 6, 7, 8]),
+            json!("12345678-9abc-def0-0102-030405060708")
+        );
+        assert_eq!(decode(oid::JSON, br#"{"a":[1,2]}"#), json!({"a": [1, 2]}));
+        assert_eq!(decode(oid::JSONB, b"\x01[true,null]"), json!([true, null]));
+        assert!(decode_binary(Ty::Oid(oid::JSONB), b"\x02{}").is_none());
+        assert_eq!(decode(oid::MACADDR, &[8, 0, 0x2b, 1, 2, 3]), json!("08:00:2b:01:02:03"));
+        assert_eq!(decode(oid::VARBIT, &[0, 0, 0, 5, 0b1011_0000]), json!("10110"));
+        assert_eq!(decode(oid::PG_LSN, &0x0000_0001_0000_00ABu64.to_be_bytes()), json!("1/AB"));
+    }
+
+    #[test]
+    fn network_addresses() {
+        assert_eq!(decode(oid::INET, &[2, 32, 0, 4, 192, 168, 0, 1]), json!("192.168.0.1"));
+        assert_eq!(decode(oid::INET, &[2, 24, 0, 4, 10, 0, 0, 0]), json!("10.0.0.0/24"));
+        assert_eq!(decode(oid::CIDR, &[2, 32, 1, 4, 10, 0, 0, 1]), json!("10.0.0.1/32"));
+        let mut v6 = vec![3, 128, 0, 16];
+        v6.extend(Ipv6Addr::LOCALHOST.octets());
+        assert_eq!(decode(oid::INET, &v6), json!("::1"));
+    }
+
+    #[test]
+    fn dates_and_times() {
+        assert_eq!(decode(oid::DATE, &0i32.to_be_bytes()), json!("2000-01-01"));
+        assert_eq!(decode(oid::DATE, &(-1i32).to_be_bytes()), json!("1999-12-31"));
+        assert_eq!(decode(oid::DATE, &i32::MAX.to_be_bytes()), json!("infinity"));
+        assert_eq!(format_date(-730_119), "0001-01-01");
+        assert_eq!(format_date(-730_120), "0001-12-31 BC");
+
+        let noon = 12 * 3_600_000_000i64 + 500_000;
+        assert_eq!(decode(oid::TIME, &noon.to_be_bytes()), json!("12:00:00.5"));
+
+        let mut timetz = noon.to_be_bytes().to_vec();
+        timetz.extend((-19_800i32).to_be_bytes());
+        assert_eq!(decode(oid::TIMETZ, &timetz), json!("12:00:00.5+05:30"));
+
+        let micros = 8_766 * USECS_PER_DAY + 3_723_000_001;
+        assert_eq!(decode(oid::TIMESTAMP, &micros.to_be_bytes()), json!("2024-01-01T01:02:03.000001"));
+        assert_eq!(decode(oid::TIMESTAMPTZ, &micros.to_be_bytes()), json!("2024-01-01T01:02:03.000001Z"));
+        assert_eq!(decode(oid::TIMESTAMP, &(-1i64).to_be_bytes()), json!("1999-12-31T23:59:59.999999"));
+        assert_eq!(decode(oid::TIMESTAMPTZ, &i64::MIN.to_be_bytes()), json!("-infinity"));
+    }
+
+    #[test]
+    fn intervals_follow_psql() {
+        assert_eq!(format_interval(0, 0, 0), "00:00:00");
+        assert_eq!(format_interval(14, 3, 3_600_000_000), "1 year 2 mons 3 days 01:00:00");
+        assert_eq!(format_interval(1, 1, 0), "1 mon 1 day");
+        assert_eq!(format_interval(0, -1, 3_600_000_000), "-1 days +01:00:00");
+        assert_eq!(format_interval(0, 0, -1_500_000), "-00:00:01.5");
+
+        let mut bytes = 90_000_000i64.to_be_bytes().to_vec();
+        bytes.extend(2i32.to_be_bytes());
+        bytes.extend(0i32.to_be_bytes());
+        assert_eq!(decode(oid::INTERVAL, &bytes), json!("2 days 00:01:30"));
+    }
+
+    #[test]
+    fn arrays() {
+        // int4[] of {{1,NULL},{3,4}}
+        let mut bytes = Vec::new();
+        for header in [2i32, 1, oid::INT4 as i32, 2, 1, 2, 1] {
+            bytes.extend(header.to_be_bytes());
+        }
+        bytes.extend(sized(&1i32.to_be_bytes()));
+        bytes.extend((-1i32).to_be_bytes());
+        bytes.extend(sized(&3i32.to_be_bytes()));
+        bytes.extend(sized(&4i32.to_be_bytes()));
+        assert_eq!(decode(1007, &bytes), json!([[1, null], [3, 4]]));
+
+        // An empty array has no dimensions
+        let empty: Vec<u8> = [0i32, 0, oid::TEXT as i32].iter().flat_map(|v| v.to_be_bytes()).collect();
+        assert_eq!(decode(1009, &empty), json!([]));
+
+        let mut text = Vec::new();
+        for header in [1i32, 0, oid::TEXT as i32, 2, 1] {
+            text.extend(header.to_be_bytes());
+        }
+        text.extend(sized(b"a"));
+        text.extend(sized(b"b,c"));
+        assert_eq!(decode(1009, &text), json!(["a", "b,c"]));
+    }
+
+    #[test]
+    fn anonymous_records() {
+        let mut bytes = 2i32.to_be_bytes().to_vec();
+        bytes.extend(oid::INT4.to_be_bytes());
+        bytes.extend(sized(&7i32.to_be_bytes()));
+        bytes.extend(oid::TEXT.to_be_bytes());
+        bytes.extend((-1i32).to_be_bytes());
+        assert_eq!(decode(oid::RECORD, &bytes), json!([7, null]));
+    }
+
+    #[test]
+    fn ranges() {
+        // [1,10)
+        let mut bytes = vec![0x02];
+        bytes.extend(sized(&1i32.to_be_bytes()));
+        bytes.extend(sized(&10i32.to_be_bytes()));
+        let range = json!({ "lower": 1, "upper": 10, "lower_inclusive": true, "upper_inclusive": false });
+        assert_eq!(decode(oid::INT4RANGE, &bytes), range);
+
+        // (,2024-01-01]
+        let mut unbounded = vec![0x08 | 0x04];
+        unbounded.extend(sized(&8_766i32.to_be_bytes()));
+        assert_eq!(
+            decode(oid::DATERANGE, &unbounded),
+            json!({ "lower": null, "upper": "2024-01-01", "lower_inclusive": false, "upper_inclusive": true })
+        );
+
+        assert_eq!(decode(oid::INT8RANGE, &[0x01]), json!({ "empty": true }));
+
+        let mut multi = 2i32.to_be_bytes().to_vec();
+        multi.extend(sized(&bytes));
+        multi.extend(sized(&[0x01]));
+        assert_eq!(decode(oid::INT4MULTIRANGE, &multi), json!([range, { "empty": true }]));
+    }
+
+    #[test]
+    fn hstore_pairs() {
+        let mut bytes = 2i32.to_be_bytes().to_vec();
+        bytes.extend(sized(b"a"));
+        bytes.extend(sized(b"1"));
+        bytes.extend(sized(b"b"));
+        bytes.extend((-1i32).to_be_bytes());
+        assert_eq!(decode_hstore(&bytes), Some(json!({ "a": "1", "b": null })));
+        assert_eq!(decode_hstore(&1i32.to_be_bytes()), None);
+    }
+
+    #[test]
+    fn geometry_and_search_types() {
+        let point: Vec<u8> = [1.5f64, -2.0].iter().flat_map(|v| v.to_be_bytes()).collect();
+        assert_eq!(decode(oid::POINT, &point), json!("(1.5,-2)"));
+
+        let mut path = vec![0];
+        path.extend(2i32.to_be_bytes());
+        path.extend(&point);
+        path.extend(&point);
+        assert_eq!(decode(oid::PATH, &path), json!("[(1.5,-2),(1.5,-2)]"));
+
+        // 'cat':1A,3 'dog'
+        let mut tsvector = 2i32.to_be_bytes().to_vec();
+        tsvector.extend(b"cat\0");
+        tsvector.extend(2u16.to_be_bytes());
+        tsvector.extend((0xC000u16 | 1).to_be_bytes());
+        tsvector.extend(3u16.to_be_bytes());
+        tsvector.extend(b"dog\0");
+        tsvector.extend(0u16.to_be_bytes());
+        assert_eq!(decode(oid::TSVECTOR, &tsvector), json!("'cat':1A,3 'dog'"));
+    }
+
+    #[test]
+    fn text_format_keeps_scalars_typed() {
+        assert_eq!(decode_text(oid::BOOL, "t"), json!(true));
+        assert_eq!(decode_text(oid::INT8, "9007199254740993"), json!("9007199254740993"));
+        assert_eq!(decode_text(oid::FLOAT8, "2.5"), json!(2.5));
+        assert_eq!(decode_text(oid::JSONB, r#"{"a": 1}"#), json!({"a": 1}));
+        assert_eq!(decode_text(oid::TEXT, "{1,2}"), json!("{1,2}"));
+    }
+
+    #[test]
+    fn unknown_payloads_fall_back() {
+        assert!(decode_binary(Ty::Oid(999_999), b"abc").is_none());
+        assert_eq!(bytes_fallback(b"plain text"), json!("plain text"));
+        assert_eq!(bytes_fallback(&[0, 159, 146]), json!("\\x009f92"));
+    }
+}
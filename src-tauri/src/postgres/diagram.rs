@@ -0,0 +1,770 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, State};
+use tauri_plugin_dialog::DialogExt;
+
+use super::schema::{load_tables, ForeignKey, TableDetails};
+use super::{session_error, PgSessions, SavedConnections};
+
+// Box metrics of the SVG, in pixels; text is 12px monospace
+const CHAR_WIDTH: f64 = 7.2;
+const HEADER_HEIGHT: f64 = 26.0;
+const ROW_HEIGHT: f64 = 20.0;
+const KEY_GUTTER: f64 = 26.0;
+const BOX_PADDING: f64 = 8.0;
+const GAP_X: f64 = 90.0;
+const GAP_Y: f64 = 50.0;
+const MARGIN: f64 = 20.0;
+// How far lines between boxes above each other, or from a box to itself, swing out to the right
+const LOOP_REACH: f64 = 40.0;
+
+// PNGs are rendered at twice the SVG size so text stays sharp
+const PNG_SCALE: f32 = 2.0;
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagramFormat {
+    Mermaid,
+    /// Graphviz
+    Dot,
+    Svg,
+    Png,
+}
+
+impl DiagramFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            DiagramFormat::Mermaid => "mmd",
+            DiagramFormat::Dot => "dot",
+            DiagramFormat::Svg => "svg",
+            DiagramFormat::Png => "png",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            DiagramFormat::Mermaid => "Mermaid",
+            DiagramFormat::Dot => "Graphviz DOT",
+            DiagramFormat::Svg => "SVG",
+            DiagramFormat::Png => "PNG",
+        }
+    }
+}
+
+/// The same diagram in each text format
+#[derive(Debug, Serialize)]
+pub struct ErDiagram {
+    pub mermaid: String,
+    pub dot: String,
+    pub svg: String,
+}
+
+/// A foreign key between two tables of the diagram, by index
+struct Relationship<'a> {
+    child: usize,
+    parent: usize,
+    foreign_key: &'a ForeignKey,
+    /// Some foreign key column is nullable, so a child row may have no parent
+    optional: bool,
+    /// The foreign key columns are unique, so it's one-to-one
+    unique: bool,
+}
+
+fn relationships(tables: &[TableDetails]) -> Vec<Relationship<'_>> {
+    let index: HashMap<&str, usize> = tables.iter().enumerate().map(|(i, t)| (t.name.as_str(), i)).collect();
+
+    let mut relationships = Vec::new();
+    for (child, table) in tables.iter().enumerate() {
+        for foreign_key in &table.foreign_keys {
+            // References to tables outside the diagram are left out
+            let Some(&parent) = index.get(foreign_key.referenced_table.as_str()) else { continue };
+            if foreign_key.referenced_schema != tables[parent].schema {
+                continue;
+            }
+
+            let optional = table
+                .columns
+                .iter()
+                .any(|c| c.nullable && foreign_key.columns.contains(&c.name));
+            let mut key_columns = foreign_key.columns.clone();
+            key_columns.sort();
+            let unique = table
+                .constraints
+                .iter()
+                .filter(|c| c.constraint_type == "PRIMARY KEY" || c.constraint_type == "UNIQUE")
+                .any(|c| {
+                    let mut columns = c.columns.clone();
+                    columns.sort();
+                    columns == key_columns
+                });
+
+            relationships.push(Relationship {
+                child,
+                parent,
+                foreign_key,
+                optional,
+                unique,
+            });
+        }
+    }
+    relationships
+}
+
+/// `PK`, `FK` or both for a column
+fn key_markers(table: &TableDetails, column: &str) -> Vec<&'static str> {
+    let mut markers = Vec::new();
+    if table.primary_key.as_ref().is_some_and(|pk| pk.columns.iter().any(|c| c == column)) {
+        markers.push("PK");
+    }
+    if table.foreign_keys.iter().any(|fk| fk.columns.iter().any(|c| c == column)) {
+        markers.push("FK");
+    }
+    markers
+}
+
+/// Attribute types and names for Mermaid: letters, digits, `_`, `-`, parentheses and brackets
+/// (as in `varchar(20)` or `integer[]`) are kept, anything else is replaced with `_`
+fn mermaid_word(text: &str) -> String {
+    let word: String = text
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "_-()[]".contains(c) { c } else { '_' })
+        .collect();
+    if word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        word
+    } else {
+        format!("_{}", word)
+    }
+}
+
+fn mermaid_entity(name: &str) -> String {
+    if name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "'"))
+    }
+}
+
+fn mermaid(tables: &[TableDetails], relationships: &[Relationship]) -> String {
+    let mut out = String::from("erDiagram\n");
+    for table in tables {
+        let _ = writeln!(out, "    {} {{", mermaid_entity(&table.name));
+        for column in &table.columns {
+            let markers = key_markers(table, &column.name);
+            let _ = write!(out, "        {} {}", mermaid_word(&column.data_type), mermaid_word(&column.name));
+            if !markers.is_empty() {
+                let _ = write!(out, " {}", markers.join(", "));
+            }
+            if !column.nullable {
+                out.push_str(" \"NOT NULL\"");
+            }
+            out.push('\n');
+        }
+        out.push_str("    }\n");
+    }
+
+    for relationship in relationships {
+        let _ = writeln!(
+            out,
+            "    {} {}--{} {} : \"{}\"",
+            mermaid_entity(&tables[relationship.child].name),
+            if relationship.unique { "|o" } else { "}o" },
+            if relationship.optional { "o|" } else { "||" },
+            mermaid_entity(&tables[relationship.parent].name),
+            relationship.foreign_key.columns.join(", ").replace('"', "'")
+        );
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn dot_id(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn dot(tables: &[TableDetails], relationships: &[Relationship]) -> String {
+    let mut out = String::from("digraph er {\n");
+    out.push_str("    graph [rankdir=LR, nodesep=0.6, ranksep=1.2];\n");
+    out.push_str("    node [shape=plaintext, fontname=\"Helvetica\", fontsize=11];\n");
+    out.push_str("    edge [dir=both, color=\"#64748b\"];\n\n");
+
+    for table in tables {
+        let _ = write!(
+            out,
+            "    {} [label=<<table border=\"0\" cellborder=\"1\" cellspacing=\"0\" cellpadding=\"4\">\
+             <tr><td colspan=\"3\" bgcolor=\"#e2e8f0\"><b>{}</b></td></tr>",
+            dot_id(&table.name),
+            escape_html(&table.name)
+        );
+        for (i, column) in table.columns.iter().enumerate() {
+            let _ = write!(
+                out,
+                "<tr><td port=\"c{}\" align=\"left\">{}</td><td align=\"left\">{}</td><td>{}</td></tr>",
+                i,
+                escape_html(&column.name),
+                escape_html(&column.data_type),
+                key_markers(table, &column.name).join(" ")
+            );
+        }
+        out.push_str("</table>>];\n");
+    }
+
+    if !relationships.is_empty() {
+        out.push('\n');
+    }
+    for relationship in relationships {
+        let child = &tables[relationship.child];
+        let parent = &tables[relationship.parent];
+        let port = |table: &TableDetails, column: Option<&String>| {
+            column
+                .and_then(|name| table.columns.iter().position(|c| c.name == *name))
+                .map_or(String::new(), |i| format!(":c{}", i))
+        };
+        let _ = writeln!(
+            out,
+            "    {}{} -> {}{} [arrowtail={}, arrowhead={}];",
+            dot_id(&child.name),
+            port(child, relationship.foreign_key.columns.first()),
+            dot_id(&parent.name),
+            port(parent, relationship.foreign_key.referenced_columns.first()),
+            if relationship.unique { "teeodot" } else { "crowodot" },
+            if relationship.optional { "teeodot" } else { "teetee" }
+        );
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Where a table's box is drawn
+struct Placement {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    /// Offset of the type column from the box's left edge
+    type_offset: f64,
+}
+
+impl Placement {
+    /// Vertical center of a column's row, or of the header if it isn't found
+    fn row_y(&self, table: &TableDetails, column: Option<&String>) -> f64 {
+        match column.and_then(|name| table.columns.iter().position(|c| c.name == *name)) {
+            Some(i) => self.y + HEADER_HEIGHT + ROW_HEIGHT * i as f64 + ROW_HEIGHT / 2.0,
+            None => self.y + HEADER_HEIGHT / 2.0,
+        }
+    }
+}
+
+/// Tables ordered so that related ones come together: each group of connected tables
+/// in breadth-first order from its most connected table
+fn layout_order(tables: &[TableDetails], relationships: &[Relationship]) -> Vec<usize> {
+    let mut neighbours: Vec<Vec<usize>> = vec![Vec::new(); tables.len()];
+    for r in relationships.iter().filter(|r| r.child != r.parent) {
+        neighbours[r.child].push(r.parent);
+        neighbours[r.parent].push(r.child);
+    }
+
+    let mut by_degree: Vec<usize> = (0..tables.len()).collect();
+    by_degree.sort_by_key(|&i| std::cmp::Reverse(neighbours[i].len()));
+
+    let mut order = Vec::with_capacity(tables.len());
+    let mut seen = vec![false; tables.len()];
+    for start in by_degree {
+        if seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        let mut queue = VecDeque::from([start]);
+        while let Some(i) = queue.pop_front() {
+            order.push(i);
+            for &next in &neighbours[i] {
+                if !seen[next] {
+                    seen[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    order
+}
+
+/// Lay the tables out on a grid, each grid column as wide as its widest box
+fn layout(tables: &[TableDetails], relationships: &[Relationship]) -> (Vec<Placement>, f64, f64) {
+    let chars = |text: &str| text.chars().count() as f64;
+    let mut placements: Vec<Placement> = tables
+        .iter()
+        .map(|table| {
+            let name_width = table.columns.iter().map(|c| chars(&c.name)).fold(0.0, f64::max);
+            let type_width = table.columns.iter().map(|c| chars(&c.data_type)).fold(0.0, f64::max);
+            let type_offset = KEY_GUTTER + (name_width + 2.0) * CHAR_WIDTH;
+            let width = (type_offset + type_width * CHAR_WIDTH + BOX_PADDING)
+                .max(chars(&table.name) * CHAR_WIDTH + 2.0 * BOX_PADDING);
+            Placement {
+                x: 0.0,
+                y: 0.0,
+                width,
+                height: HEADER_HEIGHT + ROW_HEIGHT * table.columns.len() as f64,
+                type_offset,
+            }
+        })
+        .collect();
+
+    let order = layout_order(tables, relationships);
+    let per_row = (tables.len() as f64).sqrt().ceil().max(1.0) as usize;
+    let mut column_widths = vec![0.0_f64; per_row];
+    let mut row_heights = vec![0.0_f64; tables.len().div_ceil(per_row)];
+    for (slot, &i) in order.iter().enumerate() {
+        column_widths[slot % per_row] = column_widths[slot % per_row].max(placements[i].width);
+        row_heights[slot / per_row] = row_heights[slot / per_row].max(placements[i].height);
+    }
+
+    for (slot, &i) in order.iter().enumerate() {
+        let (column, row) = (slot % per_row, slot / per_row);
+        placements[i].x = MARGIN + column_widths[..column].iter().map(|w| w + GAP_X).sum::<f64>();
+        placements[i].y = MARGIN + row_heights[..row].iter().map(|h| h + GAP_Y).sum::<f64>();
+    }
+
+    let width = 2.0 * MARGIN + LOOP_REACH + column_widths.iter().sum::<f64>() + GAP_X * (per_row.saturating_sub(1)) as f64;
+    let height =
+        2.0 * MARGIN + row_heights.iter().sum::<f64>() + GAP_Y * (row_heights.len().saturating_sub(1)) as f64;
+    (placements, width, height)
+}
+
+fn escape_xml(text: &str) -> String {
+    escape_html(text).replace('\'', "&apos;")
+}
+
+/// Crow's foot notation at one end of a relationship line; `direction` points away from the box
+fn line_end(out: &mut String, x: f64, y: f64, direction: f64, many: bool, optional: bool) {
+    let stroke = "stroke=\"#64748b\" stroke-width=\"1.2\" fill=\"none\"";
+    if many {
+        let _ = write!(
+            out,
+            r#"<path d="M{:.1},{:.1} L{:.1},{:.1} M{:.1},{:.1} L{:.1},{:.1}" {}/>"#,
+            x,
+            y - 6.0,
+            x + 10.0 * direction,
+            y,
+            x,
+            y + 6.0,
+            x + 10.0 * direction,
+            y,
+            stroke
+        );
+    } else {
+        let bar_x = x + 8.0 * direction;
+        let _ = write!(
+            out,
+            r#"<path d="M{:.1},{:.1} L{:.1},{:.1}" {}/>"#,
+            bar_x,
+            y - 6.0,
+            bar_x,
+            y + 6.0,
+            stroke
+        );
+    }
+    if optional {
+        let _ = write!(
+            out,
+            r##"<circle cx="{:.1}" cy="{:.1}" r="4" fill="#ffffff" stroke="#64748b" stroke-width="1.2"/>"##,
+            x + 16.0 * direction,
+            y
+        );
+    }
+}
+
+fn svg(tables: &[TableDetails], relationships: &[Relationship]) -> String {
+    let (placements, width, height) = layout(tables, relationships);
+
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{w:.0}" height="{h:.0}" viewBox="0 0 {w:.0} {h:.0}" font-family="Menlo, Consolas, 'DejaVu Sans Mono', monospace" font-size="12">"##,
+        w = width,
+        h = height
+    );
+    out.push_str(r##"<rect width="100%" height="100%" fill="#ffffff"/>"##);
+
+    // Lines go under the boxes
+    for relationship in relationships {
+        let (child, parent) = (&placements[relationship.child], &placements[relationship.parent]);
+        let start_y = child.row_y(&tables[relationship.child], relationship.foreign_key.columns.first());
+        let end_y = parent.row_y(
+            &tables[relationship.parent],
+            relationship.foreign_key.referenced_columns.first(),
+        );
+
+        // Leave from the side facing the parent; boxes above each other are joined on the right
+        let (start_x, start_dir, end_x, end_dir) = if child.x + child.width < parent.x {
+            (child.x + child.width, 1.0, parent.x, -1.0)
+        } else if parent.x + parent.width < child.x {
+            (child.x, -1.0, parent.x + parent.width, 1.0)
+        } else {
+            (child.x + child.width, 1.0, parent.x + parent.width, 1.0)
+        };
+        let reach = if start_dir == end_dir {
+            (start_x - end_x).abs() + LOOP_REACH
+        } else {
+            ((end_x - start_x).abs() / 2.0).max(30.0)
+        };
+        let _ = write!(
+            out,
+            r##"<path d="M{:.1},{:.1} C{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}" fill="none" stroke="#64748b" stroke-width="1.2"/>"##,
+            start_x,
+            start_y,
+            start_x + reach * start_dir,
+            start_y,
+            end_x + reach * end_dir,
+            end_y,
+            end_x,
+            end_y
+        );
+        line_end(&mut out, start_x, start_y, start_dir, !relationship.unique, true);
+        line_end(&mut out, end_x, end_y, end_dir, false, relationship.optional);
+    }
+
+    for (table, placement) in tables.iter().zip(&placements) {
+        let Placement { x, y, width, height, .. } = *placement;
+        let _ = write!(
+            out,
+            r##"<g><rect x="{x:.1}" y="{y:.1}" width="{width:.1}" height="{height:.1}" rx="4" fill="#ffffff" stroke="#94a3b8"/><rect x="{x:.1}" y="{y:.1}" width="{width:.1}" height="{HEADER_HEIGHT}" rx="4" fill="#e2e8f0" stroke="#94a3b8"/><text x="{:.1}" y="{:.1}" font-weight="bold" fill="#0f172a">{}</text>"##,
+            x + BOX_PADDING,
+            y + HEADER_HEIGHT / 2.0 + 4.0,
+            escape_xml(&table.name)
+        );
+        for (i, column) in table.columns.iter().enumerate() {
+            let baseline = y + HEADER_HEIGHT + ROW_HEIGHT * i as f64 + ROW_HEIGHT / 2.0 + 4.0;
+            let markers = key_markers(table, &column.name);
+            if !markers.is_empty() {
+                let _ = write!(
+                    out,
+                    r##"<text x="{:.1}" y="{:.1}" font-size="9" fill="#b45309">{}</text>"##,
+                    x + 4.0,
+                    baseline - 1.0,
+                    markers.join(" ").replace("PK FK", "PF")
+                );
+            }
+            let weight = if column.nullable { "normal" } else { "bold" };
+            let _ = write!(
+                out,
+                r##"<text x="{:.1}" y="{:.1}" font-weight="{}" fill="#0f172a">{}</text><text x="{:.1}" y="{:.1}" fill="#64748b">{}</text>"##,
+                x + KEY_GUTTER,
+                baseline,
+                weight,
+                escape_xml(&column.name),
+                x + placement.type_offset,
+                baseline,
+                escape_xml(&column.data_type)
+            );
+        }
+        out.push_str("</g>");
+    }
+
+    out.push_str("</svg>\n");
+    out
+}
+
+fn render_png(svg: &str, path: &Path) -> Result<(), String> {
+    let mut options = resvg::usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    let tree = resvg::usvg::Tree::from_str(svg, &options).map_err(|e| e.to_string())?;
+
+    let size = tree.size().to_int_size().scale_by(PNG_SCALE).ok_or("Diagram is too large to render")?;
+    let mut pixmap =
+        resvg::tiny_skia::Pixmap::new(size.width(), size.height()).ok_or("Diagram is too large to render")?;
+    resvg::render(
+        &tree,
+        resvg::tiny_skia::Transform::from_scale(PNG_SCALE, PNG_SCALE),
+        &mut pixmap.as_mut(),
+    );
+    pixmap
+        .save_png(path)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+async fn build_diagram(
+    sessions: &PgSessions,
+    connections: &SavedConnections,
+    connection_id: &str,
+    schema: &str,
+    tables: Option<Vec<String>>,
+) -> Result<ErDiagram, String> {
+    let connection = connections.get(connection_id).await?;
+    let session = sessions.acquire(&connection).await?;
+    let mut conn = session.conn.lock().await;
+
+    let mut details = match load_tables(&mut conn, schema, None).await {
+        Ok(details) => details,
+        Err(e) => return Err(session_error(sessions, &session, e).await),
+    };
+    drop(conn);
+
+    details.retain(|t| t.table_type == "BASE TABLE" || t.table_type == "PARTITIONED TABLE");
+    if let Some(names) = tables {
+        if let Some(missing) = names.iter().find(|name| !details.iter().any(|t| t.name == **name)) {
+            return Err(format!("Table \"{}\".\"{}\" not found", schema, missing));
+        }
+        details.retain(|t| names.contains(&t.name));
+    }
+
+    let relationships = relationships(&details);
+    Ok(ErDiagram {
+        mermaid: mermaid(&details, &relationships),
+        dot: dot(&details, &relationships),
+        svg: svg(&details, &relationships),
+    })
+}
+
+/// Entity-relationship diagram of the tables of a schema (or just `tables`) with their
+/// columns and foreign keys, as Mermaid, Graphviz DOT and SVG
+#[tauri::command]
+pub async fn pg_er_diagram(
+    sessions: State<'_, PgSessions>,
+    connections: State<'_, SavedConnections>,
+    connection_id: String,
+    schema: String,
+    tables: Option<Vec<String>>,
+) -> Result<ErDiagram, String> {
+    build_diagram(&sessions, &connections, &connection_id, &schema, tables).await
+}
+
+/// Ask the user where to save the diagram
+async fn choose_path(app: &AppHandle, schema: &str, format: DiagramFormat) -> Option<PathBuf> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    app.dialog()
+        .file()
+        .set_file_name(format!("{}.{}", schema, format.extension()))
+        .add_filter(format.label(), &[format.extension()])
+        .save_file(move |path| {
+            let _ = tx.send(path);
+        });
+
+    rx.await.ok().flatten().and_then(|path| path.into_path().ok())
+}
+
+/// Save an ER diagram to a file, rendering it for PNG. Without a `path` the user picks one in
+/// a save dialog; returns the path written, or `None` if they cancel it.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn pg_export_er_diagram(
+    app: AppHandle,
+    sessions: State<'_, PgSessions>,
+    connections: State<'_, SavedConnections>,
+    connection_id: String,
+    schema: String,
+    tables: Option<Vec<String>>,
+    format: DiagramFormat,
+    path: Option<String>,
+) -> Result<Option<String>, String> {
+    let diagram = build_diagram(&sessions, &connections, &connection_id, &schema, tables).await?;
+
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => match choose_path(&app, &schema, format).await {
+            Some(path) => path,
+            None => return Ok(None),
+        },
+    };
+
+    let text = match format {
+        DiagramFormat::Mermaid => diagram.mermaid,
+        DiagramFormat::Dot => diagram.dot,
+        DiagramFormat::Svg => diagram.svg,
+        DiagramFormat::Png => {
+            let svg = diagram.svg;
+            let png_path = path.clone();
+            tokio::task::spawn_blocking(move || render_png(&svg, &png_path))
+                .await
+                .map_err(|e| e.to_string())??;
+            return Ok(Some(path.display().to_string()));
+        }
+    };
+    std::fs::write(&path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    Ok(Some(path.display().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::postgres::schema::{ColumnDetails, ConstraintInfo, PrimaryKey};
+
+    fn column(name: &str, data_type: &str, nullable: bool) -> ColumnDetails {
+        ColumnDetails {
+            name: name.to_string(),
+            position: 0,
+            data_type: data_type.to_string(),
+            nullable,
+            default_value: None,
+            is_identity: false,
+            is_generated: false,
+            comment: None,
+        }
+    }
+
+    fn foreign_key(column: &str, referenced_table: &str) -> ForeignKey {
+        ForeignKey {
+            name: format!("{}_fkey", column),
+            columns: vec![column.to_string()],
+            referenced_schema: "public".to_string(),
+            referenced_table: referenced_table.to_string(),
+            referenced_columns: vec!["id".to_string()],
+            on_update: "NO ACTION".to_string(),
+            on_delete: "NO ACTION".to_string(),
+        }
+    }
+
+    /// A table keyed on its first column, with `foreign_keys`
+    fn table(name: &str, columns: Vec<ColumnDetails>, foreign_keys: Vec<ForeignKey>) -> TableDetails {
+        let key = vec![columns[0].name.clone()];
+        TableDetails {
+            schema: "public".to_string(),
+            name: name.to_string(),
+            table_type: "BASE TABLE".to_string(),
+            owner: "postgres".to_string(),
+            comment: None,
+            estimated_rows: None,
+            view_definition: None,
+            columns,
+            primary_key: Some(PrimaryKey {
+                name: format!("{}_pkey", name),
+                columns: key.clone(),
+            }),
+            foreign_keys,
+            indexes: Vec::new(),
+            constraints: vec![ConstraintInfo {
+                name: format!("{}_pkey", name),
+                constraint_type: "PRIMARY KEY".to_string(),
+                columns: key,
+                definition: String::new(),
+            }],
+        }
+    }
+
+    /// Users with a one-to-one profile, optional orders, a self-referencing manager,
+    /// and a key to a table that isn't in the diagram
+    fn tables() -> Vec<TableDetails> {
+        let mut elsewhere = foreign_key("account_id", "accounts");
+        elsewhere.referenced_schema = "billing".to_string();
+        vec![
+            table(
+                "users",
+                vec![
+                    column("id", "integer", false),
+                    column("manager_id", "integer", true),
+                    column("account_id", "integer", false),
+                ],
+                vec![foreign_key("manager_id", "users"), elsewhere, foreign_key("account_id", "accounts")],
+            ),
+            table(
+                "profiles",
+                vec![column("user_id", "integer", false), column("bio", "text", true)],
+                vec![foreign_key("user_id", "users")],
+            ),
+            table(
+                "orders",
+                vec![column("id", "bigint", false), column("user_id", "integer", true)],
+                vec![foreign_key("user_id", "users")],
+            ),
+            table("audit", vec![column("id", "bigint", false)], Vec::new()),
+        ]
+    }
+
+    #[test]
+    fn finds_relationships_between_tables_of_the_diagram() {
+        let tables = tables();
+        let found: Vec<(usize, usize, &str, bool, bool)> = relationships(&tables)
+            .iter()
+            .map(|r| (r.child, r.parent, r.foreign_key.name.as_str(), r.optional, r.unique))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (0, 0, "manager_id_fkey", true, false),
+                (1, 0, "user_id_fkey", false, true),
+                (2, 0, "user_id_fkey", true, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_mermaid_cardinality_and_escapes_names() {
+        let mut tables = tables();
+        tables.push(table(
+            "order items",
+            vec![column("2nd", "character varying(20)", false), column("tags", "text[]", true)],
+            vec![foreign_key("2nd", "orders")],
+        ));
+        let relationships = relationships(&tables);
+        let mermaid = mermaid(&tables, &relationships);
+
+        assert!(mermaid.starts_with("erDiagram\n    users {\n        integer id PK \"NOT NULL\"\n"));
+        assert!(mermaid.contains("        integer manager_id FK\n"));
+        assert!(mermaid.contains("    \"order items\" {\n        character_varying(20) _2nd PK, FK \"NOT NULL\"\n"));
+        assert!(mermaid.contains("        text[] tags\n"));
+        assert!(mermaid.contains("    users }o--o| users : \"manager_id\"\n"));
+        assert!(mermaid.contains("    profiles |o--|| users : \"user_id\"\n"));
+        assert!(mermaid.contains("    orders }o--o| users : \"user_id\"\n"));
+        assert!(mermaid.contains("    \"order items\" |o--|| orders : \"2nd\"\n"));
+        assert!(!mermaid.contains("accounts"));
+    }
+
+    #[test]
+    fn writes_dot_ports_and_escapes_labels() {
+        let mut tables = tables();
+        tables.push(table(
+            "say \"hi\"",
+            vec![column("a<b", "numeric(10,2)", false), column("user_id", "integer", false)],
+            vec![foreign_key("user_id", "users")],
+        ));
+        let relationships = relationships(&tables);
+        let dot = dot(&tables, &relationships);
+
+        assert!(dot.starts_with("digraph er {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("    \"users\":c1 -> \"users\":c0 [arrowtail=crowodot, arrowhead=teeodot];\n"));
+        assert!(dot.contains("    \"profiles\":c0 -> \"users\":c0 [arrowtail=teeodot, arrowhead=teetee];\n"));
+        assert!(dot.contains("    \"orders\":c1 -> \"users\":c0 [arrowtail=crowodot, arrowhead=teeodot];\n"));
+        assert!(dot.contains("    \"say \\\"hi\\\"\" [label=<"));
+        assert!(dot.contains("<b>say &quot;hi&quot;</b>"));
+        assert!(dot.contains("<td port=\"c0\" align=\"left\">a&lt;b</td><td align=\"left\">numeric(10,2)</td><td>PK</td>"));
+        assert!(dot.contains("    \"say \\\"hi\\\"\":c1 -> \"users\":c0 [arrowtail=crowodot, arrowhead=teetee];\n"));
+    }
+
+    #[test]
+    fn lays_related_tables_out_together() {
+        let tables = tables();
+        let relationships = relationships(&tables);
+        // users is the most connected; the unrelated audit table comes last
+        assert_eq!(layout_order(&tables, &relationships), vec![0, 1, 2, 3]);
+
+        let (placements, width, height) = layout(&tables, &relationships);
+        let at: Vec<(f64, f64)> = placements.iter().map(|p| (p.x, p.y)).collect();
+        let first_row = placements[0].height.max(placements[1].height);
+        let first_column = placements[0].width.max(placements[2].width);
+        assert_eq!(
+            at,
+            vec![
+                (MARGIN, MARGIN),
+                (MARGIN + first_column + GAP_X, MARGIN),
+                (MARGIN, MARGIN + first_row + GAP_Y),
+                (MARGIN + first_column + GAP_X, MARGIN + first_row + GAP_Y),
+            ]
+        );
+        assert_eq!(placements[0].height, HEADER_HEIGHT + 3.0 * ROW_HEIGHT);
+        // "manager_id" is the longest name in users, "integer" its longest type
+        assert_eq!(placements[0].type_offset, KEY_GUTTER + 12.0 * CHAR_WIDTH);
+        assert_eq!(placements[0].width, KEY_GUTTER + 12.0 * CHAR_WIDTH + 7.0 * CHAR_WIDTH + BOX_PADDING);
+        assert!(placements.iter().all(|p| p.x + p.width <= width && p.y + p.height <= height));
+        let bio = placements[1].row_y(&tables[1], Some(&"bio".to_string()));
+        assert_eq!(bio, MARGIN + HEADER_HEIGHT + 1.5 * ROW_HEIGHT);
+    }
+}
@@ -0,0 +1,866 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tauri::State;
+
+use super::schema::{
+    load_schema, ColumnDetails, ConstraintInfo, EnumInfo, FunctionInfo, IndexInfo, SchemaDetails, SequenceInfo,
+    TableDetails,
+};
+use super::{quote_identifier, session_error, PgConnection, PgSessions, SavedConnections};
+
+/// How an object differs, seen from the target: the migration adds, drops or alters it there
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffKind {
+    /// Only in the source
+    Added,
+    /// Only in the target
+    Removed,
+    Changed,
+}
+
+/// A column, index, constraint, function, sequence or enum that differs.
+/// `source` and `target` describe it on each side, `None` where it doesn't exist.
+#[derive(Debug, Serialize)]
+pub struct ObjectDiff {
+    pub name: String,
+    pub kind: DiffKind,
+    pub source: Option<String>,
+    pub target: Option<String>,
+}
+
+/// A table or view that differs. The item lists are only filled in for `changed` relations.
+#[derive(Debug, Serialize)]
+pub struct TableDiff {
+    pub name: String,
+    pub kind: DiffKind,
+    pub table_type: String,
+    /// Query of a view or materialized view differs
+    pub definition_changed: bool,
+    pub columns: Vec<ObjectDiff>,
+    pub indexes: Vec<ObjectDiff>,
+    pub constraints: Vec<ObjectDiff>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SchemaDiff {
+    pub schema: String,
+    pub tables: Vec<TableDiff>,
+    pub functions: Vec<ObjectDiff>,
+    pub sequences: Vec<ObjectDiff>,
+    pub enums: Vec<ObjectDiff>,
+    /// SQL that brings the target schema in line with the source
+    pub migration: String,
+}
+
+/// Objects of both sides matched by key, in key order
+fn pair<'a, T>(source: &'a [T], target: &'a [T], key: impl Fn(&T) -> String) -> Vec<(String, Option<&'a T>, Option<&'a T>)> {
+    let mut pairs: BTreeMap<String, (Option<&T>, Option<&T>)> = BTreeMap::new();
+    for item in source {
+        pairs.entry(key(item)).or_default().0 = Some(item);
+    }
+    for item in target {
+        pairs.entry(key(item)).or_default().1 = Some(item);
+    }
+    pairs.into_iter().map(|(key, (s, t))| (key, s, t)).collect()
+}
+
+/// Objects that are missing on one side or whose description differs
+fn diff_objects<T>(pairs: &[(String, Option<&T>, Option<&T>)], describe: impl Fn(&T) -> String) -> Vec<ObjectDiff> {
+    pairs
+        .iter()
+        .filter_map(|(name, source, target)| {
+            let source = source.map(&describe);
+            let target = target.map(&describe);
+            let kind = match (&source, &target) {
+                (Some(_), None) => DiffKind::Added,
+                (None, Some(_)) => DiffKind::Removed,
+                (s, t) if s != t => DiffKind::Changed,
+                _ => return None,
+            };
+            Some(ObjectDiff {
+                name: name.clone(),
+                kind,
+                source,
+                target,
+            })
+        })
+        .collect()
+}
+
+/// Column as written after its name in `CREATE TABLE`. Identity columns are always
+/// described as `BY DEFAULT`, since the schema doesn't say which kind they are.
+fn column_definition(column: &ColumnDetails) -> String {
+    let mut definition = column.data_type.clone();
+    if column.is_generated {
+        let expression = column.default_value.as_deref().unwrap_or_default();
+        definition.push_str(&format!(" GENERATED ALWAYS AS ({}) STORED", expression));
+    } else if column.is_identity {
+        definition.push_str(" GENERATED BY DEFAULT AS IDENTITY");
+    } else if let Some(default) = &column.default_value {
+        definition.push_str(&format!(" DEFAULT {}", default));
+    }
+    if !column.nullable {
+        definition.push_str(" NOT NULL");
+    }
+    definition
+}
+
+fn sequence_definition(sequence: &SequenceInfo) -> String {
+    format!(
+        "AS {} INCREMENT BY {} MINVALUE {} MAXVALUE {} START WITH {} {}",
+        sequence.data_type,
+        sequence.increment_by,
+        sequence.min_value,
+        sequence.max_value,
+        sequence.start_value,
+        if sequence.cycle { "CYCLE" } else { "NO CYCLE" }
+    )
+}
+
+fn enum_definition(info: &EnumInfo) -> String {
+    let labels: Vec<String> = info.values.iter().map(|v| format!("'{}'", v.replace('\'', "''"))).collect();
+    format!("ENUM ({})", labels.join(", "))
+}
+
+fn function_definition(function: &FunctionInfo) -> String {
+    function.definition.clone().unwrap_or_else(|| {
+        format!(
+            "{} {}({}) RETURNS {}",
+            function.kind,
+            function.name,
+            function.arguments,
+            function.result_type.as_deref().unwrap_or_default()
+        )
+    })
+}
+
+/// Indexes created by hand; those backing a primary key, unique or exclusion constraint
+/// come and go with the constraint
+fn standalone_indexes(table: &TableDetails) -> Vec<&IndexInfo> {
+    table
+        .indexes
+        .iter()
+        .filter(|index| !table.constraints.iter().any(|c| c.name == index.name))
+        .collect()
+}
+
+fn is_view(table: &TableDetails) -> bool {
+    table.table_type.ends_with("VIEW")
+}
+
+fn view_definition(table: &TableDetails) -> String {
+    table
+        .view_definition
+        .as_deref()
+        .unwrap_or_default()
+        .trim()
+        .trim_end_matches(';')
+        .to_string()
+}
+
+/// Compare one relation present on both sides; `None` when they're the same
+fn diff_table(source: &TableDetails, target: &TableDetails) -> Option<TableDiff> {
+    let columns = diff_objects(&pair(&source.columns, &target.columns, |c| c.name.clone()), column_definition);
+    let indexes = diff_objects(
+        &pair(&standalone_indexes(source), &standalone_indexes(target), |i| i.name.clone()),
+        |i| i.definition.clone(),
+    );
+    let constraints = diff_objects(
+        &pair(&source.constraints, &target.constraints, |c| c.name.clone()),
+        |c| c.definition.clone(),
+    );
+    let definition_changed = view_definition(source) != view_definition(target);
+
+    if columns.is_empty() && indexes.is_empty() && constraints.is_empty() && !definition_changed {
+        return None;
+    }
+    Some(TableDiff {
+        name: source.name.clone(),
+        kind: DiffKind::Changed,
+        table_type: source.table_type.clone(),
+        definition_changed,
+        columns,
+        indexes,
+        constraints,
+    })
+}
+
+// Heads the enum labels that are added ahead of the rest of the migration
+const ENUM_VALUES_NOTE: &str = "\
+-- Run these first and commit them on their own: before PostgreSQL 12, ALTER TYPE ... ADD VALUE
+-- can't run inside a transaction block, and a new label can't be used in the transaction that adds it";
+
+/// Statements of a migration, collected by phase so that dependent objects are dropped
+/// before and created after what they depend on
+#[derive(Default)]
+struct Migration {
+    /// `ALTER TYPE ... ADD VALUE`, a batch of its own ahead of the phases below
+    add_enum_values: Vec<String>,
+    drop_views: Vec<String>,
+    drop_functions: Vec<String>,
+    drop_foreign_keys: Vec<String>,
+    drop_constraints: Vec<String>,
+    drop_indexes: Vec<String>,
+    drop_tables: Vec<String>,
+    drop_types: Vec<String>,
+    create_types: Vec<String>,
+    create_tables: Vec<String>,
+    alter_tables: Vec<String>,
+    add_constraints: Vec<String>,
+    add_foreign_keys: Vec<String>,
+    create_indexes: Vec<String>,
+    create_functions: Vec<String>,
+    create_views: Vec<String>,
+}
+
+impl Migration {
+    /// Statements of every phase except `add_enum_values`, in order
+    fn statements(self) -> Vec<String> {
+        [
+            self.drop_views,
+            self.drop_functions,
+            self.drop_foreign_keys,
+            self.drop_constraints,
+            self.drop_indexes,
+            self.drop_tables,
+            self.drop_types,
+            self.create_types,
+            self.create_tables,
+            self.alter_tables,
+            self.add_constraints,
+            self.add_foreign_keys,
+            self.create_indexes,
+            self.create_functions,
+            self.create_views,
+        ]
+        .concat()
+    }
+}
+
+struct MigrationBuilder<'a> {
+    schema: &'a str,
+    migration: Migration,
+}
+
+impl MigrationBuilder<'_> {
+    fn qualified(&self, name: &str) -> String {
+        format!("{}.{}", quote_identifier(self.schema), quote_identifier(name))
+    }
+
+    fn add_constraint(&mut self, table: &str, constraint: &ConstraintInfo) {
+        let statement = format!(
+            "ALTER TABLE {} ADD CONSTRAINT {} {};",
+            self.qualified(table),
+            quote_identifier(&constraint.name),
+            constraint.definition
+        );
+        if constraint.constraint_type == "FOREIGN KEY" {
+            self.migration.add_foreign_keys.push(statement);
+        } else {
+            self.migration.add_constraints.push(statement);
+        }
+    }
+
+    fn drop_constraint(&mut self, table: &str, constraint: &ConstraintInfo) {
+        let statement = format!(
+            "ALTER TABLE {} DROP CONSTRAINT {};",
+            self.qualified(table),
+            quote_identifier(&constraint.name)
+        );
+        if constraint.constraint_type == "FOREIGN KEY" {
+            self.migration.drop_foreign_keys.push(statement);
+        } else {
+            self.migration.drop_constraints.push(statement);
+        }
+    }
+
+    fn create_relation(&mut self, table: &TableDetails) {
+        let name = self.qualified(&table.name);
+        match table.table_type.as_str() {
+            "VIEW" => self
+                .migration
+                .create_views
+                .push(format!("CREATE VIEW {} AS\n{};", name, view_definition(table))),
+            "MATERIALIZED VIEW" => self
+                .migration
+                .create_views
+                .push(format!("CREATE MATERIALIZED VIEW {} AS\n{};", name, view_definition(table))),
+            "BASE TABLE" => {
+                let columns: Vec<String> = table
+                    .columns
+                    .iter()
+                    .map(|c| format!("    {} {}", quote_identifier(&c.name), column_definition(c)))
+                    .collect();
+                self.migration
+                    .create_tables
+                    .push(format!("CREATE TABLE {} (\n{}\n);", name, columns.join(",\n")));
+                for constraint in &table.constraints {
+                    self.add_constraint(&table.name, constraint);
+                }
+                for index in standalone_indexes(table) {
+                    self.migration.create_indexes.push(format!("{};", index.definition));
+                }
+            }
+            other => self.migration.create_tables.push(format!(
+                "-- {} {} has to be created by hand",
+                other.to_lowercase(),
+                name
+            )),
+        }
+    }
+
+    fn drop_relation(&mut self, table: &TableDetails) {
+        let name = self.qualified(&table.name);
+        match table.table_type.as_str() {
+            "VIEW" => self.migration.drop_views.push(format!("DROP VIEW {};", name)),
+            "MATERIALIZED VIEW" => self.migration.drop_views.push(format!("DROP MATERIALIZED VIEW {};", name)),
+            "FOREIGN TABLE" => self.migration.drop_tables.push(format!("DROP FOREIGN TABLE {};", name)),
+            _ => self.migration.drop_tables.push(format!("DROP TABLE {};", name)),
+        }
+    }
+
+    /// Alter a table present on both sides to match the source
+    fn alter_table(&mut self, source: &TableDetails, target: &TableDetails) {
+        let mut actions = Vec::new();
+        for (name, s, t) in pair(&source.columns, &target.columns, |c| c.name.clone()) {
+            let column = quote_identifier(&name);
+            match (s, t) {
+                (Some(s), None) => actions.push(format!("ADD COLUMN {} {}", column, column_definition(s))),
+                (None, Some(_)) => actions.push(format!("DROP COLUMN {}", column)),
+                (Some(s), Some(t)) => {
+                    // A generated column's expression can't be altered, only replaced
+                    if s.is_generated != t.is_generated || (s.is_generated && s.default_value != t.default_value) {
+                        actions.push(format!("DROP COLUMN {}", column));
+                        actions.push(format!("ADD COLUMN {} {}", column, column_definition(s)));
+                        continue;
+                    }
+                    if s.data_type != t.data_type {
+                        actions.push(format!(
+                            "ALTER COLUMN {} TYPE {} USING {}::{}",
+                            column, s.data_type, column, s.data_type
+                        ));
+                    }
+                    if s.is_identity != t.is_identity {
+                        if s.is_identity {
+                            if t.default_value.is_some() {
+                                actions.push(format!("ALTER COLUMN {} DROP DEFAULT", column));
+                            }
+                            actions.push(format!("ALTER COLUMN {} ADD GENERATED BY DEFAULT AS IDENTITY", column));
+                        } else {
+                            actions.push(format!("ALTER COLUMN {} DROP IDENTITY", column));
+                        }
+                    }
+                    if !s.is_generated && !s.is_identity && s.default_value != t.default_value {
+                        match &s.default_value {
+                            Some(default) => actions.push(format!("ALTER COLUMN {} SET DEFAULT {}", column, default)),
+                            None => actions.push(format!("ALTER COLUMN {} DROP DEFAULT", column)),
+                        }
+                    }
+                    if s.nullable != t.nullable {
+                        let change = if s.nullable { "DROP" } else { "SET" };
+                        actions.push(format!("ALTER COLUMN {} {} NOT NULL", column, change));
+                    }
+                }
+                (None, None) => {}
+            }
+        }
+        if !actions.is_empty() {
+            self.migration.alter_tables.push(format!(
+                "ALTER TABLE {}\n    {};",
+                self.qualified(&source.name),
+                actions.join(",\n    ")
+            ));
+        }
+
+        for (_, s, t) in pair(&source.constraints, &target.constraints, |c| c.name.clone()) {
+            match (s, t) {
+                (Some(s), Some(t)) if s.definition == t.definition => {}
+                (s, t) => {
+                    if let Some(t) = t {
+                        self.drop_constraint(&target.name, t);
+                    }
+                    if let Some(s) = s {
+                        self.add_constraint(&source.name, s);
+                    }
+                }
+            }
+        }
+
+        for (name, s, t) in pair(&standalone_indexes(source), &standalone_indexes(target), |i| i.name.clone()) {
+            match (s, t) {
+                (Some(s), Some(t)) if s.definition == t.definition => {}
+                (s, t) => {
+                    if t.is_some() {
+                        self.migration.drop_indexes.push(format!("DROP INDEX {};", self.qualified(&name)));
+                    }
+                    if let Some(s) = s {
+                        self.migration.create_indexes.push(format!("{};", s.definition));
+                    }
+                }
+            }
+        }
+    }
+
+    fn drop_function(&mut self, function: &FunctionInfo) {
+        let kind = match function.kind.as_str() {
+            "procedure" => "PROCEDURE",
+            "aggregate" => "AGGREGATE",
+            _ => "FUNCTION",
+        };
+        self.migration.drop_functions.push(format!(
+            "DROP {} {}({});",
+            kind,
+            self.qualified(&function.name),
+            function.identity_arguments
+        ));
+    }
+
+    fn create_function(&mut self, function: &FunctionInfo) {
+        match &function.definition {
+            Some(definition) => self
+                .migration
+                .create_functions
+                .push(format!("{};", definition.trim_end())),
+            None => self.migration.create_functions.push(format!(
+                "-- {} {}({}) has to be created by hand",
+                function.kind,
+                self.qualified(&function.name),
+                function.arguments
+            )),
+        }
+    }
+}
+
+/// Differences between two snapshots of a schema, and the SQL that turns `target` into `source`
+fn diff_schemas(source: &SchemaDetails, target: &SchemaDetails, header: &str) -> SchemaDiff {
+    let mut builder = MigrationBuilder {
+        schema: &source.name,
+        migration: Migration::default(),
+    };
+
+    let mut tables = Vec::new();
+    for (name, s, t) in pair(&source.tables, &target.tables, |t| t.name.clone()) {
+        match (s, t) {
+            (Some(s), Some(t)) if s.table_type == t.table_type => {
+                let Some(diff) = diff_table(s, t) else { continue };
+                if is_view(s) {
+                    // Views are recreated rather than altered, as their columns may change
+                    builder.drop_relation(t);
+                    builder.create_relation(s);
+                } else {
+                    builder.alter_table(s, t);
+                }
+                tables.push(diff);
+            }
+            (s, t) => {
+                // Missing on one side, or a table on one side and a view on the other
+                if let Some(t) = t {
+                    builder.drop_relation(t);
+                    tables.push(TableDiff {
+                        name: name.clone(),
+                        kind: DiffKind::Removed,
+                        table_type: t.table_type.clone(),
+                        definition_changed: false,
+                        columns: Vec::new(),
+                        indexes: Vec::new(),
+                        constraints: Vec::new(),
+                    });
+                }
+                if let Some(s) = s {
+                    builder.create_relation(s);
+                    tables.push(TableDiff {
+                        name,
+                        kind: DiffKind::Added,
+                        table_type: s.table_type.clone(),
+                        definition_changed: false,
+                        columns: Vec::new(),
+                        indexes: Vec::new(),
+                        constraints: Vec::new(),
+                    });
+                }
+            }
+        }
+    }
+
+    let function_pairs = pair(&source.functions, &target.functions, |f| {
+        format!("{}({})", f.name, f.identity_arguments)
+    });
+    let functions = diff_objects(&function_pairs, function_definition);
+    for (_, s, t) in &function_pairs {
+        match (s, t) {
+            (Some(s), Some(t)) if s.definition == t.definition => {}
+            // `CREATE OR REPLACE` can't change the result type or the kind
+            (Some(s), Some(t)) if s.result_type == t.result_type && s.kind == t.kind => builder.create_function(s),
+            (s, t) => {
+                if let Some(t) = t {
+                    builder.drop_function(t);
+                }
+                if let Some(s) = s {
+                    builder.create_function(s);
+                }
+            }
+        }
+    }
+
+    // Identity sequences belong to their column
+    let source_sequences: Vec<&SequenceInfo> = source.sequences.iter().filter(|s| !s.is_identity).collect();
+    let target_sequences: Vec<&SequenceInfo> = target.sequences.iter().filter(|s| !s.is_identity).collect();
+    let sequence_pairs = pair(&source_sequences, &target_sequences, |s| s.name.clone());
+    let sequences = diff_objects(&sequence_pairs, |s| sequence_definition(s));
+    for (name, s, t) in &sequence_pairs {
+        let qualified = builder.qualified(name);
+        match (s, t) {
+            (Some(s), None) => builder
+                .migration
+                .create_types
+                .push(format!("CREATE SEQUENCE {} {};", qualified, sequence_definition(s))),
+            (None, Some(_)) => builder.migration.drop_types.push(format!("DROP SEQUENCE {};", qualified)),
+            (Some(s), Some(t)) if sequence_definition(s) != sequence_definition(t) => builder
+                .migration
+                .create_types
+                .push(format!("ALTER SEQUENCE {} {};", qualified, sequence_definition(s))),
+            _ => {}
+        }
+    }
+
+    let enum_pairs = pair(&source.enums, &target.enums, |e| e.name.clone());
+    let enums = diff_objects(&enum_pairs, enum_definition);
+    for (name, s, t) in &enum_pairs {
+        let qualified = builder.qualified(name);
+        match (s, t) {
+            (Some(s), None) => builder
+                .migration
+                .create_types
+                .push(format!("CREATE TYPE {} AS {};", qualified, enum_definition(s))),
+            (None, Some(_)) => builder.migration.drop_types.push(format!("DROP TYPE {};", qualified)),
+            (Some(s), Some(t)) => {
+                // Labels can be added in place; removing or reordering them needs a new type
+                let kept = s.values.iter().filter(|v| t.values.contains(v));
+                if s.values.len() > t.values.len() && kept.clone().eq(&t.values) {
+                    let mut previous: Option<&String> = None;
+                    for value in &s.values {
+                        if !t.values.contains(value) {
+                            // Without a position the label would go last
+                            let position = match (previous, t.values.first()) {
+                                (Some(previous), _) => format!(" AFTER '{}'", previous.replace('\'', "''")),
+                                (None, Some(first)) => format!(" BEFORE '{}'", first.replace('\'', "''")),
+                                (None, None) => String::new(),
+                            };
+                            builder.migration.add_enum_values.push(format!(
+                                "ALTER TYPE {} ADD VALUE '{}'{};",
+                                qualified,
+                                value.replace('\'', "''"),
+                                position
+                            ));
+                        }
+                        previous = Some(value);
+                    }
+                } else if s.values != t.values {
+                    builder.migration.create_types.push(format!(
+                        "-- enum {} differs in ways ALTER TYPE can't express; it has to be recreated by hand",
+                        qualified
+                    ));
+                }
+            }
+            (None, None) => {}
+        }
+    }
+
+    let enum_values = std::mem::take(&mut builder.migration.add_enum_values);
+    let statements = builder.migration.statements();
+    let migration = match (enum_values.is_empty(), statements.is_empty()) {
+        (true, true) => format!("-- {}\n-- No differences\n", header),
+        (true, false) => format!("-- {}\n-- Review before running\n\n{}\n", header, statements.join("\n\n")),
+        (false, true) => format!(
+            "-- {}\n-- Review before running\n\n{}\n{}\n",
+            header,
+            ENUM_VALUES_NOTE,
+            enum_values.join("\n")
+        ),
+        (false, false) => format!(
+            "-- {}\n-- Review before running\n\n{}\n{}\n\n-- Then the rest of the migration\n\n{}\n",
+            header,
+            ENUM_VALUES_NOTE,
+            enum_values.join("\n"),
+            statements.join("\n\n")
+        ),
+    };
+
+    SchemaDiff {
+        schema: source.name.clone(),
+        tables,
+        functions,
+        sequences,
+        enums,
+        migration,
+    }
+}
+
+async fn describe(sessions: &PgSessions, connection: &PgConnection, schema: &str) -> Result<SchemaDetails, String> {
+    let session = sessions.acquire(connection).await?;
+    let mut conn = session.conn.lock().await;
+
+    match load_schema(&mut conn, schema).await {
+        Ok(details) => Ok(details),
+        Err(e) => Err(session_error(sessions, &session, e).await),
+    }
+}
+
+/// Compare a schema on two connections (e.g. staging and production): tables, views, columns,
+/// indexes, constraints, functions, sequences and enums. The migration script makes the target
+/// match the source; it isn't run.
+#[tauri::command]
+pub async fn pg_diff_schemas(
+    sessions: State<'_, PgSessions>,
+    connections: State<'_, SavedConnections>,
+    source_connection_id: String,
+    target_connection_id: String,
+    schema: String,
+) -> Result<SchemaDiff, String> {
+    let source_connection = connections.get(&source_connection_id).await?;
+    let target_connection = connections.get(&target_connection_id).await?;
+
+    let source = describe(&sessions, &source_connection, &schema).await?;
+    let target = describe(&sessions, &target_connection, &schema).await?;
+
+    let header = format!(
+        "Make schema {} on \"{}\" match \"{}\"",
+        quote_identifier(&schema),
+        target_connection.name,
+        source_connection.name
+    );
+    Ok(diff_schemas(&source, &target, &header))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, data_type: &str) -> ColumnDetails {
+        ColumnDetails {
+            name: name.to_string(),
+            position: 0,
+            data_type: data_type.to_string(),
+            nullable: true,
+            default_value: None,
+            is_identity: false,
+            is_generated: false,
+            comment: None,
+        }
+    }
+
+    fn constraint(name: &str, constraint_type: &str, definition: &str) -> ConstraintInfo {
+        ConstraintInfo {
+            name: name.to_string(),
+            constraint_type: constraint_type.to_string(),
+            columns: Vec::new(),
+            definition: definition.to_string(),
+        }
+    }
+
+    fn table(name: &str, columns: Vec<ColumnDetails>, constraints: Vec<ConstraintInfo>) -> TableDetails {
+        TableDetails {
+            schema: "public".to_string(),
+            name: name.to_string(),
+            table_type: "BASE TABLE".to_string(),
+            owner: "postgres".to_string(),
+            comment: None,
+            estimated_rows: None,
+            view_definition: None,
+            columns,
+            primary_key: None,
+            foreign_keys: Vec::new(),
+            indexes: Vec::new(),
+            constraints,
+        }
+    }
+
+    fn view(name: &str, definition: &str) -> TableDetails {
+        TableDetails {
+            table_type: "VIEW".to_string(),
+            view_definition: Some(definition.to_string()),
+            ..table(name, vec![column("id", "integer")], Vec::new())
+        }
+    }
+
+    fn mood(values: &[&str]) -> EnumInfo {
+        EnumInfo {
+            name: "mood".to_string(),
+            values: values.iter().map(|v| v.to_string()).collect(),
+        }
+    }
+
+    fn schema(tables: Vec<TableDetails>, enums: Vec<EnumInfo>) -> SchemaDetails {
+        SchemaDetails {
+            name: "public".to_string(),
+            tables,
+            functions: Vec::new(),
+            sequences: Vec::new(),
+            enums,
+        }
+    }
+
+    /// Statements of the migration, without comments
+    fn statements(diff: &SchemaDiff) -> Vec<&str> {
+        diff.migration
+            .split("\n\n")
+            .map(str::trim)
+            .filter(|s| !s.is_empty() && !s.starts_with("--"))
+            .collect()
+    }
+
+    #[test]
+    fn identical_schemas_have_no_differences() {
+        let source = schema(vec![table("t", vec![column("id", "integer")], Vec::new())], vec![mood(&["a"])]);
+        let target = schema(vec![table("t", vec![column("id", "integer")], Vec::new())], vec![mood(&["a"])]);
+        let diff = diff_schemas(&source, &target, "header");
+        assert!(diff.tables.is_empty() && diff.enums.is_empty());
+        assert_eq!(diff.migration, "-- header\n-- No differences\n");
+    }
+
+    #[test]
+    fn creates_drops_and_recreates_relations() {
+        let source = schema(
+            vec![table("added", vec![column("id", "integer")], Vec::new()), view("v", "SELECT 2 AS id;")],
+            Vec::new(),
+        );
+        let target = schema(
+            vec![table("removed", vec![column("id", "integer")], Vec::new()), view("v", "SELECT 1 AS id")],
+            Vec::new(),
+        );
+        let diff = diff_schemas(&source, &target, "header");
+
+        let kinds: Vec<(&str, DiffKind)> = diff.tables.iter().map(|t| (t.name.as_str(), t.kind)).collect();
+        assert_eq!(
+            kinds,
+            [("added", DiffKind::Added), ("removed", DiffKind::Removed), ("v", DiffKind::Changed)]
+        );
+        assert!(diff.tables[2].definition_changed);
+        assert_eq!(
+            statements(&diff),
+            [
+                "DROP VIEW \"public\".\"v\";",
+                "DROP TABLE \"public\".\"removed\";",
+                "CREATE TABLE \"public\".\"added\" (\n    \"id\" integer\n);",
+                "CREATE VIEW \"public\".\"v\" AS\nSELECT 2 AS id;",
+            ]
+        );
+    }
+
+    #[test]
+    fn alters_tables_in_dependency_order() {
+        let mut price = column("price", "numeric");
+        price.default_value = Some("0".to_string());
+        price.nullable = false;
+        let mut total = column("total", "numeric");
+        total.is_generated = true;
+        total.default_value = Some("(price * 2)".to_string());
+        let source = table(
+            "orders",
+            vec![column("id", "bigint"), price, total, column("note", "text")],
+            vec![
+                constraint("orders_price_check", "CHECK", "CHECK ((price > 0))"),
+                constraint("orders_user_fkey", "FOREIGN KEY", "FOREIGN KEY (id) REFERENCES users(id)"),
+            ],
+        );
+
+        let mut total = column("total", "numeric");
+        total.is_generated = true;
+        total.default_value = Some("(price * 3)".to_string());
+        let target = table(
+            "orders",
+            vec![column("id", "integer"), column("price", "numeric"), total, column("legacy", "text")],
+            vec![
+                constraint("orders_price_check", "CHECK", "CHECK ((price >= 0))"),
+                constraint("orders_user_fkey", "FOREIGN KEY", "FOREIGN KEY (id) REFERENCES people(id)"),
+            ],
+        );
+
+        let diff = diff_schemas(&schema(vec![source], Vec::new()), &schema(vec![target], Vec::new()), "header");
+        let names: Vec<&str> = diff.tables[0].columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["id", "legacy", "note", "price", "total"]);
+        assert_eq!(
+            statements(&diff),
+            [
+                "ALTER TABLE \"public\".\"orders\" DROP CONSTRAINT \"orders_user_fkey\";",
+                "ALTER TABLE \"public\".\"orders\" DROP CONSTRAINT \"orders_price_check\";",
+                "ALTER TABLE \"public\".\"orders\"\n    \
+                 ALTER COLUMN \"id\" TYPE bigint USING \"id\"::bigint,\n    \
+                 DROP COLUMN \"legacy\",\n    \
+                 ADD COLUMN \"note\" text,\n    \
+                 ALTER COLUMN \"price\" SET DEFAULT 0,\n    \
+                 ALTER COLUMN \"price\" SET NOT NULL,\n    \
+                 DROP COLUMN \"total\",\n    \
+                 ADD COLUMN \"total\" numeric GENERATED ALWAYS AS ((price * 2)) STORED;",
+                "ALTER TABLE \"public\".\"orders\" ADD CONSTRAINT \"orders_price_check\" CHECK ((price > 0));",
+                "ALTER TABLE \"public\".\"orders\" ADD CONSTRAINT \"orders_user_fkey\" \
+                 FOREIGN KEY (id) REFERENCES users(id);",
+            ]
+        );
+    }
+
+    #[test]
+    fn adds_enum_labels_in_a_batch_of_their_own() {
+        let source = schema(
+            vec![table("t", vec![column("id", "integer"), column("m", "mood")], Vec::new())],
+            vec![mood(&["new", "sad", "meh", "happy", "ecstatic"])],
+        );
+        let target = schema(
+            vec![table("t", vec![column("id", "integer")], Vec::new())],
+            vec![mood(&["sad", "happy"])],
+        );
+        let diff = diff_schemas(&source, &target, "header");
+
+        assert_eq!(diff.enums.len(), 1);
+        assert_eq!(diff.enums[0].kind, DiffKind::Changed);
+        assert_eq!(diff.enums[0].source.as_deref(), Some("ENUM ('new', 'sad', 'meh', 'happy', 'ecstatic')"));
+        assert_eq!(
+            diff.migration,
+            format!(
+                "-- header\n-- Review before running\n\n{}\n\
+                 ALTER TYPE \"public\".\"mood\" ADD VALUE 'new' BEFORE 'sad';\n\
+                 ALTER TYPE \"public\".\"mood\" ADD VALUE 'meh' AFTER 'sad';\n\
+                 ALTER TYPE \"public\".\"mood\" ADD VALUE 'ecstatic' AFTER 'happy';\n\n\
+                 -- Then the rest of the migration\n\n\
+                 ALTER TABLE \"public\".\"t\"\n    ADD COLUMN \"m\" mood;\n",
+                ENUM_VALUES_NOTE
+            )
+        );
+
+        let source = schema(Vec::new(), vec![mood(&["a", "b"])]);
+        let diff = diff_schemas(&source, &schema(Vec::new(), vec![mood(&["a"])]), "h");
+        assert_eq!(
+            diff.migration,
+            format!(
+                "-- h\n-- Review before running\n\n{}\nALTER TYPE \"public\".\"mood\" ADD VALUE 'b' AFTER 'a';\n",
+                ENUM_VALUES_NOTE
+            )
+        );
+    }
+
+    #[test]
+    fn leaves_enums_that_lose_or_reorder_labels_to_the_user() {
+        // A label dropped, two swapped, and swapped with one added
+        let cases: [(&[&str], &[&str]); 3] = [
+            (&["a", "b"], &["a", "b", "c"]),
+            (&["a", "b"], &["b", "a"]),
+            (&["b", "a", "c"], &["a", "b"]),
+        ];
+        for (source, target) in cases {
+            let source = schema(Vec::new(), vec![mood(source)]);
+            let target = schema(Vec::new(), vec![mood(target)]);
+            let diff = diff_schemas(&source, &target, "header");
+            assert!(!diff.migration.contains("ADD VALUE"));
+            assert!(diff
+                .migration
+                .contains("-- enum \"public\".\"mood\" differs in ways ALTER TYPE can't express"));
+        }
+
+        let source = schema(Vec::new(), vec![mood(&["a"])]);
+        let diff = diff_schemas(&source, &schema(Vec::new(), Vec::new()), "header");
+        assert_eq!(statements(&diff), ["CREATE TYPE \"public\".\"mood\" AS ENUM ('a');"]);
+        let diff = diff_schemas(&schema(Vec::new(), Vec::new()), &source, "header");
+        assert_eq!(statements(&diff), ["DROP TYPE \"public\".\"mood\";"]);
+    }
+}
@@ -0,0 +1,223 @@
+//! The seam between the shared connection machinery and a database engine.
+//!
+//! Sessions, query cancellation, the row-streaming query runner and table listing are written
+//! once against `Driver`; each engine implements it on its sqlx connection type.
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Column, Connection, Database, Execute, Executor, Row};
+
+use super::tunnel::Tunnel;
+use super::{decode, script, PgConnection, TableInfo};
+
+// Cursor a Postgres query is read through when its rows are capped
+const CAPPED_CURSOR: &str = "pg_capped_rows";
+
+/// Database engine a saved connection talks to
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DriverKind {
+    #[default]
+    Postgres,
+    /// MySQL and MariaDB, which share the wire protocol
+    #[serde(alias = "mariadb")]
+    Mysql,
+}
+
+impl DriverKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            DriverKind::Postgres => "PostgreSQL",
+            DriverKind::Mysql => "MySQL",
+        }
+    }
+}
+
+/// A connection type the session manager and query runner can drive
+pub trait Driver: Connection + Sized + 'static {
+    const KIND: DriverKind;
+
+    /// One statement of plain SQL, ready to run
+    type Statement<'q>: Execute<'q, Self::Database> + 'q;
+
+    /// Plain SQL as the statements to send. Postgres runs each statement as an unnamed prepared
+    /// statement so its rows come back in the binary format, as those of bound queries do;
+    /// MySQL sends the text as it is.
+    fn statements(sql: &str) -> Vec<Self::Statement<'_>>;
+
+    /// A single statement of plain SQL, sent the same way as those of `statements`
+    fn statement(sql: &str) -> Self::Statement<'_>;
+
+    /// Start reading `query` through a cursor if it is a plain query, so that stopping at a row
+    /// cap stops the server as well. Returns the SQL that fetches the next `rows` rows, or `None`
+    /// when the query should run as it is, which is all drivers without cursors do.
+    fn declare_cursor<'e, 'q: 'e, E>(
+        &'e mut self,
+        query: &'e mut E,
+        rows: usize,
+        in_transaction: bool,
+    ) -> BoxFuture<'e, Result<Option<String>, sqlx::Error>>
+    where
+        E: Execute<'q, Self::Database> + 'q,
+    {
+        let _ = (query, rows, in_transaction);
+        Box::pin(async { Ok(None) })
+    }
+
+    /// Close the cursor opened by `declare_cursor`, ending the transaction it opened if any.
+    /// After a failed fetch the transaction is rolled back instead.
+    fn close_cursor(&mut self, in_transaction: bool, succeeded: bool) -> BoxFuture<'_, Result<(), sqlx::Error>> {
+        let _ = (in_transaction, succeeded);
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Open a connection for a saved connection, through its SSH tunnel if it has one
+    fn open<'a>(connection: &'a PgConnection, tunnel: Option<&'a Tunnel>) -> BoxFuture<'a, Result<Self, String>>;
+
+    /// Server-side id of this connection, used to cancel its queries, and the server version
+    fn server_info(&mut self) -> BoxFuture<'_, Result<(u64, String), sqlx::Error>>;
+
+    /// From a separate connection, stop the statement running on connection `backend_id`
+    fn cancel_backend(&mut self, backend_id: u64) -> BoxFuture<'_, Result<bool, sqlx::Error>>;
+
+    /// Whether `error` is the server reporting a statement stopped by `cancel_backend`
+    fn is_cancelled(error: &sqlx::Error) -> bool;
+
+    /// Tables and views outside the system schemas
+    fn list_tables(&mut self) -> BoxFuture<'_, Result<Vec<TableInfo>, sqlx::Error>>;
+
+    /// Rows written (or returned) by one statement of a query
+    fn rows_affected(done: &<Self::Database as Database>::QueryResult) -> u64;
+
+    /// Type name used to tag a result column, e.g. `INT4` or `BIGINT UNSIGNED`
+    fn type_name(column: &<Self::Database as Database>::Column) -> String;
+
+    /// Decode every column of a row into JSON
+    fn row_to_json(row: &<Self::Database as Database>::Row) -> Vec<Value>;
+}
+
+impl Driver for sqlx::PgConnection {
+    const KIND: DriverKind = DriverKind::Postgres;
+
+    type Statement<'q> = sqlx::query::Query<'q, sqlx::Postgres, sqlx::postgres::PgArguments>;
+
+    fn statements(sql: &str) -> Vec<Self::Statement<'_>> {
+        // The extended protocol takes one statement at a time
+        script::split_statements(sql, DriverKind::Postgres).into_iter().map(Self::statement).collect()
+    }
+
+    fn statement(sql: &str) -> Self::Statement<'_> {
+        sqlx::query(sql).persistent(false)
+    }
+
+    fn declare_cursor<'e, 'q: 'e, E>(
+        &'e mut self,
+        query: &'e mut E,
+        rows: usize,
+        in_transaction: bool,
+    ) -> BoxFuture<'e, Result<Option<String>, sqlx::Error>>
+    where
+        E: Execute<'q, sqlx::Postgres> + 'q,
+    {
+        Box::pin(async move {
+            // Only queries that just read can be declared as a cursor
+            if !script::is_plain_query(query.sql()) {
+                return Ok(None);
+            }
+
+            let declare = format!("DECLARE {} NO SCROLL CURSOR FOR {}", CAPPED_CURSOR, query.sql());
+            let arguments = query.take_arguments().map_err(sqlx::Error::Encode)?.unwrap_or_default();
+
+            // Cursors only live inside a transaction block
+            if !in_transaction {
+                self.execute("BEGIN").await?;
+            }
+            if let Err(e) = sqlx::query_with(&declare, arguments).persistent(false).execute(&mut *self).await {
+                if !in_transaction {
+                    let _ = self.execute("ROLLBACK").await;
+                }
+                return Err(e);
+            }
+
+            Ok(Some(format!("FETCH FORWARD {} FROM {}", rows, CAPPED_CURSOR)))
+        })
+    }
+
+    fn close_cursor(&mut self, in_transaction: bool, succeeded: bool) -> BoxFuture<'_, Result<(), sqlx::Error>> {
+        Box::pin(async move {
+            let statement = match (in_transaction, succeeded) {
+                (false, true) => "COMMIT".to_string(),
+                (false, false) => "ROLLBACK".to_string(),
+                (true, true) => format!("CLOSE {}", CAPPED_CURSOR),
+                // The error aborted the open transaction, cursor and all
+                (true, false) => return Ok(()),
+            };
+            self.execute(statement.as_str()).await.map(|_| ())
+        })
+    }
+
+    fn open<'a>(connection: &'a PgConnection, tunnel: Option<&'a Tunnel>) -> BoxFuture<'a, Result<Self, String>> {
+        Box::pin(super::connect(connection, tunnel))
+    }
+
+    fn server_info(&mut self) -> BoxFuture<'_, Result<(u64, String), sqlx::Error>> {
+        Box::pin(async move {
+            let (backend_pid, server_version): (i32, String) =
+                sqlx::query_as("SELECT pg_backend_pid(), current_setting('server_version')")
+                    .fetch_one(self)
+                    .await?;
+            Ok((backend_pid as u64, server_version))
+        })
+    }
+
+    fn cancel_backend(&mut self, backend_id: u64) -> BoxFuture<'_, Result<bool, sqlx::Error>> {
+        Box::pin(async move {
+            sqlx::query_scalar("SELECT pg_cancel_backend($1)")
+                .bind(backend_id as i32)
+                .fetch_one(self)
+                .await
+        })
+    }
+
+    fn is_cancelled(error: &sqlx::Error) -> bool {
+        // SQLSTATE query_canceled
+        matches!(error, sqlx::Error::Database(e) if e.code().as_deref() == Some("57014"))
+    }
+
+    fn list_tables(&mut self) -> BoxFuture<'_, Result<Vec<TableInfo>, sqlx::Error>> {
+        Box::pin(async move {
+            let rows = sqlx::query(
+                r#"
+                SELECT table_schema, table_name, table_type
+                FROM information_schema.tables
+                WHERE table_schema NOT IN ('pg_catalog', 'information_schema')
+                ORDER BY table_schema, table_name
+                "#,
+            )
+            .fetch_all(self)
+            .await?;
+
+            Ok(rows
+                .iter()
+                .map(|row| TableInfo {
+                    schema: row.get("table_schema"),
+                    name: row.get("table_name"),
+                    table_type: row.get("table_type"),
+                })
+                .collect())
+        })
+    }
+
+    fn rows_affected(done: &sqlx::postgres::PgQueryResult) -> u64 {
+        done.rows_affected()
+    }
+
+    fn type_name(column: &sqlx::postgres::PgColumn) -> String {
+        decode::type_name(column.type_info())
+    }
+
+    fn row_to_json(row: &sqlx::postgres::PgRow) -> Vec<Value> {
+        decode::row_to_json(row)
+    }
+}
@@ -0,0 +1,463 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::postgres::PgArguments;
+use sqlx::{Arguments, Row};
+use std::time::Instant;
+use tauri::State;
+
+use super::snippets::{parameter_text, Parameters};
+use super::session::Session;
+use super::{
+    cast_type, quote_identifier, read_only, script, session_error, PgSessions, QueryHistory, SavedConnections,
+    TableInfo,
+};
+
+/// One edit from the result grid. Rows are identified by their primary key values.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum RowChange {
+    Insert { values: Parameters },
+    Update { key: Parameters, values: Parameters },
+    Delete { key: Parameters },
+}
+
+/// A generated statement with its bound values, in `$n` order
+#[derive(Debug, Serialize)]
+pub struct DmlStatement {
+    pub sql: String,
+    pub parameters: Vec<Value>,
+    /// The same statement with `:pN` placeholders, as history keeps it so it can be rerun
+    #[serde(skip)]
+    named_sql: String,
+    /// Column type of each parameter, to bind arrays as array literals
+    #[serde(skip)]
+    types: Vec<String>,
+    /// Keyed by the primary key, so it has to affect exactly one row
+    #[serde(skip)]
+    single_row: bool,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct TableChangesSummary {
+    pub inserted: u64,
+    pub updated: u64,
+    pub deleted: u64,
+}
+
+impl DmlStatement {
+    /// Values by the names of their `:pN` placeholders
+    fn named_parameters(&self) -> Parameters {
+        (1..)
+            .zip(&self.parameters)
+            .map(|(n, value)| (format!("p{}", n), value.clone()))
+            .collect()
+    }
+}
+
+/// Why grid edits could not be applied
+enum EditError {
+    /// The edits don't fit the table, e.g. an unknown column or a row that is no longer there
+    Invalid(String),
+    Db(sqlx::Error),
+}
+
+impl From<sqlx::Error> for EditError {
+    fn from(error: sqlx::Error) -> Self {
+        EditError::Db(error)
+    }
+}
+
+/// The message for an edit error; database errors drop the session if its connection broke
+async fn edit_error(sessions: &PgSessions, session: &Session<sqlx::PgConnection>, error: EditError) -> String {
+    match error {
+        EditError::Invalid(message) => message,
+        EditError::Db(e) => session_error(sessions, session, e).await,
+    }
+}
+
+struct EditableColumn {
+    name: String,
+    /// Type without modifiers, so a too-long value fails on assignment instead of being cut off by the cast
+    base_type: String,
+    generated: bool,
+    primary_key: bool,
+}
+
+const COLUMNS_QUERY: &str = r#"
+SELECT a.attname::text AS name,
+       format_type(a.atttypid, NULL) AS base_type,
+       a.attgenerated <> '' AS generated,
+       coalesce(a.attnum = ANY (i.indkey), false) AS primary_key
+FROM pg_attribute a
+LEFT JOIN pg_index i ON i.indrelid = a.attrelid AND i.indisprimary
+WHERE a.attrelid = to_regclass($1) AND a.attnum > 0 AND NOT a.attisdropped
+ORDER BY a.attnum
+"#;
+
+/// Builds parameterized statements for one table, binding every value as text cast to its column type
+struct DmlBuilder<'a> {
+    table: String,
+    columns: &'a [EditableColumn],
+}
+
+impl DmlBuilder<'_> {
+    fn column(&self, name: &str) -> Result<&EditableColumn, String> {
+        self.columns
+            .iter()
+            .find(|c| c.name == name)
+            .ok_or_else(|| format!("Table {} has no column \"{}\"", self.table, name))
+    }
+
+    fn placeholder(parameters: &mut Vec<(Value, String)>, column: &EditableColumn, value: &Value) -> String {
+        let sql_type = cast_type(&column.base_type);
+        let placeholder = format!("CAST(:p{}::text AS {})", parameters.len() + 1, sql_type);
+        parameters.push((value.clone(), sql_type));
+        placeholder
+    }
+
+    /// `"a" = :p1 AND "b" = :p2` over exactly the primary key columns
+    fn key_condition(&self, key: &Parameters, parameters: &mut Vec<(Value, String)>) -> Result<String, String> {
+        let key_columns: Vec<&EditableColumn> = self.columns.iter().filter(|c| c.primary_key).collect();
+        let matches_key = key.len() == key_columns.len() && key_columns.iter().all(|c| key.contains_key(&c.name));
+        if !matches_key {
+            let names: Vec<&str> = key_columns.iter().map(|c| c.name.as_str()).collect();
+            return Err(format!("Rows of {} are identified by ({})", self.table, names.join(", ")));
+        }
+
+        let conditions: Vec<String> = key_columns
+            .iter()
+            .map(|column| {
+                let value = &key[&column.name];
+                format!("{} = {}", quote_identifier(&column.name), Self::placeholder(parameters, column, value))
+            })
+            .collect();
+        Ok(conditions.join(" AND "))
+    }
+
+    fn writable(&self, name: &str) -> Result<&EditableColumn, String> {
+        let column = self.column(name)?;
+        if column.generated {
+            return Err(format!("Column \"{}\" is generated and can't be written", name));
+        }
+        Ok(column)
+    }
+
+    fn statement(&self, change: &RowChange) -> Result<DmlStatement, String> {
+        let mut parameters = Vec::new();
+        let (named_sql, single_row) = match change {
+            RowChange::Insert { values } if values.is_empty() => (format!("INSERT INTO {} DEFAULT VALUES", self.table), false),
+            RowChange::Insert { values } => {
+                let mut names = Vec::new();
+                let mut placeholders = Vec::new();
+                for (name, value) in values {
+                    let column = self.writable(name)?;
+                    names.push(quote_identifier(name));
+                    placeholders.push(Self::placeholder(&mut parameters, column, value));
+                }
+                let sql = format!(
+                    "INSERT INTO {} ({}) VALUES ({})",
+                    self.table,
+                    names.join(", "),
+                    placeholders.join(", ")
+                );
+                (sql, false)
+            }
+            RowChange::Update { key, values } => {
+                if values.is_empty() {
+                    return Err("An update needs at least one changed column".to_string());
+                }
+                let mut assignments = Vec::new();
+                for (name, value) in values {
+                    let column = self.writable(name)?;
+                    assignments.push(format!(
+                        "{} = {}",
+                        quote_identifier(name),
+                        Self::placeholder(&mut parameters, column, value)
+                    ));
+                }
+                let condition = self.key_condition(key, &mut parameters)?;
+                (format!("UPDATE {} SET {} WHERE {}", self.table, assignments.join(", "), condition), true)
+            }
+            RowChange::Delete { key } => {
+                let condition = self.key_condition(key, &mut parameters)?;
+                (format!("DELETE FROM {} WHERE {}", self.table, condition), true)
+            }
+        };
+
+        let (parameters, types) = parameters.into_iter().unzip();
+        Ok(DmlStatement {
+            sql: script::replace_named_parameters(&named_sql, |n| format!("${}", n)).0,
+            parameters,
+            named_sql,
+            types,
+            single_row,
+        })
+    }
+}
+
+/// Look up the table's columns and turn the changes into statements, one per change in order
+async fn build_statements(
+    conn: &mut sqlx::PgConnection,
+    table: &TableInfo,
+    changes: &[RowChange],
+) -> Result<Vec<DmlStatement>, EditError> {
+    let qualified = format!("{}.{}", quote_identifier(&table.schema), quote_identifier(&table.name));
+    let columns: Vec<EditableColumn> = sqlx::query(COLUMNS_QUERY)
+        .bind(&qualified)
+        .fetch_all(&mut *conn)
+        .await?
+        .iter()
+        .map(|row| EditableColumn {
+            name: row.get("name"),
+            base_type: row.get("base_type"),
+            generated: row.get("generated"),
+            primary_key: row.get("primary_key"),
+        })
+        .collect();
+
+    if columns.is_empty() {
+        return Err(EditError::Invalid(format!("Table {} not found", qualified)));
+    }
+    if !columns.iter().any(|c| c.primary_key) {
+        return Err(EditError::Invalid(format!(
+            "Table {} has no primary key, so its rows can't be edited safely",
+            qualified
+        )));
+    }
+
+    let builder = DmlBuilder {
+        table: qualified,
+        columns: &columns,
+    };
+    changes
+        .iter()
+        .map(|change| builder.statement(change).map_err(EditError::Invalid))
+        .collect()
+}
+
+/// The statements `pg_apply_table_changes` would run, with their bound values
+#[tauri::command]
+pub async fn pg_preview_table_changes(
+    sessions: State<'_, PgSessions>,
+    connections: State<'_, SavedConnections>,
+    connection_id: String,
+    table: TableInfo,
+    changes: Vec<RowChange>,
+) -> Result<Vec<DmlStatement>, String> {
+    let connection = connections.get(&connection_id).await?;
+    let session = sessions.acquire(&connection).await?;
+    let mut conn = session.conn.lock().await;
+
+    match build_statements(&mut conn, &table, &changes).await {
+        Ok(statements) => Ok(statements),
+        Err(e) => Err(edit_error(&sessions, &session, e).await),
+    }
+}
+
+/// Apply grid edits to a table with a primary key in one transaction (a savepoint inside an
+/// open transaction). Nothing is applied if any statement fails or an update or delete
+/// no longer finds its row. Once the transaction ends, the statements that took effect, or
+/// the one that failed, are added to the history.
+#[tauri::command]
+pub async fn pg_apply_table_changes(
+    sessions: State<'_, PgSessions>,
+    connections: State<'_, SavedConnections>,
+    history: State<'_, QueryHistory>,
+    connection_id: String,
+    table: TableInfo,
+    changes: Vec<RowChange>,
+) -> Result<TableChangesSummary, String> {
+    let connection = connections.get(&connection_id).await?;
+    let session = sessions.acquire(&connection).await?;
+    let mut conn = session.conn.lock().await;
+
+    let statements = match build_statements(&mut conn, &table, &changes).await {
+        Ok(statements) => statements,
+        Err(e) => return Err(edit_error(&sessions, &session, e).await),
+    };
+    for statement in &statements {
+        read_only::check(&connection, &statement.sql)?;
+    }
+
+    let (begin, commit, rollback) = if session.in_transaction() {
+        ("SAVEPOINT pg_edit", "RELEASE SAVEPOINT pg_edit", "ROLLBACK TO SAVEPOINT pg_edit")
+    } else {
+        ("BEGIN", "COMMIT", "ROLLBACK")
+    };
+
+    if let Err(e) = sqlx::query(begin).execute(&mut *conn).await {
+        return Err(session_error(&sessions, &session, e).await);
+    }
+
+    // When each statement started and the rows it affected, in order; the last one may have failed
+    let mut runs = Vec::new();
+    let outcome = async {
+        let mut summary = TableChangesSummary::default();
+        for (statement, change) in statements.iter().zip(&changes) {
+            runs.push((Instant::now(), 0));
+            let mut arguments = PgArguments::default();
+            for (value, sql_type) in statement.parameters.iter().zip(&statement.types) {
+                arguments
+                    .add(parameter_text(value, sql_type))
+                    .map_err(|e| EditError::Invalid(e.to_string()))?;
+            }
+
+            let affected = sqlx::query_with(&statement.sql, arguments)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+            if let Some((_, rows)) = runs.last_mut() {
+                *rows = affected;
+            }
+            if statement.single_row && affected != 1 {
+                return Err(EditError::Invalid(format!(
+                    "{} matched {} rows instead of 1; the row may have been changed or deleted meanwhile",
+                    statement.sql, affected
+                )));
+            }
+
+            match change {
+                RowChange::Insert { .. } => summary.inserted += affected,
+                RowChange::Update { .. } => summary.updated += affected,
+                RowChange::Delete { .. } => summary.deleted += affected,
+            }
+        }
+        Ok(summary)
+    }
+    .await;
+
+    let summary = match outcome {
+        Ok(summary) => summary,
+        Err(e) => {
+            let _ = sqlx::query(rollback).execute(&mut *conn).await;
+            let message = edit_error(&sessions, &session, e).await;
+            // The statements before it were rolled back, so only the failing one is recorded
+            if let Some((started, _)) = runs.last() {
+                let statement = &statements[runs.len() - 1];
+                let parameters = statement.named_parameters();
+                history
+                    .record_outcome(&connection.id, &statement.named_sql, Some(&parameters), *started, Err(&message))
+                    .await;
+            }
+            return Err(message);
+        }
+    };
+
+    if let Err(e) = sqlx::query(commit).execute(&mut *conn).await {
+        let _ = sqlx::query(rollback).execute(&mut *conn).await;
+        return Err(session_error(&sessions, &session, e).await);
+    }
+
+    for (statement, (started, rows)) in statements.iter().zip(runs) {
+        let parameters = statement.named_parameters();
+        history
+            .record_outcome(&connection.id, &statement.named_sql, Some(&parameters), started, Ok((0, rows)))
+            .await;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns() -> Vec<EditableColumn> {
+        let column = |name: &str, base_type: &str, generated, primary_key| EditableColumn {
+            name: name.to_string(),
+            base_type: base_type.to_string(),
+            generated,
+            primary_key,
+        };
+        vec![
+            column("id", "integer", false, true),
+            column("name", "text", false, false),
+            column("total", "numeric", true, false),
+            column("code", "character", false, false),
+            column("flags", "bit", false, false),
+            column("codes", "character[]", false, false),
+        ]
+    }
+
+    fn change(json: Value) -> RowChange {
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn statement(change: RowChange) -> Result<DmlStatement, String> {
+        let columns = columns();
+        let builder = DmlBuilder {
+            table: r#""public"."t""#.to_string(),
+            columns: &columns,
+        };
+        builder.statement(&change)
+    }
+
+    #[test]
+    fn inserts_bind_every_value_cast_to_its_column() {
+        let insert = statement(change(serde_json::json!({ "type": "insert", "values": { "name": "x" } }))).unwrap();
+        assert_eq!(insert.sql, r#"INSERT INTO "public"."t" ("name") VALUES (CAST($1::text AS text))"#);
+        assert_eq!(insert.parameters, vec![Value::from("x")]);
+        assert!(!insert.single_row);
+
+        let defaults = statement(change(serde_json::json!({ "type": "insert", "values": {} }))).unwrap();
+        assert_eq!(defaults.sql, r#"INSERT INTO "public"."t" DEFAULT VALUES"#);
+    }
+
+    #[test]
+    fn updates_and_deletes_match_one_row_by_primary_key() {
+        let update = statement(change(serde_json::json!({
+            "type": "update", "key": { "id": 7 }, "values": { "name": null }
+        })))
+        .unwrap();
+        assert_eq!(
+            update.sql,
+            r#"UPDATE "public"."t" SET "name" = CAST($1::text AS text) WHERE "id" = CAST($2::text AS integer)"#
+        );
+        assert_eq!(update.parameters, vec![Value::Null, Value::from(7)]);
+        assert!(update.single_row);
+        assert_eq!(
+            update.named_sql,
+            r#"UPDATE "public"."t" SET "name" = CAST(:p1::text AS text) WHERE "id" = CAST(:p2::text AS integer)"#
+        );
+        assert_eq!(
+            Value::Object(update.named_parameters()),
+            serde_json::json!({ "p1": null, "p2": 7 })
+        );
+
+        let delete = statement(change(serde_json::json!({ "type": "delete", "key": { "id": 7 } }))).unwrap();
+        assert_eq!(delete.sql, r#"DELETE FROM "public"."t" WHERE "id" = CAST($1::text AS integer)"#);
+    }
+
+    #[test]
+    fn keeps_the_length_of_char_and_bit_values() {
+        // `format_type` names char(3) and bit(3) without their length; cast to those names they'd mean length one
+        let insert = statement(change(serde_json::json!({
+            "type": "insert", "values": { "code": "ABC", "codes": ["ABC"], "flags": "101" }
+        })))
+        .unwrap();
+        assert_eq!(
+            insert.sql,
+            concat!(
+                r#"INSERT INTO "public"."t" ("code", "codes", "flags") "#,
+                r#"VALUES (CAST($1::text AS bpchar), CAST($2::text AS bpchar[]), CAST($3::text AS varbit))"#
+            )
+        );
+        assert_eq!(insert.types, ["bpchar", "bpchar[]", "varbit"]);
+    }
+
+    #[test]
+    fn rejects_edits_that_do_not_fit_the_table() {
+        let wrong_key = statement(change(serde_json::json!({ "type": "delete", "key": { "name": "x" } })));
+        assert_eq!(wrong_key.unwrap_err(), r#"Rows of "public"."t" are identified by (id)"#);
+
+        let generated = statement(change(serde_json::json!({
+            "type": "update", "key": { "id": 1 }, "values": { "total": 3 }
+        })));
+        assert_eq!(generated.unwrap_err(), "Column \"total\" is generated and can't be written");
+
+        let unknown = statement(change(serde_json::json!({ "type": "insert", "values": { "nope": 1 } })));
+        assert_eq!(unknown.unwrap_err(), r#"Table "public"."t" has no column "nope""#);
+
+        let empty = statement(change(serde_json::json!({ "type": "update", "key": { "id": 1 }, "values": {} })));
+        assert!(empty.is_err());
+    }
+}
@@ -0,0 +1,494 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::time::{Duration, Instant};
+use tauri::State;
+
+use super::{
+    decode, query_error, read_only, script, session_error, PgConnection, PgSessions, QueryHistory, SavedConnections,
+};
+
+// An estimate this many times off from the actual row count is reported as a hot spot
+const ROW_ESTIMATE_MISS_FACTOR: f64 = 10.0;
+
+#[derive(Debug, Deserialize, Default)]
+pub struct ExplainOptions {
+    /// Run the query and report actual rows and timings. The query runs in a transaction
+    /// (or savepoint) that is always rolled back, so writes are never kept.
+    #[serde(default)]
+    pub analyze: bool,
+    /// Report shared/local/temp buffer usage per node
+    #[serde(default)]
+    pub buffers: bool,
+    /// Id used to cancel the query with `pg_cancel_query`
+    pub query_id: Option<String>,
+    /// Cancel the query if it runs longer than this
+    pub statement_timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct Buffers {
+    pub shared_hit: u64,
+    pub shared_read: u64,
+    pub shared_dirtied: u64,
+    pub shared_written: u64,
+    pub local_hit: u64,
+    pub local_read: u64,
+    pub local_dirtied: u64,
+    pub local_written: u64,
+    pub temp_read: u64,
+    pub temp_written: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlanNode {
+    /// Position of the node in a depth-first walk of the plan, starting at 0 for the root
+    pub id: usize,
+    /// e.g. `Seq Scan`, `Hash Join` or `Aggregate`
+    pub node_type: String,
+    pub relation: Option<String>,
+    pub alias: Option<String>,
+    pub index_name: Option<String>,
+    pub startup_cost: f64,
+    pub total_cost: f64,
+    /// Cost of this node alone, without its children
+    pub self_cost: f64,
+    pub plan_rows: f64,
+    pub plan_width: u64,
+    // Actual figures are only present with ANALYZE; rows and times are per loop
+    pub actual_startup_time_ms: Option<f64>,
+    pub actual_total_time_ms: Option<f64>,
+    pub actual_rows: Option<f64>,
+    pub actual_loops: Option<f64>,
+    /// Time spent in this node alone across all loops, without its children
+    pub self_time_ms: Option<f64>,
+    pub buffers: Option<Buffers>,
+    /// Remaining node properties as reported by Postgres, e.g. `Filter` or `Hash Cond`
+    pub details: Map<String, Value>,
+    pub children: Vec<PlanNode>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HotSpot {
+    pub node_id: usize,
+    /// `slowest_node`, `most_expensive_node` or `row_estimate_miss`
+    pub kind: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExplainResult {
+    pub plan: PlanNode,
+    pub planning_time_ms: Option<f64>,
+    pub execution_time_ms: Option<f64>,
+    pub hot_spots: Vec<HotSpot>,
+    /// The plan exactly as returned by `EXPLAIN (FORMAT JSON)`
+    pub raw: Value,
+}
+
+/// Build a plan node (and its subtree) from an EXPLAIN JSON "Plan" object
+fn parse_node(mut node: Map<String, Value>, next_id: &mut usize) -> PlanNode {
+    let id = *next_id;
+    *next_id += 1;
+
+    let mut take_f64 = |key: &str| node.remove(key).and_then(|v| v.as_f64());
+    let startup_cost = take_f64("Startup Cost").unwrap_or(0.0);
+    let total_cost = take_f64("Total Cost").unwrap_or(0.0);
+    let plan_rows = take_f64("Plan Rows").unwrap_or(0.0);
+    let plan_width = take_f64("Plan Width").unwrap_or(0.0) as u64;
+    let actual_startup_time_ms = take_f64("Actual Startup Time");
+    let actual_total_time_ms = take_f64("Actual Total Time");
+    let actual_rows = take_f64("Actual Rows");
+    let actual_loops = take_f64("Actual Loops");
+
+    let buffers = node.contains_key("Shared Hit Blocks").then(|| {
+        let mut take = |key: &str| node.remove(key).and_then(|v| v.as_u64()).unwrap_or(0);
+        Buffers {
+            shared_hit: take("Shared Hit Blocks"),
+            shared_read: take("Shared Read Blocks"),
+            shared_dirtied: take("Shared Dirtied Blocks"),
+            shared_written: take("Shared Written Blocks"),
+            local_hit: take("Local Hit Blocks"),
+            local_read: take("Local Read Blocks"),
+            local_dirtied: take("Local Dirtied Blocks"),
+            local_written: take("Local Written Blocks"),
+            temp_read: take("Temp Read Blocks"),
+            temp_written: take("Temp Written Blocks"),
+        }
+    });
+
+    let mut take_string = |key: &str| match node.remove(key) {
+        Some(Value::String(s)) => Some(s),
+        _ => None,
+    };
+    let node_type = take_string("Node Type").unwrap_or_default();
+    let relation = take_string("Relation Name").map(|name| match take_string("Schema") {
+        Some(schema) => format!("{}.{}", schema, name),
+        None => name,
+    });
+    let alias = take_string("Alias");
+    let index_name = take_string("Index Name");
+
+    let children: Vec<PlanNode> = match node.remove("Plans") {
+        Some(Value::Array(plans)) => plans
+            .into_iter()
+            .filter_map(|plan| match plan {
+                Value::Object(child) => Some(parse_node(child, next_id)),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let self_cost = (total_cost - children.iter().map(|c| c.total_cost).sum::<f64>()).max(0.0);
+    let self_time_ms = actual_total_time_ms.map(|total| {
+        let loops = actual_loops.unwrap_or(1.0);
+        let children_time: f64 = children
+            .iter()
+            .map(|c| c.actual_total_time_ms.unwrap_or(0.0) * c.actual_loops.unwrap_or(1.0))
+            .sum();
+        (total * loops - children_time).max(0.0)
+    });
+
+    PlanNode {
+        id,
+        node_type,
+        relation,
+        alias,
+        index_name,
+        startup_cost,
+        total_cost,
+        self_cost,
+        plan_rows,
+        plan_width,
+        actual_startup_time_ms,
+        actual_total_time_ms,
+        actual_rows,
+        actual_loops,
+        self_time_ms,
+        buffers,
+        details: node,
+        children,
+    }
+}
+
+fn walk<'a>(node: &'a PlanNode, nodes: &mut Vec<&'a PlanNode>) {
+    nodes.push(node);
+    for child in &node.children {
+        walk(child, nodes);
+    }
+}
+
+/// Describe a node for hot-spot messages, e.g. `Seq Scan on public.users`
+fn label(node: &PlanNode) -> String {
+    match (&node.relation, &node.index_name) {
+        (Some(relation), _) => format!("{} on {}", node.node_type, relation),
+        (None, Some(index)) => format!("{} using {}", node.node_type, index),
+        _ => node.node_type.clone(),
+    }
+}
+
+fn find_hot_spots(plan: &PlanNode, execution_time_ms: Option<f64>) -> Vec<HotSpot> {
+    let mut nodes = Vec::new();
+    walk(plan, &mut nodes);
+    let mut hot_spots = Vec::new();
+
+    let slowest = nodes
+        .iter()
+        .filter_map(|n| n.self_time_ms.map(|t| (n, t)))
+        .max_by(|a, b| a.1.total_cmp(&b.1));
+    if let Some((node, time)) = slowest {
+        let share = execution_time_ms
+            .filter(|total| *total > 0.0)
+            .map(|total| format!(" ({:.0}% of execution time)", time / total * 100.0))
+            .unwrap_or_default();
+        hot_spots.push(HotSpot {
+            node_id: node.id,
+            kind: "slowest_node".to_string(),
+            message: format!("{} took {:.3} ms{}", label(node), time, share),
+        });
+    }
+
+    let most_expensive = nodes.iter().max_by(|a, b| a.self_cost.total_cmp(&b.self_cost));
+    if let Some(node) = most_expensive {
+        let share = if plan.total_cost > 0.0 {
+            format!(" ({:.0}% of total cost)", node.self_cost / plan.total_cost * 100.0)
+        } else {
+            String::new()
+        };
+        hot_spots.push(HotSpot {
+            node_id: node.id,
+            kind: "most_expensive_node".to_string(),
+            message: format!("{} has a cost of {:.2}{}", label(node), node.self_cost, share),
+        });
+    }
+
+    for node in &nodes {
+        // Nodes that never ran have no meaningful actual row count
+        let Some(actual) = node.actual_rows.filter(|_| node.actual_loops.unwrap_or(0.0) > 0.0) else {
+            continue;
+        };
+        let (estimated, actual_rows) = (node.plan_rows.max(1.0), actual.max(1.0));
+        let factor = (estimated / actual_rows).max(actual_rows / estimated);
+        if factor >= ROW_ESTIMATE_MISS_FACTOR {
+            let direction = if actual_rows > estimated { "under" } else { "over" };
+            hot_spots.push(HotSpot {
+                node_id: node.id,
+                kind: "row_estimate_miss".to_string(),
+                message: format!(
+                    "{} estimated {:.0} rows but returned {:.0} ({:.0}x {}estimate)",
+                    label(node),
+                    node.plan_rows,
+                    actual,
+                    factor,
+                    direction
+                ),
+            });
+        }
+    }
+
+    hot_spots
+}
+
+/// Run `EXPLAIN (FORMAT JSON)` on a single statement and return the parsed plan tree with hot spots
+#[tauri::command]
+pub async fn pg_explain(
+    sessions: State<'_, PgSessions>,
+    connections: State<'_, SavedConnections>,
+    history: State<'_, QueryHistory>,
+    connection_id: String,
+    query: String,
+    options: Option<ExplainOptions>,
+) -> Result<ExplainResult, String> {
+    let connection = connections.get(&connection_id).await?;
+    let options = options.unwrap_or_default();
+
+    let statement = match script::split_statements(&query, connection.driver).as_slice() {
+        [statement] => *statement,
+        [] => return Err("Nothing to explain".to_string()),
+        _ => return Err("EXPLAIN takes a single statement".to_string()),
+    };
+
+    let mut flags = vec!["FORMAT JSON"];
+    if options.analyze {
+        flags.push("ANALYZE");
+    }
+    if options.buffers {
+        flags.push("BUFFERS");
+    }
+    let sql = format!("EXPLAIN ({}) {}", flags.join(", "), statement);
+
+    let started = Instant::now();
+    let result = explain(&sessions, &connection, &sql, options).await;
+    // The plan comes back as a single row
+    let outcome = result.as_ref().map(|_| (1, 0)).map_err(String::as_str);
+    history.record_outcome(&connection.id, &sql, None, started, outcome).await;
+    result
+}
+
+async fn explain(
+    sessions: &PgSessions,
+    connection: &PgConnection,
+    sql: &str,
+    options: ExplainOptions,
+) -> Result<ExplainResult, String> {
+    read_only::check(connection, sql)?;
+
+    let session = sessions.acquire(connection).await?;
+    let mut conn = session.conn.lock().await;
+    let running = sessions.track_query(
+        &session,
+        options.query_id,
+        options.statement_timeout_ms.map(Duration::from_millis),
+    );
+
+    // ANALYZE executes the statement; make sure whatever it writes is undone
+    let rollback = options.analyze.then(|| {
+        if session.in_transaction() {
+            ("SAVEPOINT explain_analyze", "ROLLBACK TO SAVEPOINT explain_analyze")
+        } else {
+            ("BEGIN", "ROLLBACK")
+        }
+    });
+
+    if let Some((begin, _)) = rollback {
+        if let Err(e) = sqlx::query(begin).execute(&mut *conn).await {
+            return Err(session_error(sessions, &session, e).await);
+        }
+    }
+
+    let outcome = sqlx::query(sql).fetch_one(&mut *conn).await;
+
+    if let Some((_, end)) = rollback {
+        if let Err(e) = sqlx::query(end).execute(&mut *conn).await {
+            return Err(session_error(sessions, &session, e).await);
+        }
+    }
+
+    let row = match outcome {
+        Ok(row) => row,
+        Err(e) => return Err(query_error(sessions, &session, &running, e).await),
+    };
+
+    let raw = decode::row_to_json(&row).into_iter().next().unwrap_or(Value::Null);
+
+    let Some(Value::Object(explained)) = raw.get(0).cloned() else {
+        return Err("Unexpected EXPLAIN output".to_string());
+    };
+    let Some(Value::Object(root)) = explained.get("Plan").cloned() else {
+        return Err("Unexpected EXPLAIN output".to_string());
+    };
+
+    let plan = parse_node(root, &mut 0);
+    let planning_time_ms = explained.get("Planning Time").and_then(Value::as_f64);
+    let execution_time_ms = explained.get("Execution Time").and_then(Value::as_f64);
+    let hot_spots = find_hot_spots(&plan, execution_time_ms);
+
+    Ok(ExplainResult {
+        plan,
+        planning_time_ms,
+        execution_time_ms,
+        hot_spots,
+        raw,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(json: Value) -> PlanNode {
+        let Value::Object(root) = json else { panic!("not an object") };
+        parse_node(root, &mut 0)
+    }
+
+    /// `EXPLAIN (FORMAT JSON) SELECT * FROM orders o JOIN users u ON u.id = o.user_id`
+    fn estimated() -> PlanNode {
+        plan(serde_json::json!({
+            "Node Type": "Hash Join", "Join Type": "Inner", "Startup Cost": 13.75, "Total Cost": 70.0,
+            "Plan Rows": 2000, "Plan Width": 72, "Hash Cond": "(o.user_id = u.id)",
+            "Plans": [
+                {
+                    "Node Type": "Seq Scan", "Parent Relationship": "Outer", "Relation Name": "orders",
+                    "Schema": "public", "Alias": "o", "Startup Cost": 0.0, "Total Cost": 30.0,
+                    "Plan Rows": 2000, "Plan Width": 40
+                },
+                {
+                    "Node Type": "Hash", "Parent Relationship": "Inner", "Startup Cost": 12.5, "Total Cost": 12.5,
+                    "Plan Rows": 100, "Plan Width": 32,
+                    "Plans": [{
+                        "Node Type": "Seq Scan", "Parent Relationship": "Outer", "Relation Name": "users",
+                        "Schema": "public", "Alias": "u", "Startup Cost": 0.0, "Total Cost": 12.5,
+                        "Plan Rows": 100, "Plan Width": 32
+                    }]
+                }
+            ]
+        }))
+    }
+
+    /// `EXPLAIN (FORMAT JSON, ANALYZE, BUFFERS)` of a nested loop whose inner index scan runs once per user,
+    /// with a subplan that never ran
+    fn analyzed() -> PlanNode {
+        plan(serde_json::json!({
+            "Node Type": "Nested Loop", "Join Type": "Inner", "Startup Cost": 0.29, "Total Cost": 850.0,
+            "Plan Rows": 10, "Plan Width": 16, "Actual Startup Time": 0.05, "Actual Total Time": 12.5,
+            "Actual Rows": 1000, "Actual Loops": 1,
+            "Plans": [
+                {
+                    "Node Type": "Seq Scan", "Parent Relationship": "Outer", "Relation Name": "users",
+                    "Schema": "public", "Alias": "u", "Startup Cost": 0.0, "Total Cost": 25.0, "Plan Rows": 100,
+                    "Plan Width": 8, "Actual Startup Time": 0.01, "Actual Total Time": 0.5, "Actual Rows": 100,
+                    "Actual Loops": 1, "Filter": "active", "Rows Removed by Filter": 5
+                },
+                {
+                    "Node Type": "Index Scan", "Parent Relationship": "Inner", "Index Name": "orders_user_id_idx",
+                    "Relation Name": "orders", "Schema": "public", "Alias": "o", "Startup Cost": 0.29,
+                    "Total Cost": 8.0, "Plan Rows": 1, "Plan Width": 8, "Actual Startup Time": 0.02,
+                    "Actual Total Time": 0.1, "Actual Rows": 10, "Actual Loops": 100,
+                    "Index Cond": "(user_id = u.id)", "Shared Hit Blocks": 300, "Shared Read Blocks": 12,
+                    "Shared Dirtied Blocks": 0, "Shared Written Blocks": 0, "Local Hit Blocks": 0,
+                    "Local Read Blocks": 0, "Local Dirtied Blocks": 0, "Local Written Blocks": 0,
+                    "Temp Read Blocks": 0, "Temp Written Blocks": 0
+                },
+                {
+                    "Node Type": "Result", "Parent Relationship": "SubPlan", "Subplan Name": "SubPlan 1",
+                    "Startup Cost": 0.0, "Total Cost": 2.0, "Plan Rows": 500, "Plan Width": 4,
+                    "Actual Startup Time": 0.0, "Actual Total Time": 0.0, "Actual Rows": 0, "Actual Loops": 0
+                }
+            ]
+        }))
+    }
+
+    fn ids(node: &PlanNode) -> Vec<(usize, String)> {
+        let mut nodes = Vec::new();
+        walk(node, &mut nodes);
+        nodes.iter().map(|n| (n.id, label(n))).collect()
+    }
+
+    #[test]
+    fn numbers_nodes_depth_first() {
+        let plan = estimated();
+        assert_eq!(
+            ids(&plan),
+            vec![
+                (0, "Hash Join".to_string()),
+                (1, "Seq Scan on public.orders".to_string()),
+                (2, "Hash".to_string()),
+                (3, "Seq Scan on public.users".to_string()),
+            ]
+        );
+
+        assert_eq!(plan.children[0].alias.as_deref(), Some("o"));
+        assert_eq!(plan.plan_width, 72);
+        assert_eq!(plan.self_cost, 27.5);
+        assert_eq!(plan.children[1].self_cost, 0.0);
+        assert_eq!(plan.actual_total_time_ms, None);
+        assert_eq!(plan.self_time_ms, None);
+        assert!(plan.buffers.is_none());
+        // Properties without a field of their own are kept as details
+        assert_eq!(plan.details.get("Hash Cond"), Some(&serde_json::json!("(o.user_id = u.id)")));
+        assert!(!plan.details.contains_key("Plans") && !plan.details.contains_key("Node Type"));
+    }
+
+    #[test]
+    fn adjusts_times_for_loops() {
+        let plan = analyzed();
+        let (scan, index_scan, subplan) = (&plan.children[0], &plan.children[1], &plan.children[2]);
+
+        assert_eq!(index_scan.index_name.as_deref(), Some("orders_user_id_idx"));
+        assert_eq!(label(index_scan), "Index Scan on public.orders");
+        // The inner side ran 100 times at 0.1 ms each; the join's own time is what its children don't explain
+        assert!((index_scan.self_time_ms.unwrap() - 10.0).abs() < 1e-9);
+        assert_eq!(scan.self_time_ms, Some(0.5));
+        assert_eq!(subplan.self_time_ms, Some(0.0));
+        assert!((plan.self_time_ms.unwrap() - 2.0).abs() < 1e-9);
+        assert_eq!(plan.self_cost, 815.0);
+
+        let buffers = index_scan.buffers.as_ref().unwrap();
+        assert_eq!((buffers.shared_hit, buffers.shared_read), (300, 12));
+        assert!(!index_scan.details.contains_key("Shared Hit Blocks"));
+        assert_eq!(scan.details.get("Rows Removed by Filter"), Some(&serde_json::json!(5)));
+    }
+
+    #[test]
+    fn ranks_hot_spots() {
+        let found = |hot_spots: Vec<HotSpot>| -> Vec<String> {
+            hot_spots.iter().map(|h| format!("{} {}: {}", h.node_id, h.kind, h.message)).collect()
+        };
+
+        assert_eq!(
+            found(find_hot_spots(&analyzed(), Some(12.8))),
+            [
+                "2 slowest_node: Index Scan on public.orders took 10.000 ms (78% of execution time)",
+                "0 most_expensive_node: Nested Loop has a cost of 815.00 (96% of total cost)",
+                "0 row_estimate_miss: Nested Loop estimated 10 rows but returned 1000 (100x underestimate)",
+                "2 row_estimate_miss: Index Scan on public.orders estimated 1 rows but returned 10 (10x underestimate)",
+            ]
+        );
+
+        // Without ANALYZE only the cost is known
+        assert_eq!(
+            found(find_hot_spots(&estimated(), None)),
+            ["1 most_expensive_node: Seq Scan on public.orders has a cost of 30.00 (43% of total cost)"]
+        );
+    }
+}
@@ -0,0 +1,561 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Column, Connection, Executor, Statement};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, State};
+use tauri_plugin_dialog::DialogExt;
+
+use super::snippets::{array_literal, quote_element};
+use super::{
+    decode, query_error, quote_identifier, read_only, session_error, stream_rows, transaction, PgConnection,
+    PgSessions, QueryHistory, SavedConnections,
+};
+
+// Rows decoded and written per batch while exporting
+const EXPORT_CHUNK_SIZE: usize = 1_000;
+
+const DEFAULT_TABLE_NAME: &str = "exported_rows";
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    /// A single JSON array of row objects
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    /// One INSERT statement per row
+    Sql,
+    Markdown,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Sql => "sql",
+            ExportFormat::Markdown => "md",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::Json => "JSON",
+            ExportFormat::Ndjson => "NDJSON",
+            ExportFormat::Sql => "SQL",
+            ExportFormat::Markdown => "Markdown",
+        }
+    }
+}
+
+/// When CSV fields are wrapped in quotes. NULL is never quoted, so it stays distinct from an empty string.
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CsvQuoting {
+    /// Only fields containing the delimiter, the quote, a line break, or empty strings
+    #[default]
+    Necessary,
+    Always,
+    /// Every field that isn't a number
+    NonNumeric,
+    Never,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct ExportOptions {
+    /// CSV field separator (default `,`)
+    pub delimiter: Option<char>,
+    /// CSV quote character (default `"`)
+    pub quote: Option<char>,
+    #[serde(default)]
+    pub quoting: CsvQuoting,
+    /// Write a header row in CSV (default true)
+    pub header: Option<bool>,
+    /// Text written for NULL in CSV and Markdown (default empty)
+    #[serde(default)]
+    pub null_value: String,
+    /// Table named in SQL INSERT statements, written as given (default `exported_rows`)
+    pub table_name: Option<String>,
+    /// Id used to cancel the export with `pg_cancel_query`
+    pub query_id: Option<String>,
+    /// Cancel the query if it runs longer than this
+    pub statement_timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportSummary {
+    pub path: String,
+    pub format: ExportFormat,
+    pub row_count: usize,
+    pub bytes_written: u64,
+}
+
+/// Writes rows in one export format as they arrive
+struct ExportWriter<W: Write> {
+    out: W,
+    format: ExportFormat,
+    options: ExportOptions,
+    columns: Vec<String>,
+    column_types: Vec<String>,
+    rows_written: usize,
+}
+
+impl<W: Write> ExportWriter<W> {
+    fn begin(&mut self) -> std::io::Result<()> {
+        match self.format {
+            ExportFormat::Csv if self.options.header.unwrap_or(true) => {
+                let header: Vec<Value> = self.columns.iter().cloned().map(Value::String).collect();
+                self.write_csv_row(&header)
+            }
+            ExportFormat::Json => write!(self.out, "["),
+            ExportFormat::Markdown => {
+                let header: Vec<String> = self.columns.iter().map(|c| markdown_cell(c)).collect();
+                writeln!(self.out, "| {} |", header.join(" | "))?;
+                writeln!(self.out, "|{}", " --- |".repeat(self.columns.len()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn write_row(&mut self, row: &[Value]) -> std::io::Result<()> {
+        match self.format {
+            ExportFormat::Csv => self.write_csv_row(row)?,
+            ExportFormat::Json => {
+                let separator = if self.rows_written == 0 { "\n  " } else { ",\n  " };
+                write!(self.out, "{}{}", separator, self.json_object(row))?;
+            }
+            ExportFormat::Ndjson => writeln!(self.out, "{}", self.json_object(row))?,
+            ExportFormat::Sql => {
+                let columns: Vec<String> = self.columns.iter().map(|c| quote_identifier(c)).collect();
+                let values: Vec<String> = row
+                    .iter()
+                    .zip(&self.column_types)
+                    .map(|(value, column_type)| sql_literal(value, column_type))
+                    .collect();
+                writeln!(
+                    self.out,
+                    "INSERT INTO {} ({}) VALUES ({});",
+                    self.options.table_name.as_deref().unwrap_or(DEFAULT_TABLE_NAME),
+                    columns.join(", "),
+                    values.join(", ")
+                )?;
+            }
+            ExportFormat::Markdown => {
+                let cells: Vec<String> = row
+                    .iter()
+                    .map(|value| match value {
+                        Value::Null => markdown_cell(&self.options.null_value),
+                        Value::String(s) => markdown_cell(s),
+                        other => markdown_cell(&other.to_string()),
+                    })
+                    .collect();
+                writeln!(self.out, "| {} |", cells.join(" | "))?;
+            }
+        }
+        self.rows_written += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> std::io::Result<()> {
+        if let ExportFormat::Json = self.format {
+            let end = if self.rows_written == 0 { "]\n" } else { "\n]\n" };
+            write!(self.out, "{}", end)?;
+        }
+        self.out.flush()
+    }
+
+    /// Build the object by hand so keys keep the column order
+    fn json_object(&self, row: &[Value]) -> String {
+        let fields: Vec<String> = self
+            .columns
+            .iter()
+            .zip(row)
+            .map(|(column, value)| format!("{}:{}", Value::String(column.clone()), value))
+            .collect();
+        format!("{{{}}}", fields.join(","))
+    }
+
+    fn write_csv_row(&mut self, row: &[Value]) -> std::io::Result<()> {
+        let delimiter = self.options.delimiter.unwrap_or(',');
+        let quote = self.options.quote.unwrap_or('"');
+        let mut line = String::new();
+
+        for (i, value) in row.iter().enumerate() {
+            if i > 0 {
+                line.push(delimiter);
+            }
+
+            let text = match value {
+                Value::Null => {
+                    line.push_str(&self.options.null_value);
+                    continue;
+                }
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+
+            let quoted = match self.options.quoting {
+                CsvQuoting::Always => true,
+                CsvQuoting::Never => false,
+                CsvQuoting::NonNumeric => !value.is_number(),
+                CsvQuoting::Necessary => {
+                    text.is_empty()
+                        || text.contains(delimiter)
+                        || text.contains(quote)
+                        || text.contains(['\n', '\r'])
+                }
+            };
+
+            if quoted {
+                let doubled = format!("{}{}", quote, quote);
+                line.push(quote);
+                line.push_str(&text.replace(quote, &doubled));
+                line.push(quote);
+            } else {
+                line.push_str(&text);
+            }
+        }
+
+        line.push('\n');
+        self.out.write_all(line.as_bytes())
+    }
+}
+
+fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace("\r\n", "<br>").replace(['\n', '\r'], "<br>")
+}
+
+fn quote_literal(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// SQL literal for a decoded value. Arrays and ranges are turned back into Postgres literals;
+/// composites are written as JSON text.
+fn sql_literal(value: &Value, column_type: &str) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Bool(b) => if *b { "TRUE" } else { "FALSE" }.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => quote_literal(s),
+        Value::Array(items) if column_type.ends_with("[]") => quote_literal(&array_literal(items)),
+        Value::Array(ranges) if column_type.ends_with("MULTIRANGE") => {
+            let ranges: Vec<String> = ranges.iter().map(range_literal).collect();
+            quote_literal(&format!("{{{}}}", ranges.join(",")))
+        }
+        Value::Object(range) if column_type.ends_with("RANGE") && !range.is_empty() => {
+            quote_literal(&range_literal(value))
+        }
+        other => quote_literal(&other.to_string()),
+    }
+}
+
+fn range_literal(range: &Value) -> String {
+    if range.get("empty").is_some() {
+        return "empty".to_string();
+    }
+
+    let bound = |key: &str| match range.get(key) {
+        Some(Value::String(s)) => quote_element(s),
+        Some(Value::Null) | None => String::new(),
+        Some(other) => other.to_string(),
+    };
+    let inclusive = |key: &str| range.get(key).and_then(Value::as_bool).unwrap_or(false);
+
+    format!(
+        "{}{},{}{}",
+        if inclusive("lower_inclusive") { '[' } else { '(' },
+        bound("lower"),
+        bound("upper"),
+        if inclusive("upper_inclusive") { ']' } else { ')' },
+    )
+}
+
+/// Ask the user where to save the export
+async fn choose_path(app: &AppHandle, format: ExportFormat) -> Option<PathBuf> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    app.dialog()
+        .file()
+        .set_file_name(format!("export.{}", format.extension()))
+        .add_filter(format.label(), &[format.extension()])
+        .save_file(move |path| {
+            let _ = tx.send(path);
+        });
+
+    rx.await.ok().flatten().and_then(|path| path.into_path().ok())
+}
+
+/// Run a query and stream its rows straight into a file.
+/// Without a `path` the user picks one in a save dialog; returns `None` if they cancel it.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn pg_export_query(
+    app: AppHandle,
+    sessions: State<'_, PgSessions>,
+    connections: State<'_, SavedConnections>,
+    history: State<'_, QueryHistory>,
+    connection_id: String,
+    query: String,
+    format: ExportFormat,
+    path: Option<String>,
+    options: Option<ExportOptions>,
+) -> Result<Option<ExportSummary>, String> {
+    let connection = connections.get(&connection_id).await?;
+    let checked = read_only::check(&connection, &query)
+        .and_then(|()| transaction::check_statements(&query, connection.driver));
+    if let Err(e) = checked {
+        history.record_outcome(&connection.id, &query, None, Instant::now(), Err(&e)).await;
+        return Err(e);
+    }
+
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => match choose_path(&app, format).await {
+            Some(path) => path,
+            None => return Ok(None),
+        },
+    };
+
+    let started = Instant::now();
+    let result = export_to_file(&sessions, &connection, &query, format, &path, options.unwrap_or_default()).await;
+    let outcome = result.as_ref().map(|summary| (summary.row_count, 0)).map_err(String::as_str);
+    history.record_outcome(&connection.id, &query, None, started, outcome).await;
+    result.map(Some)
+}
+
+async fn export_to_file(
+    sessions: &PgSessions,
+    connection: &PgConnection,
+    query: &str,
+    format: ExportFormat,
+    path: &Path,
+    options: ExportOptions,
+) -> Result<ExportSummary, String> {
+    let session = sessions.acquire(connection).await?;
+    let mut conn = session.conn.lock().await;
+
+    // Prepare first so the header can be written even when the query returns no rows. `prepare`
+    // caches the statement and later runs of the same SQL would reuse it, which fails once DDL
+    // changes the tables it reads, so the cache is cleared again right away.
+    let statement = match (&mut *conn).prepare(query).await {
+        Ok(statement) => statement,
+        Err(e) => return Err(session_error(sessions, &session, e).await),
+    };
+    let columns = statement.columns().iter().map(|c| c.name().to_string()).collect();
+    let column_types = statement
+        .columns()
+        .iter()
+        .map(|c| decode::type_name(c.type_info()))
+        .collect();
+    if let Err(e) = conn.clear_cached_statements().await {
+        return Err(session_error(sessions, &session, e).await);
+    }
+
+    let running = sessions.track_query(
+        &session,
+        options.query_id.clone(),
+        options.statement_timeout_ms.map(Duration::from_millis),
+    );
+
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut writer = ExportWriter {
+        out: BufWriter::new(file),
+        format,
+        options,
+        columns,
+        column_types,
+        rows_written: 0,
+    };
+
+    let mut write_error = writer.begin().err();
+    let queries = [sqlx::query(query).persistent(false)];
+    let outcome = if write_error.is_some() {
+        None
+    } else {
+        Some(
+            stream_rows(&mut *conn, queries, usize::MAX, EXPORT_CHUNK_SIZE, session.in_transaction(), |_, rows| {
+                for row in &rows {
+                    if let Err(e) = writer.write_row(row) {
+                        write_error = Some(e);
+                        return ControlFlow::Break(());
+                    }
+                }
+                ControlFlow::Continue(())
+            })
+            .await,
+        )
+    };
+
+    let row_count = writer.rows_written;
+    if write_error.is_none() {
+        write_error = writer.finish().err();
+    }
+
+    let failure = match (outcome, write_error) {
+        (_, Some(e)) => Some(format!("Failed to write {}: {}", path.display(), e)),
+        (Some(Err(e)), None) => Some(query_error(sessions, &session, &running, e).await),
+        _ => None,
+    };
+
+    if let Some(message) = failure {
+        // Don't leave a partial export behind
+        let _ = std::fs::remove_file(path);
+        return Err(message);
+    }
+
+    Ok(ExportSummary {
+        path: path.display().to_string(),
+        format,
+        row_count,
+        bytes_written: std::fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn export(format: ExportFormat, options: ExportOptions, rows: &[Value]) -> String {
+        let mut out = Vec::new();
+        let mut writer = ExportWriter {
+            out: &mut out,
+            format,
+            options,
+            columns: vec!["id".to_string(), "name".to_string()],
+            column_types: vec!["INT4".to_string(), "TEXT".to_string()],
+            rows_written: 0,
+        };
+        writer.begin().unwrap();
+        for row in rows {
+            writer.write_row(row.as_array().unwrap()).unwrap();
+        }
+        writer.finish().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn rows() -> Vec<Value> {
+        vec![
+            json!([1, "plain"]),
+            json!([2, "a,b \"q\""]),
+            json!([3, ""]),
+            json!([null, "two\nlines"]),
+            json!([4.5, null]),
+        ]
+    }
+
+    fn csv(options: serde_json::Value) -> String {
+        export(ExportFormat::Csv, serde_json::from_value(options).unwrap(), &rows())
+    }
+
+    #[test]
+    fn quotes_csv_fields_as_asked() {
+        assert_eq!(
+            csv(json!({})),
+            "id,name\n1,plain\n2,\"a,b \"\"q\"\"\"\n3,\"\"\n,\"two\nlines\"\n4.5,\n"
+        );
+        assert_eq!(
+            csv(json!({ "quoting": "always" })),
+            "\"id\",\"name\"\n\"1\",\"plain\"\n\"2\",\"a,b \"\"q\"\"\"\n\"3\",\"\"\n,\"two\nlines\"\n\"4.5\",\n"
+        );
+        assert_eq!(
+            csv(json!({ "quoting": "non_numeric" })),
+            "\"id\",\"name\"\n1,\"plain\"\n2,\"a,b \"\"q\"\"\"\n3,\"\"\n,\"two\nlines\"\n4.5,\n"
+        );
+        assert_eq!(
+            csv(json!({ "quoting": "never" })),
+            "id,name\n1,plain\n2,a,b \"q\"\n3,\n,two\nlines\n4.5,\n"
+        );
+    }
+
+    #[test]
+    fn writes_csv_with_custom_delimiter_quote_and_null() {
+        assert_eq!(
+            csv(json!({ "delimiter": ";", "quote": "'", "null_value": "\\N", "header": false })),
+            "1;plain\n2;a,b \"q\"\n3;''\n\\N;'two\nlines'\n4.5;\\N\n"
+        );
+        let quoted = export(
+            ExportFormat::Csv,
+            serde_json::from_value(json!({ "delimiter": "\t", "quote": "'" })).unwrap(),
+            &[json!([1, "it's\tx"])],
+        );
+        assert_eq!(quoted, "id\tname\n1\t'it''s\tx'\n");
+    }
+
+    #[test]
+    fn writes_json_objects_in_column_order() {
+        let rows = [json!([1, {"k": [1, "x"]}]), json!([null, "b"])];
+        assert_eq!(
+            export(ExportFormat::Json, ExportOptions::default(), &rows),
+            "[\n  {\"id\":1,\"name\":{\"k\":[1,\"x\"]}},\n  {\"id\":null,\"name\":\"b\"}\n]\n"
+        );
+        assert_eq!(export(ExportFormat::Json, ExportOptions::default(), &[]), "[]\n");
+        assert_eq!(
+            export(ExportFormat::Ndjson, ExportOptions::default(), &rows),
+            "{\"id\":1,\"name\":{\"k\":[1,\"x\"]}}\n{\"id\":null,\"name\":\"b\"}\n"
+        );
+    }
+
+    #[test]
+    fn escapes_markdown_cells() {
+        let options = ExportOptions {
+            null_value: "-".to_string(),
+            ..Default::default()
+        };
+        let rows = [json!([1, "a | b"]), json!([null, "x\r\ny\nz"]), json!([true, {"k": 1}])];
+        assert_eq!(
+            export(ExportFormat::Markdown, options, &rows),
+            "| id | name |\n| --- | --- |\n| 1 | a \\| b |\n| - | x<br>y<br>z |\n| true | {\"k\":1} |\n"
+        );
+    }
+
+    #[test]
+    fn writes_insert_statements() {
+        let options = ExportOptions {
+            table_name: Some("public.t".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            export(ExportFormat::Sql, options, &[json!([1, "O'Brien"]), json!([null, null])]),
+            "INSERT INTO public.t (\"id\", \"name\") VALUES (1, 'O''Brien');\n\
+             INSERT INTO public.t (\"id\", \"name\") VALUES (NULL, NULL);\n"
+        );
+    }
+
+    #[test]
+    fn turns_values_into_sql_literals() {
+        assert_eq!(sql_literal(&json!(null), "INT4"), "NULL");
+        assert_eq!(sql_literal(&json!(false), "BOOL"), "FALSE");
+        assert_eq!(sql_literal(&json!(-1.5), "NUMERIC"), "-1.5");
+        assert_eq!(sql_literal(&json!("it's"), "TEXT"), "'it''s'");
+        assert_eq!(sql_literal(&json!({"k": "it's"}), "JSONB"), r#"'{"k":"it''s"}'"#);
+        assert_eq!(sql_literal(&json!([1, 2]), "JSONB"), "'[1,2]'");
+        assert_eq!(
+            sql_literal(&json!(["a", null, "b \"q\""]), "TEXT[]"),
+            r#"'{"a",NULL,"b \"q\""}'"#
+        );
+        assert_eq!(sql_literal(&json!([[1, 2], [3, null]]), "INT4[]"), "'{{1,2},{3,NULL}}'");
+    }
+
+    #[test]
+    fn turns_ranges_into_range_literals() {
+        let range = json!({ "lower": 1, "upper": 10, "lower_inclusive": true, "upper_inclusive": false });
+        assert_eq!(range_literal(&range), "[1,10)");
+        let open = json!({ "lower": null, "upper": "2024-01-01", "lower_inclusive": false, "upper_inclusive": true });
+        assert_eq!(range_literal(&open), r#"(,"2024-01-01"]"#);
+        assert_eq!(range_literal(&json!({ "empty": true })), "empty");
+
+        assert_eq!(sql_literal(&range, "INT4RANGE"), "'[1,10)'");
+        assert_eq!(
+            sql_literal(&json!([range, { "empty": true }]), "INT4MULTIRANGE"),
+            "'{[1,10),empty}'"
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tauri::State;
+
+use super::snippets::{self, Parameters};
+use super::driver::DriverKind;
+use super::{execute_sql, PgSessions, QueryOptions, QueryResult, SavedConnections};
+use crate::mysql::MySqlSessions;
+
+// One JSON object per line in the app data directory, oldest first
+const HISTORY_FILE: &str = "pg-history.jsonl";
+
+// Entries kept once the file is compacted
+const MAX_HISTORY_ENTRIES: usize = 10_000;
+
+// Entries returned by a search when the caller doesn't ask for a number
+const DEFAULT_SEARCH_LIMIT: usize = 200;
+
+/// One execution of a query
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HistoryEntry {
+    pub id: String,
+    pub connection_id: String,
+    pub sql: String,
+    /// Values bound to the `:name` placeholders of a snippet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Parameters>,
+    /// Milliseconds since the Unix epoch
+    pub executed_at: u64,
+    pub duration_ms: u64,
+    /// Rows returned; `None` when the query failed
+    pub row_count: Option<usize>,
+    pub rows_affected: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Which entries `pg_search_history` returns; every field narrows the search
+#[derive(Debug, Deserialize, Default)]
+pub struct HistoryFilter {
+    pub connection_id: Option<String>,
+    /// Case-insensitive text the SQL must contain
+    pub search: Option<String>,
+    /// Only failed (`true`) or only successful (`false`) executions
+    pub failed: Option<bool>,
+    /// Executed at or after, in milliseconds since the Unix epoch
+    pub since: Option<u64>,
+    /// Executed before, in milliseconds since the Unix epoch
+    pub until: Option<u64>,
+    /// Most entries to return (default 200), newest first
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+impl HistoryFilter {
+    fn matches(&self, entry: &HistoryEntry, search: Option<&str>) -> bool {
+        self.connection_id.as_ref().is_none_or(|id| *id == entry.connection_id)
+            && search.is_none_or(|text| entry.sql.to_lowercase().contains(text))
+            && self.failed.is_none_or(|failed| failed == entry.error.is_some())
+            && self.since.is_none_or(|since| entry.executed_at >= since)
+            && self.until.is_none_or(|until| entry.executed_at < until)
+    }
+}
+
+/// History of executed queries, kept in Tauri managed state. The file is only read and
+/// written on blocking threads, as it can hold thousands of entries.
+pub struct QueryHistory {
+    file: Arc<HistoryFile>,
+}
+
+impl QueryHistory {
+    pub fn load(dir: PathBuf) -> Self {
+        QueryHistory {
+            file: Arc::new(HistoryFile::open(dir.join(HISTORY_FILE), MAX_HISTORY_ENTRIES)),
+        }
+    }
+
+    async fn with_file<T, F>(&self, call: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&HistoryFile) -> Result<T, String> + Send + 'static,
+    {
+        let file = self.file.clone();
+        tokio::task::spawn_blocking(move || call(&file))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+
+    /// Log a finished query. Failing to write the history never fails the query itself.
+    pub async fn record(
+        &self,
+        connection_id: &str,
+        sql: &str,
+        parameters: Option<&Parameters>,
+        started: Instant,
+        result: &Result<QueryResult, String>,
+    ) {
+        let outcome = match result {
+            Ok(result) => Ok((result.row_count, result.rows_affected)),
+            Err(e) => Err(e.as_str()),
+        };
+        self.record_outcome(connection_id, sql, parameters, started, outcome).await;
+    }
+
+    /// Log a finished statement that has no `QueryResult`, by its rows returned and rows affected
+    pub async fn record_outcome(
+        &self,
+        connection_id: &str,
+        sql: &str,
+        parameters: Option<&Parameters>,
+        started: Instant,
+        outcome: Result<(usize, u64), &str>,
+    ) {
+        let duration = started.elapsed();
+        let executed_at = SystemTime::now()
+            .checked_sub(duration)
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since_epoch| since_epoch.as_millis() as u64);
+
+        let entry = HistoryEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            connection_id: connection_id.to_string(),
+            sql: sql.to_string(),
+            parameters: parameters.cloned(),
+            executed_at,
+            duration_ms: duration.as_millis() as u64,
+            row_count: outcome.ok().map(|(row_count, _)| row_count),
+            rows_affected: outcome.ok().map(|(_, rows_affected)| rows_affected),
+            error: outcome.err().map(str::to_string),
+        };
+
+        let _ = self.with_file(move |file| file.append(&entry)).await;
+    }
+
+    pub async fn search(&self, filter: HistoryFilter) -> Result<Vec<HistoryEntry>, String> {
+        self.with_file(move |file| file.search(&filter)).await
+    }
+
+    pub async fn get(&self, entry_id: String) -> Result<HistoryEntry, String> {
+        self.with_file(move |file| file.get(&entry_id)).await
+    }
+
+    /// Remove the entries of one connection, or all of them. Returns how many were removed.
+    pub async fn clear(&self, connection_id: Option<String>) -> Result<usize, String> {
+        self.with_file(move |file| file.clear(connection_id.as_deref())).await
+    }
+}
+
+/// The history file, compacted to its newest `max_entries` once it grows past them
+struct HistoryFile {
+    path: PathBuf,
+    max_entries: usize,
+    // Entries in the file, to know when to compact it; guards all file access
+    entries: std::sync::Mutex<usize>,
+}
+
+impl HistoryFile {
+    fn open(path: PathBuf, max_entries: usize) -> Self {
+        let entries = std::fs::read_to_string(&path)
+            .map(|contents| contents.lines().count())
+            .unwrap_or(0);
+
+        HistoryFile {
+            path,
+            max_entries,
+            entries: std::sync::Mutex::new(entries),
+        }
+    }
+
+    fn append(&self, entry: &HistoryEntry) -> Result<(), String> {
+        let mut entries = self.entries.lock().map_err(|e| e.to_string())?;
+
+        let mut line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        line.push('\n');
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(|e| e.to_string())?;
+        *entries += 1;
+
+        // Let the file grow a little past the cap so it isn't rewritten on every query
+        if *entries > self.max_entries + self.max_entries / 10 {
+            let all = self.read_entries()?;
+            let keep = &all[all.len().saturating_sub(self.max_entries)..];
+            self.write_entries(keep)?;
+            *entries = keep.len();
+        }
+        Ok(())
+    }
+
+    /// Every entry, oldest first. Lines that don't parse, e.g. a partly written last line, are skipped.
+    fn read_entries(&self) -> Result<Vec<HistoryEntry>, String> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(contents) => Ok(contents
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn write_entries(&self, entries: &[HistoryEntry]) -> Result<(), String> {
+        let mut contents = String::new();
+        for entry in entries {
+            contents.push_str(&serde_json::to_string(entry).map_err(|e| e.to_string())?);
+            contents.push('\n');
+        }
+        std::fs::write(&self.path, contents).map_err(|e| e.to_string())
+    }
+
+    fn search(&self, filter: &HistoryFilter) -> Result<Vec<HistoryEntry>, String> {
+        let _guard = self.entries.lock().map_err(|e| e.to_string())?;
+
+        let search = filter.search.as_ref().map(|text| text.to_lowercase());
+        Ok(self
+            .read_entries()?
+            .into_iter()
+            .rev()
+            .filter(|entry| filter.matches(entry, search.as_deref()))
+            .skip(filter.offset.unwrap_or(0))
+            .take(filter.limit.unwrap_or(DEFAULT_SEARCH_LIMIT))
+            .collect())
+    }
+
+    fn get(&self, entry_id: &str) -> Result<HistoryEntry, String> {
+        let _guard = self.entries.lock().map_err(|e| e.to_string())?;
+
+        self.read_entries()?
+            .into_iter()
+            .find(|entry| entry.id == entry_id)
+            .ok_or_else(|| format!("No history entry with id {}", entry_id))
+    }
+
+    fn clear(&self, connection_id: Option<&str>) -> Result<usize, String> {
+        let mut entries = self.entries.lock().map_err(|e| e.to_string())?;
+
+        let all = self.read_entries()?;
+        let before = all.len();
+        let kept: Vec<HistoryEntry> = match connection_id {
+            Some(id) => all.into_iter().filter(|entry| entry.connection_id != id).collect(),
+            None => Vec::new(),
+        };
+        self.write_entries(&kept)?;
+        *entries = kept.len();
+        Ok(before - kept.len())
+    }
+}
+
+/// Executed queries matching the filter, newest first
+#[tauri::command]
+pub async fn pg_search_history(
+    history: State<'_, QueryHistory>,
+    filter: Option<HistoryFilter>,
+) -> Result<Vec<HistoryEntry>, String> {
+    history.search(filter.unwrap_or_default()).await
+}
+
+/// Run a query from history again, with the same parameters, on its own connection or on
+/// `connection_id`. The run is itself added to the history.
+#[tauri::command]
+pub async fn pg_rerun_history_entry(
+    sessions: State<'_, PgSessions>,
+    mysql_sessions: State<'_, MySqlSessions>,
+    connections: State<'_, SavedConnections>,
+    history: State<'_, QueryHistory>,
+    entry_id: String,
+    connection_id: Option<String>,
+    options: Option<QueryOptions>,
+) -> Result<QueryResult, String> {
+    let entry = history.get(entry_id).await?;
+    let connection = connections
+        .get(connection_id.as_deref().unwrap_or(&entry.connection_id))
+        .await?;
+
+    let options = options.unwrap_or_default();
+    let started = Instant::now();
+    let result = match (&entry.parameters, connection.driver) {
+        (Some(parameters), DriverKind::Postgres) => {
+            snippets::execute_with_parameters(&sessions, &connection, &entry.sql, parameters, options).await
+        }
+        (None, DriverKind::Postgres) => execute_sql(&sessions, &connection, &entry.sql, options).await,
+        // Binding `:name` parameters asks Postgres for their types, which MySQL can't do
+        (Some(_), DriverKind::Mysql) => Err("Queries with parameters can only be rerun on a Postgres connection".to_string()),
+        (None, DriverKind::Mysql) => execute_sql(&mysql_sessions, &connection, &entry.sql, options).await,
+    };
+    history.record(&connection.id, &entry.sql, entry.parameters.as_ref(), started, &result).await;
+    result
+}
+
+/// Forget the history of one connection, or all history. Returns how many entries were removed.
+#[tauri::command]
+pub async fn pg_clear_history(
+    history: State<'_, QueryHistory>,
+    connection_id: Option<String>,
+) -> Result<usize, String> {
+    history.clear(connection_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, connection_id: &str, sql: &str, executed_at: u64, error: Option<&str>) -> HistoryEntry {
+        HistoryEntry {
+            id: id.to_string(),
+            connection_id: connection_id.to_string(),
+            sql: sql.to_string(),
+            parameters: None,
+            executed_at,
+            duration_ms: 1,
+            row_count: error.is_none().then_some(1),
+            rows_affected: error.is_none().then_some(0),
+            error: error.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn filters_entries_by_every_field() {
+        let ok = entry("1", "sales", "SELECT * FROM Orders", 1_000, None);
+        let failed = entry("2", "hr", "DELETE FROM staff", 2_000, Some("permission denied"));
+        let matches = |filter: serde_json::Value, entry: &HistoryEntry| {
+            let filter: HistoryFilter = serde_json::from_value(filter).unwrap();
+            let search = filter.search.as_ref().map(|text| text.to_lowercase());
+            filter.matches(entry, search.as_deref())
+        };
+
+        assert!(matches(serde_json::json!({}), &ok));
+        assert!(matches(serde_json::json!({"connection_id": "sales"}), &ok));
+        assert!(!matches(serde_json::json!({"connection_id": "sales"}), &failed));
+        assert!(matches(serde_json::json!({"search": "from orders"}), &ok));
+        assert!(!matches(serde_json::json!({"search": "orders"}), &failed));
+        assert!(matches(serde_json::json!({"failed": true}), &failed));
+        assert!(!matches(serde_json::json!({"failed": true}), &ok));
+        assert!(matches(serde_json::json!({"failed": false}), &ok));
+        // `since` is inclusive and `until` exclusive
+        assert!(matches(serde_json::json!({"since": 1_000, "until": 1_001}), &ok));
+        assert!(!matches(serde_json::json!({"since": 1_001}), &ok));
+        assert!(!matches(serde_json::json!({"until": 1_000}), &ok));
+        assert!(!matches(serde_json::json!({"connection_id": "hr", "failed": false}), &failed));
+    }
+
+    #[test]
+    fn compacts_to_the_newest_entries() {
+        let path = std::env::temp_dir().join(format!("pg-history-compact-{}.jsonl", std::process::id()));
+        // A partly written line, as left by a crash, is skipped when the file is read
+        std::fs::write(&path, "{\"id\":\"torn\n").unwrap();
+        let file = HistoryFile::open(path.clone(), 10);
+        let ids = |file: &HistoryFile| -> Vec<String> {
+            file.read_entries().unwrap().into_iter().map(|entry| entry.id).collect()
+        };
+
+        for i in 0..10 {
+            file.append(&entry(&i.to_string(), "c", "SELECT 1", i, None)).unwrap();
+        }
+        // Ten entries and the torn line: one past the cap is still within its slack
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 11);
+        assert_eq!(ids(&file).len(), 10);
+
+        file.append(&entry("10", "c", "SELECT 1", 10, None)).unwrap();
+        let kept = ids(&file);
+        assert_eq!(kept, (1..=10).map(|i| i.to_string()).collect::<Vec<_>>());
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 10);
+        assert_eq!(*file.entries.lock().unwrap(), 10);
+
+        let newest = file.search(&HistoryFilter { limit: Some(2), ..Default::default() }).unwrap();
+        assert_eq!(newest.iter().map(|entry| entry.id.as_str()).collect::<Vec<_>>(), ["10", "9"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn clears_one_connection_or_everything() {
+        let path = std::env::temp_dir().join(format!("pg-history-clear-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let file = HistoryFile::open(path.clone(), 10);
+        file.append(&entry("1", "sales", "SELECT 1", 1, None)).unwrap();
+        file.append(&entry("2", "hr", "SELECT 2", 2, None)).unwrap();
+        file.append(&entry("3", "sales", "SELECT 3", 3, None)).unwrap();
+
+        assert_eq!(file.clear(Some("sales")).unwrap(), 2);
+        assert_eq!(file.get("2").unwrap().sql, "SELECT 2");
+        assert_eq!(file.get("1").unwrap_err(), "No history entry with id 1");
+        assert_eq!(file.clear(None).unwrap(), 1);
+        assert!(file.read_entries().unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
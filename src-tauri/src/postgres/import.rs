@@ -0,0 +1,1071 @@
+use serde::de::{Deserializer, MapAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::postgres::PgDatabaseError;
+use sqlx::Row;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, State};
+use tauri_plugin_dialog::DialogExt;
+
+use super::session::QUERY_CANCELLED;
+use super::{quote_identifier, read_only, session_error, PgConnection, PgSessions, SavedConnections};
+
+// Records read before any row is sent, used to name columns and infer their types
+const DEFAULT_SAMPLE_SIZE: usize = 1_000;
+
+// Skipped rows tolerated before the whole import is abandoned
+const DEFAULT_MAX_ERRORS: usize = 1_000;
+
+// COPY data is sent to the server in batches of about this many bytes
+const COPY_BATCH_BYTES: usize = 1 << 20;
+
+// A `pg-import-progress` event is emitted every this many records
+const PROGRESS_EVERY: usize = 10_000;
+
+// Records the reader thread hands to the COPY loop at a time
+const READ_BATCH_RECORDS: usize = 1_000;
+
+// Inferred types, from most to least specific
+const INFERRED_TYPES: [&str; 10] = [
+    "boolean",
+    "integer",
+    "bigint",
+    "numeric",
+    "date",
+    "timestamp",
+    "timestamptz",
+    "uuid",
+    "jsonb",
+    "text",
+];
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportFormat {
+    Csv,
+    /// A JSON array of objects
+    Json,
+    /// One JSON object per line
+    Ndjson,
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct ImportOptions {
+    /// Target table
+    pub table: String,
+    /// Schema of the target table (default: first match on the search_path)
+    pub schema: Option<String>,
+    /// File format; guessed from the extension when missing
+    pub format: Option<ImportFormat>,
+    /// Create the table from the inferred column types before loading
+    #[serde(default)]
+    pub create_table: bool,
+    /// Override inferred types when creating the table, keyed by column name
+    #[serde(default)]
+    pub column_types: HashMap<String, String>,
+    /// CSV field separator (default `,`)
+    pub delimiter: Option<char>,
+    /// CSV quote character (default `"`)
+    pub quote: Option<char>,
+    /// First CSV line holds column names (default true)
+    pub has_header: Option<bool>,
+    /// CSV fields with exactly this text are loaded as NULL (default empty)
+    #[serde(default)]
+    pub null_value: String,
+    /// Records used for type inference (default 1 000)
+    pub sample_size: Option<usize>,
+    /// Skipped rows tolerated before the import is abandoned (default 1 000)
+    pub max_errors: Option<usize>,
+    /// Tags `pg-import-progress` events; also cancels the import with `pg_cancel_query`
+    pub import_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ImportColumn {
+    pub name: String,
+    pub data_type: String,
+}
+
+/// A record that was skipped, numbered from 1 not counting the CSV header
+#[derive(Debug, Serialize, Clone)]
+pub struct RowError {
+    pub row: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportPreview {
+    pub format: ImportFormat,
+    pub columns: Vec<ImportColumn>,
+    /// The first few records as they would be loaded
+    pub sample_rows: Vec<Vec<Value>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportSummary {
+    pub path: String,
+    pub table: String,
+    pub created_table: bool,
+    pub columns: Vec<ImportColumn>,
+    pub rows_read: usize,
+    pub rows_imported: u64,
+    pub errors: Vec<RowError>,
+}
+
+/// Payload of the `pg-import-progress` event
+#[derive(Debug, Serialize, Clone)]
+pub struct ImportProgress {
+    pub import_id: Option<String>,
+    pub rows_read: usize,
+    pub errors: usize,
+    pub bytes_read: u64,
+    pub total_bytes: u64,
+    pub done: bool,
+}
+
+/// A JSON object with its keys in file order
+#[derive(Clone)]
+struct JsonRecord(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for JsonRecord {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RecordVisitor;
+
+        impl<'de> Visitor<'de> for RecordVisitor {
+            type Value = JsonRecord;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a JSON object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<JsonRecord, A::Error> {
+                let mut fields = Vec::new();
+                while let Some(field) = map.next_entry::<String, Value>()? {
+                    fields.push(field);
+                }
+                Ok(JsonRecord(fields))
+            }
+        }
+
+        deserializer.deserialize_map(RecordVisitor)
+    }
+}
+
+#[derive(Clone)]
+enum RawRecord {
+    Fields(Vec<String>),
+    Object(JsonRecord),
+}
+
+#[derive(Clone)]
+enum Cell {
+    Null,
+    Text(String),
+    /// A nested JSON object or array
+    Json(Value),
+}
+
+impl Cell {
+    fn text(&self) -> Option<String> {
+        match self {
+            Cell::Null => None,
+            Cell::Text(s) => Some(s.clone()),
+            Cell::Json(v) => Some(v.to_string()),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            Cell::Null => Value::Null,
+            Cell::Text(s) => Value::String(s.clone()),
+            Cell::Json(v) => v.clone(),
+        }
+    }
+}
+
+impl From<Value> for Cell {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => Cell::Null,
+            Value::String(s) => Cell::Text(s),
+            Value::Bool(b) => Cell::Text(b.to_string()),
+            Value::Number(n) => Cell::Text(n.to_string()),
+            nested => Cell::Json(nested),
+        }
+    }
+}
+
+/// Reads records one at a time from a CSV, JSON or NDJSON file
+enum Source {
+    Csv(csv::Reader<File>, csv::StringRecord),
+    Json(std::vec::IntoIter<JsonRecord>, usize, u64),
+    Ndjson(BufReader<File>, u64),
+}
+
+impl Source {
+    fn open(path: &Path, format: ImportFormat, options: &ImportOptions) -> Result<(Self, Option<Vec<String>>), String> {
+        let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+
+        match format {
+            ImportFormat::Csv => {
+                let delimiter = single_byte(options.delimiter.unwrap_or(','), "delimiter")?;
+                let quote = single_byte(options.quote.unwrap_or('"'), "quote")?;
+                let has_header = options.has_header.unwrap_or(true);
+                let mut reader = csv::ReaderBuilder::new()
+                    .delimiter(delimiter)
+                    .quote(quote)
+                    .has_headers(has_header)
+                    .flexible(true)
+                    .from_reader(file);
+                let header = if has_header {
+                    let header = reader.headers().map_err(|e| e.to_string())?;
+                    Some(header.iter().map(str::to_string).collect())
+                } else {
+                    None
+                };
+                Ok((Source::Csv(reader, csv::StringRecord::new()), header))
+            }
+            ImportFormat::Json => {
+                let total = file.metadata().map(|m| m.len()).unwrap_or(0);
+                let records: Vec<JsonRecord> = serde_json::from_reader(BufReader::new(file))
+                    .map_err(|e| format!("Expected a JSON array of objects: {}", e))?;
+                let count = records.len();
+                Ok((Source::Json(records.into_iter(), count, total), None))
+            }
+            ImportFormat::Ndjson => Ok((Source::Ndjson(BufReader::new(file), 0), None)),
+        }
+    }
+
+    fn next(&mut self) -> Option<Result<RawRecord, String>> {
+        match self {
+            Source::Csv(reader, record) => match reader.read_record(record) {
+                Ok(true) => Some(Ok(RawRecord::Fields(record.iter().map(str::to_string).collect()))),
+                Ok(false) => None,
+                Err(e) => Some(Err(e.to_string())),
+            },
+            Source::Json(records, _, _) => records.next().map(|record| Ok(RawRecord::Object(record))),
+            Source::Ndjson(reader, bytes_read) => loop {
+                let mut line = String::new();
+                match reader.read_line(&mut line) {
+                    Ok(0) => return None,
+                    Ok(n) => *bytes_read += n as u64,
+                    Err(e) => return Some(Err(e.to_string())),
+                }
+                if line.trim().is_empty() {
+                    continue;
+                }
+                return Some(serde_json::from_str(&line).map(RawRecord::Object).map_err(|e| e.to_string()));
+            },
+        }
+    }
+
+    fn bytes_read(&self) -> u64 {
+        match self {
+            Source::Csv(reader, _) => reader.position().byte(),
+            // The array is parsed up front; estimate from the records left
+            Source::Json(records, count, total) => {
+                if *count == 0 {
+                    *total
+                } else {
+                    total - total * records.len() as u64 / *count as u64
+                }
+            }
+            Source::Ndjson(_, bytes_read) => *bytes_read,
+        }
+    }
+}
+
+/// Records read from a file, with the bytes consumed so far
+type ReadBatch = (Vec<Result<RawRecord, String>>, u64);
+
+/// Read the rest of a file on a blocking thread, a batch at a time. The thread stops at the
+/// end of the file or once the receiver is dropped.
+fn read_in_background(mut source: Source) -> tokio::sync::mpsc::Receiver<ReadBatch> {
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    tokio::task::spawn_blocking(move || loop {
+        let batch: Vec<_> = std::iter::from_fn(|| source.next()).take(READ_BATCH_RECORDS).collect();
+        if batch.is_empty() || tx.blocking_send((batch, source.bytes_read())).is_err() {
+            break;
+        }
+    });
+    rx
+}
+
+fn single_byte(c: char, what: &str) -> Result<u8, String> {
+    u8::try_from(c)
+        .ok()
+        .filter(u8::is_ascii)
+        .ok_or_else(|| format!("The CSV {} must be a single ASCII character", what))
+}
+
+fn guess_format(path: &Path) -> Result<ImportFormat, String> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    match extension.as_str() {
+        "csv" | "tsv" | "txt" => Ok(ImportFormat::Csv),
+        "json" => Ok(ImportFormat::Json),
+        "ndjson" | "jsonl" => Ok(ImportFormat::Ndjson),
+        _ => Err(format!("Can't tell the format of {}; pass one explicitly", path.display())),
+    }
+}
+
+/// Column names from the CSV header, or from the keys of the sampled JSON objects in first-seen order
+fn column_names(header: Option<Vec<String>>, sample: &[Result<RawRecord, String>]) -> Vec<String> {
+    let names = match header {
+        Some(header) => header,
+        None => {
+            let mut names: Vec<String> = Vec::new();
+            for record in sample.iter().flatten() {
+                match record {
+                    RawRecord::Fields(fields) => {
+                        while names.len() < fields.len() {
+                            names.push(String::new());
+                        }
+                    }
+                    RawRecord::Object(JsonRecord(fields)) => {
+                        for (key, _) in fields {
+                            if !names.contains(key) {
+                                names.push(key.clone());
+                            }
+                        }
+                    }
+                }
+            }
+            names
+        }
+    };
+
+    // Fill in blank names and make duplicates unique
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    names
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let name = match name.trim() {
+                "" => format!("column{}", i + 1),
+                trimmed => trimmed.to_string(),
+            };
+            let count = seen.entry(name.clone()).or_insert(0);
+            *count += 1;
+            if *count == 1 {
+                name
+            } else {
+                format!("{}_{}", name, count)
+            }
+        })
+        .collect()
+}
+
+fn to_cells(record: RawRecord, columns: &[String], null_value: &str) -> Result<Vec<Cell>, String> {
+    match record {
+        RawRecord::Fields(fields) => {
+            if fields.len() != columns.len() {
+                return Err(format!("Expected {} fields, found {}", columns.len(), fields.len()));
+            }
+            Ok(fields
+                .into_iter()
+                .map(|field| if field == null_value { Cell::Null } else { Cell::Text(field) })
+                .collect())
+        }
+        RawRecord::Object(JsonRecord(fields)) => {
+            let mut cells = vec![Cell::Null; columns.len()];
+            for (key, value) in fields {
+                let i = columns
+                    .iter()
+                    .position(|c| *c == key)
+                    .ok_or_else(|| format!("Unexpected field \"{}\"", key))?;
+                cells[i] = Cell::from(value);
+            }
+            Ok(cells)
+        }
+    }
+}
+
+/// The most specific type every sampled value of each column fits
+fn infer_types(rows: &[Vec<Cell>], column_count: usize) -> Vec<String> {
+    (0..column_count)
+        .map(|i| {
+            let mut candidates = INFERRED_TYPES.to_vec();
+            for row in rows {
+                if !matches!(row[i], Cell::Null) {
+                    candidates.retain(|ty| fits(ty, &row[i]));
+                }
+            }
+            // Columns with no values in the sample are loaded as text
+            if rows.iter().all(|row| matches!(row[i], Cell::Null)) {
+                "text".to_string()
+            } else {
+                candidates.first().unwrap_or(&"text").to_string()
+            }
+        })
+        .collect()
+}
+
+/// Whether a non-null value can be loaded into a column of this type
+fn fits(ty: &str, cell: &Cell) -> bool {
+    let text = match cell {
+        Cell::Null => return true,
+        Cell::Json(_) => return matches!(ty, "jsonb" | "text"),
+        Cell::Text(text) => text.trim(),
+    };
+
+    match ty {
+        "boolean" => matches!(
+            text.to_lowercase().as_str(),
+            "true" | "false" | "t" | "f" | "yes" | "no" | "y" | "n" | "on" | "off"
+        ),
+        "smallint" => text.parse::<i16>().is_ok(),
+        "integer" => text.parse::<i32>().is_ok(),
+        "bigint" => text.parse::<i64>().is_ok(),
+        "numeric" => {
+            matches!(text.to_lowercase().as_str(), "nan" | "infinity" | "-infinity")
+                || (text.bytes().all(|b| b.is_ascii_digit() || b"+-.eE".contains(&b))
+                    && text.parse::<f64>().is_ok_and(f64::is_finite))
+        }
+        "date" => parse_date(text).is_some_and(|rest| rest.is_empty()),
+        "timestamp" => parse_timestamp(text).is_some_and(|zone| zone.is_empty()),
+        "timestamptz" => parse_timestamp(text).is_some_and(is_utc_offset),
+        "uuid" => {
+            text.len() == 36
+                && text.char_indices().all(|(i, c)| match i {
+                    8 | 13 | 18 | 23 => c == '-',
+                    _ => c.is_ascii_hexdigit(),
+                })
+        }
+        "jsonb" => {
+            (text.starts_with('{') || text.starts_with('[')) && serde_json::from_str::<Value>(text).is_ok()
+        }
+        _ => true,
+    }
+}
+
+/// Parse a leading `YYYY-MM-DD`, returning the rest of the text
+fn parse_date(text: &str) -> Option<&str> {
+    let (date, rest) = text.split_at_checked(10)?;
+    let mut parts = date.split('-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+
+    let year: u32 = year.parse().ok()?;
+    let month: u32 = month.parse().ok()?;
+    let day: u32 = day.parse().ok()?;
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return None,
+    };
+
+    (1..=days_in_month).contains(&day).then_some(rest)
+}
+
+/// Parse `YYYY-MM-DD[( |T)HH:MM[:SS[.fff]]]`, returning the trailing zone text
+fn parse_timestamp(text: &str) -> Option<&str> {
+    let rest = parse_date(text)?;
+    let Some(time) = rest.strip_prefix(['T', ' ']) else {
+        return Some(rest);
+    };
+
+    let digits = |s: &str, max: u32| s.len() == 2 && s.parse::<u32>().is_ok_and(|n| n <= max);
+    let (hour, time) = time.split_at_checked(2)?;
+    let time = time.strip_prefix(':')?;
+    let (minute, mut time) = time.split_at_checked(2)?;
+    if !digits(hour, 24) || !digits(minute, 59) {
+        return None;
+    }
+
+    if let Some(seconds) = time.strip_prefix(':') {
+        let (second, rest) = seconds.split_at_checked(2)?;
+        if !digits(second, 60) {
+            return None;
+        }
+        time = rest;
+        if let Some(fraction) = time.strip_prefix('.') {
+            let len = fraction.bytes().take_while(u8::is_ascii_digit).count();
+            if len == 0 {
+                return None;
+            }
+            time = &fraction[len..];
+        }
+    }
+
+    Some(time.trim_start())
+}
+
+/// Whether a timestamp's zone part is empty, `Z` or a `±HH[:MM]` offset
+fn is_utc_offset(zone: &str) -> bool {
+    let Some(offset) = zone.strip_prefix(['+', '-']) else {
+        return zone.is_empty() || zone == "Z";
+    };
+    let bytes = offset.as_bytes();
+    match bytes.len() {
+        2 => bytes.iter().all(u8::is_ascii_digit),
+        4 => bytes.iter().all(u8::is_ascii_digit),
+        5 => bytes[2] == b':' && bytes.iter().enumerate().all(|(i, b)| i == 2 || b.is_ascii_digit()),
+        _ => false,
+    }
+}
+
+/// Append a record to the COPY buffer in CSV form. Values are always quoted so that
+/// empty strings stay distinct from NULL, which COPY writes as an unquoted empty field.
+fn write_copy_row(buffer: &mut Vec<u8>, cells: &[Cell]) {
+    for (i, cell) in cells.iter().enumerate() {
+        if i > 0 {
+            buffer.push(b',');
+        }
+        if let Some(text) = cell.text() {
+            buffer.push(b'"');
+            buffer.extend_from_slice(text.replace('"', "\"\"").as_bytes());
+            buffer.push(b'"');
+        }
+    }
+    buffer.push(b'\n');
+}
+
+/// Record number of COPY data line `line`, given the records that were skipped
+fn source_row(line: usize, skipped: &[usize]) -> usize {
+    let mut row = line;
+    for &s in skipped {
+        if s <= row {
+            row += 1;
+        } else {
+            break;
+        }
+    }
+    row
+}
+
+/// Point a COPY error at the record it came from. Postgres reports the data line in the
+/// error context, e.g. `COPY users, line 42, column email: "..."`.
+fn copy_error(error: &sqlx::Error, skipped: &[usize]) -> String {
+    let line = error
+        .as_database_error()
+        .and_then(|e| e.try_downcast_ref::<PgDatabaseError>())
+        .and_then(|e| e.r#where())
+        .and_then(|context| context.split(", line ").nth(1))
+        .and_then(|rest| rest.split(|c: char| !c.is_ascii_digit()).next())
+        .and_then(|line| line.parse::<usize>().ok());
+
+    match line {
+        Some(line) => format!("Row {}: {}", source_row(line, skipped), error),
+        None => error.to_string(),
+    }
+}
+
+fn qualified_name(schema: Option<&str>, table: &str) -> String {
+    match schema {
+        Some(schema) => format!("{}.{}", quote_identifier(schema), quote_identifier(table)),
+        None => quote_identifier(table),
+    }
+}
+
+/// Ask the user for the file to import
+async fn choose_file(app: &AppHandle) -> Option<PathBuf> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    app.dialog()
+        .file()
+        .add_filter("CSV or JSON", &["csv", "tsv", "txt", "json", "ndjson", "jsonl"])
+        .pick_file(move |path| {
+            let _ = tx.send(path);
+        });
+
+    rx.await.ok().flatten().and_then(|path| path.into_path().ok())
+}
+
+/// Read the sample of a file and work out its columns and their types
+struct Sampled {
+    source: Source,
+    format: ImportFormat,
+    columns: Vec<String>,
+    inferred_types: Vec<String>,
+    sample: Vec<Result<RawRecord, String>>,
+}
+
+/// Sample a file on a blocking thread; a JSON array is parsed whole before the first record
+async fn sample_file(path: &Path, options: &ImportOptions) -> Result<Sampled, String> {
+    let (path, options) = (path.to_path_buf(), options.clone());
+    tokio::task::spawn_blocking(move || read_sample(&path, &options))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn read_sample(path: &Path, options: &ImportOptions) -> Result<Sampled, String> {
+    let format = match options.format {
+        Some(format) => format,
+        None => guess_format(path)?,
+    };
+    let (mut source, header) = Source::open(path, format, options)?;
+
+    let sample_size = options.sample_size.unwrap_or(DEFAULT_SAMPLE_SIZE).max(1);
+    let mut sample = Vec::new();
+    while sample.len() < sample_size {
+        match source.next() {
+            Some(record) => sample.push(record),
+            None => break,
+        }
+    }
+
+    let columns = column_names(header, &sample);
+    if columns.is_empty() {
+        return Err("The file has no columns to import".to_string());
+    }
+
+    let sample_rows: Vec<Vec<Cell>> = sample
+        .iter()
+        .filter_map(|record| match record {
+            Ok(record) => to_cells(record.clone(), &columns, &options.null_value).ok(),
+            Err(_) => None,
+        })
+        .collect();
+    let inferred_types = infer_types(&sample_rows, columns.len());
+
+    Ok(Sampled {
+        source,
+        format,
+        columns,
+        inferred_types,
+        sample,
+    })
+}
+
+/// Look at a file without importing it: its columns, their inferred types and the first rows
+#[tauri::command]
+pub async fn pg_preview_import(path: String, options: Option<ImportOptions>) -> Result<ImportPreview, String> {
+    let options = options.unwrap_or_default();
+    let sampled = sample_file(Path::new(&path), &options).await?;
+
+    let sample_rows = sampled
+        .sample
+        .into_iter()
+        .flatten()
+        .filter_map(|record| to_cells(record, &sampled.columns, &options.null_value).ok())
+        .take(20)
+        .map(|cells| cells.iter().map(Cell::to_json).collect())
+        .collect();
+
+    let columns = sampled
+        .columns
+        .into_iter()
+        .zip(sampled.inferred_types)
+        .map(|(name, data_type)| ImportColumn { name, data_type })
+        .collect();
+
+    Ok(ImportPreview {
+        format: sampled.format,
+        columns,
+        sample_rows,
+    })
+}
+
+/// Bulk-load a CSV, JSON or NDJSON file into a table with COPY FROM STDIN.
+/// Records that don't fit the inferred column types of a created table are skipped and reported;
+/// a value an existing column refuses fails the import at its row. The whole import
+/// runs in one transaction (or savepoint), so a failure leaves the table untouched.
+/// Without a `path` the user picks the file; returns `None` if they cancel.
+#[tauri::command]
+pub async fn pg_import_file(
+    app: AppHandle,
+    sessions: State<'_, PgSessions>,
+    connections: State<'_, SavedConnections>,
+    connection_id: String,
+    path: Option<String>,
+    options: ImportOptions,
+) -> Result<Option<ImportSummary>, String> {
+    let connection = connections.get(&connection_id).await?;
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => match choose_file(&app).await {
+            Some(path) => path,
+            None => return Ok(None),
+        },
+    };
+
+    import_file(&app, &sessions, &connection, &path, options).await.map(Some)
+}
+
+async fn import_file(
+    app: &AppHandle,
+    sessions: &PgSessions,
+    connection: &PgConnection,
+    path: &Path,
+    options: ImportOptions,
+) -> Result<ImportSummary, String> {
+    if options.table.trim().is_empty() {
+        return Err("A target table is required".to_string());
+    }
+
+    let table = qualified_name(options.schema.as_deref(), &options.table);
+    let total_bytes = tokio::fs::metadata(path).await.map(|m| m.len()).unwrap_or(0);
+    let Sampled {
+        source,
+        columns,
+        inferred_types,
+        sample,
+        ..
+    } = sample_file(path, &options).await?;
+
+    let quoted_columns: Vec<String> = columns.iter().map(|c| quote_identifier(c)).collect();
+    let copy_statement = format!("COPY {} ({}) FROM STDIN WITH (FORMAT csv)", table, quoted_columns.join(", "));
+    read_only::check(connection, &copy_statement)?;
+
+    let session = sessions.acquire(connection).await?;
+    let mut conn = session.conn.lock().await;
+    let running = sessions.track_query(&session, options.import_id.clone(), None);
+
+    let (begin, commit, rollback) = if session.in_transaction() {
+        ("SAVEPOINT pg_import", "RELEASE SAVEPOINT pg_import", "ROLLBACK TO SAVEPOINT pg_import")
+    } else {
+        ("BEGIN", "COMMIT", "ROLLBACK")
+    };
+
+    if let Err(e) = sqlx::query(begin).execute(&mut *conn).await {
+        return Err(session_error(sessions, &session, e).await);
+    }
+
+    let outcome = async {
+        let column_types: Vec<String> = if options.create_table {
+            let mut types = Vec::with_capacity(columns.len());
+            for (name, inferred) in columns.iter().zip(&inferred_types) {
+                match options.column_types.get(name) {
+                    Some(ty) => {
+                        check_type_name(&mut conn, name, ty).await?;
+                        types.push(ty.clone());
+                    }
+                    None => types.push(inferred.clone()),
+                }
+            }
+            let definitions: Vec<String> = quoted_columns
+                .iter()
+                .zip(&types)
+                .map(|(name, ty)| format!("{} {}", name, ty))
+                .collect();
+            let create = format!("CREATE TABLE {} ({})", table, definitions.join(", "));
+            sqlx::query(&create).execute(&mut *conn).await.map_err(|e| e.to_string())?;
+            types
+        } else {
+            let existing: HashMap<String, String> = sqlx::query(
+                r#"
+                SELECT a.attname::text AS name, format_type(a.atttypid, a.atttypmod) AS data_type
+                FROM pg_attribute a
+                WHERE a.attrelid = to_regclass($1) AND a.attnum > 0 AND NOT a.attisdropped
+                "#,
+            )
+            .bind(&table)
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?
+            .iter()
+            .map(|row| (row.get("name"), row.get("data_type")))
+            .collect();
+            if existing.is_empty() {
+                return Err(format!("Table {} does not exist; enable create_table to create it", table));
+            }
+
+            let missing: Vec<&str> = columns
+                .iter()
+                .filter(|c| !existing.contains_key(*c))
+                .map(String::as_str)
+                .collect();
+            if !missing.is_empty() {
+                return Err(format!("Table {} has no column(s): {}", table, missing.join(", ")));
+            }
+
+            columns.iter().map(|c| existing[c].clone()).collect()
+        };
+
+        // Values are only checked against the types inferred from them, which `fits` is exactly as strict
+        // as; declared and overridden types accept more spellings (`+02` offsets, non-ISO dates, ...),
+        // so those values are left to Postgres, whose COPY error names the row
+        let validators: Vec<Option<&str>> = columns
+            .iter()
+            .zip(&inferred_types)
+            .map(|(name, inferred)| {
+                (options.create_table && !options.column_types.contains_key(name)).then_some(inferred.as_str())
+            })
+            .collect();
+        let max_errors = options.max_errors.unwrap_or(DEFAULT_MAX_ERRORS);
+        let mut copy = conn.copy_in_raw(&copy_statement).await.map_err(|e| e.to_string())?;
+
+        let mut records = sample.into_iter();
+        let mut batches = read_in_background(source);
+        let mut bytes_read = 0;
+        let mut buffer = Vec::with_capacity(COPY_BATCH_BYTES);
+        let mut errors: Vec<RowError> = Vec::new();
+        let mut skipped: Vec<usize> = Vec::new();
+        let mut rows_read = 0;
+        let mut failure: Option<String> = None;
+
+        loop {
+            let record = match records.next() {
+                Some(record) => record,
+                None => match batches.recv().await {
+                    Some((batch, read)) => {
+                        records = batch.into_iter();
+                        bytes_read = read;
+                        continue;
+                    }
+                    None => break,
+                },
+            };
+            rows_read += 1;
+
+            let cells = record
+                .and_then(|record| to_cells(record, &columns, &options.null_value))
+                .and_then(|cells| {
+                    let typed = columns.iter().zip(&column_types).zip(&validators);
+                    for (cell, ((column, ty), validator)) in cells.iter().zip(typed) {
+                        let Some(validator) = validator else { continue };
+                        // Postgres also takes 0 and 1 for booleans, which inference doesn't
+                        let boolean_digit =
+                            *validator == "boolean" && matches!(cell, Cell::Text(t) if t == "0" || t == "1");
+                        if !fits(validator, cell) && !boolean_digit {
+                            let value = cell.text().unwrap_or_default();
+                            return Err(format!("Invalid {} value for column \"{}\": {:?}", ty, column, value));
+                        }
+                    }
+                    Ok(cells)
+                });
+
+            match cells {
+                Ok(cells) => write_copy_row(&mut buffer, &cells),
+                Err(message) => {
+                    skipped.push(rows_read);
+                    errors.push(RowError { row: rows_read, message });
+                    if errors.len() > max_errors {
+                        failure = Some(format!(
+                            "Import stopped after {} invalid rows; row {}: {}",
+                            errors.len(),
+                            errors[0].row,
+                            errors[0].message
+                        ));
+                        break;
+                    }
+                }
+            }
+
+            if buffer.len() >= COPY_BATCH_BYTES {
+                if let Err(e) = copy.send(std::mem::take(&mut buffer)).await {
+                    failure = Some(copy_error(&e, &skipped));
+                    break;
+                }
+            }
+
+            if rows_read.is_multiple_of(PROGRESS_EVERY) {
+                if running.is_cancelled() {
+                    failure = Some(QUERY_CANCELLED.to_string());
+                    break;
+                }
+                progress(app, &options, rows_read, errors.len(), bytes_read, total_bytes, false);
+            }
+        }
+
+        if failure.is_none() && !buffer.is_empty() {
+            if let Err(e) = copy.send(buffer).await {
+                failure = Some(copy_error(&e, &skipped));
+            }
+        }
+
+        let rows_imported = match failure {
+            Some(message) => {
+                let _ = copy.abort(message.clone()).await;
+                return Err(message);
+            }
+            None => copy.finish().await.map_err(|e| copy_error(&e, &skipped))?,
+        };
+
+        Ok((column_types, rows_read, rows_imported, errors))
+    }
+    .await;
+
+    // A cancel from `pg_cancel_query` also stops the COPY on the server
+    let outcome = outcome.map_err(|message| {
+        if running.is_cancelled() {
+            QUERY_CANCELLED.to_string()
+        } else {
+            message
+        }
+    });
+    drop(running);
+
+    let (column_types, rows_read, rows_imported, errors) = match outcome {
+        Ok(loaded) => loaded,
+        Err(message) => {
+            let _ = sqlx::query(rollback).execute(&mut *conn).await;
+            return Err(message);
+        }
+    };
+
+    if let Err(e) = sqlx::query(commit).execute(&mut *conn).await {
+        let _ = sqlx::query(rollback).execute(&mut *conn).await;
+        return Err(e.to_string());
+    }
+
+    progress(app, &options, rows_read, errors.len(), total_bytes, total_bytes, true);
+
+    Ok(ImportSummary {
+        path: path.display().to_string(),
+        table,
+        created_table: options.create_table,
+        columns: columns
+            .into_iter()
+            .zip(column_types)
+            .map(|(name, data_type)| ImportColumn { name, data_type })
+            .collect(),
+        rows_read,
+        rows_imported,
+        errors,
+    })
+}
+
+/// Refuse a `column_types` override that isn't a single type name, since it is written into
+/// CREATE TABLE as is. `to_regtype` parses its argument as exactly one type name: servers
+/// before 16 raise a syntax error for anything else, newer ones return NULL.
+async fn check_type_name(conn: &mut sqlx::PgConnection, column: &str, ty: &str) -> Result<(), String> {
+    let known = sqlx::query_scalar::<_, bool>("SELECT to_regtype($1) IS NOT NULL")
+        .bind(ty)
+        .fetch_one(&mut *conn)
+        .await;
+    match known {
+        Ok(true) => Ok(()),
+        Ok(false) | Err(sqlx::Error::Database(_)) => Err(format!("Unknown type \"{}\" for column \"{}\"", ty, column)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn progress(
+    app: &AppHandle,
+    options: &ImportOptions,
+    rows_read: usize,
+    errors: usize,
+    bytes_read: u64,
+    total_bytes: u64,
+    done: bool,
+) {
+    let _ = app.emit(
+        "pg-import-progress",
+        ImportProgress {
+            import_id: options.import_id.clone(),
+            rows_read,
+            errors,
+            bytes_read,
+            total_bytes,
+            done,
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(value: &str) -> Cell {
+        Cell::Text(value.to_string())
+    }
+
+    fn object(json: &str) -> Result<RawRecord, String> {
+        Ok(RawRecord::Object(serde_json::from_str(json).unwrap()))
+    }
+
+    #[test]
+    fn infers_the_most_specific_type_every_value_fits() {
+        let rows = vec![
+            vec![text("1"), text("true"), text("2020-01-02"), text("2020-01-02 03:04"), Cell::Null],
+            vec![text("3000000000"), text("no"), text("2020-02-29"), text("2020-01-02T03:04:05Z"), Cell::Null],
+            vec![text("-2"), Cell::Null, text("2021-12-31"), text("2020-01-02 03:04:05.5+02:00"), Cell::Null],
+        ];
+        assert_eq!(infer_types(&rows, 5), ["bigint", "boolean", "date", "timestamptz", "text"]);
+
+        let rows = vec![
+            vec![text("1.5"), text("{\"a\": 1}"), Cell::Json(serde_json::json!([1]))],
+            vec![text("2"), text("[]"), Cell::Json(serde_json::json!({"b": null}))],
+        ];
+        assert_eq!(infer_types(&rows, 3), ["numeric", "jsonb", "jsonb"]);
+
+        let rows = vec![vec![text("2020-01-02")], vec![text("2020-01-02 10:00")]];
+        assert_eq!(infer_types(&rows, 1), ["timestamp"]);
+        let rows = vec![vec![text("2f1c7a2e-9d9b-4a51-9b1a-3c2d1e0f9a8b")], vec![text("nope")]];
+        assert_eq!(infer_types(&rows, 1), ["text"]);
+    }
+
+    #[test]
+    fn checks_values_against_column_types() {
+        assert!(fits("integer", &text(" 42 ")));
+        assert!(!fits("integer", &text("3000000000")));
+        assert!(fits("smallint", &text("-32768")));
+        assert!(!fits("smallint", &text("32768")));
+        assert!(fits("numeric", &text("1e10")));
+        assert!(fits("numeric", &text("NaN")));
+        assert!(!fits("numeric", &text("inf")));
+        assert!(!fits("numeric", &text("0x10")));
+        assert!(fits("boolean", &text("Off")));
+        assert!(!fits("boolean", &text("1")));
+        assert!(fits("uuid", &text("2F1C7A2E-9D9B-4A51-9B1A-3C2D1E0F9A8B")));
+        assert!(!fits("uuid", &text("2f1c7a2e9d9b4a519b1a3c2d1e0f9a8b")));
+        assert!(!fits("jsonb", &text("42")));
+        assert!(fits("jsonb", &Cell::Json(serde_json::json!({"a": 1}))));
+        assert!(!fits("integer", &Cell::Json(serde_json::json!([1]))));
+        assert!(fits("integer", &Cell::Null));
+        assert!(fits("text", &text("anything")));
+    }
+
+    #[test]
+    fn parses_dates_and_timestamps() {
+        assert_eq!(parse_date("2024-02-29"), Some(""));
+        assert_eq!(parse_date("2023-02-29"), None);
+        assert_eq!(parse_date("1900-02-29"), None);
+        assert_eq!(parse_date("2000-02-29 rest"), Some(" rest"));
+        assert_eq!(parse_date("2020-13-01"), None);
+        assert_eq!(parse_date("20-01-2020"), None);
+        assert_eq!(parse_date("2020-1-1"), None);
+
+        assert_eq!(parse_timestamp("2020-01-02"), Some(""));
+        assert_eq!(parse_timestamp("2020-01-02T03:04"), Some(""));
+        assert_eq!(parse_timestamp("2020-01-02 03:04:05.123456 +02"), Some("+02"));
+        assert_eq!(parse_timestamp("2020-01-02 03:04:05Z"), Some("Z"));
+        assert_eq!(parse_timestamp("2020-01-02 25:00"), None);
+        assert_eq!(parse_timestamp("2020-01-02 03:04:05."), None);
+
+        assert!(is_utc_offset(""));
+        assert!(is_utc_offset("Z"));
+        assert!(is_utc_offset("-05"));
+        assert!(is_utc_offset("+0530"));
+        assert!(is_utc_offset("+05:30"));
+        assert!(!is_utc_offset("+5"));
+        assert!(!is_utc_offset("Europe/Paris"));
+    }
+
+    #[test]
+    fn names_columns_from_the_header_or_json_keys() {
+        let header = Some(vec!["id".to_string(), " ".to_string(), "id".to_string(), " name ".to_string()]);
+        assert_eq!(column_names(header, &[]), ["id", "column2", "id_2", "name"]);
+
+        let sample = vec![object(r#"{"b": 1, "a": 2}"#), Err("bad line".to_string()), object(r#"{"c": 3, "b": 4}"#)];
+        assert_eq!(column_names(None, &sample), ["b", "a", "c"]);
+
+        let sample = vec![Ok(RawRecord::Fields(vec!["1".to_string()])), Ok(RawRecord::Fields(vec![String::new(); 3]))];
+        assert_eq!(column_names(None, &sample), ["column1", "column2", "column3"]);
+    }
+
+    #[test]
+    fn turns_records_into_cells() {
+        let columns = vec!["a".to_string(), "b".to_string()];
+
+        let cells = to_cells(RawRecord::Fields(vec!["x".to_string(), "NULL".to_string()]), &columns, "NULL").unwrap();
+        assert_eq!(cells.iter().map(Cell::text).collect::<Vec<_>>(), [Some("x".to_string()), None]);
+        assert!(to_cells(RawRecord::Fields(vec!["x".to_string()]), &columns, "").is_err());
+
+        let cells = to_cells(object(r#"{"b": {"x": 1}}"#).unwrap(), &columns, "").unwrap();
+        assert!(matches!(cells[0], Cell::Null));
+        assert!(matches!(cells[1], Cell::Json(_)));
+
+        let error = to_cells(object(r#"{"c": 1}"#).unwrap(), &columns, "").err();
+        assert_eq!(error.as_deref(), Some("Unexpected field \"c\""));
+    }
+}
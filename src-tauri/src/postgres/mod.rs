@@ -0,0 +1,605 @@
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use sqlx::{Column, ConnectOptions, Either, Execute, Executor, Row};
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, State};
+
+pub mod connections;
+pub mod conninfo;
+mod decode;
+pub mod diagram;
+pub mod diff;
+pub mod driver;
+pub mod edit;
+pub mod explain;
+pub mod export;
+pub mod history;
+pub mod import;
+mod notices;
+mod read_only;
+pub mod schema;
+pub mod script;
+pub mod services;
+mod session;
+pub mod snippets;
+pub mod transaction;
+mod tunnel;
+
+pub use connections::{ConnectionStore, SavedConnections, StoredConnection};
+pub use history::QueryHistory;
+pub use notices::init as init_notice_capture;
+pub use services::spawn_service_watcher;
+pub use session::{spawn_idle_reaper, PgSessionInfo, PgSessions, Sessions};
+pub use snippets::SnippetLibrary;
+pub use tunnel::SshTunnel;
+pub(crate) use tunnel::Tunnel;
+
+use crate::mysql::MySqlSessions;
+use driver::{Driver, DriverKind};
+use session::{RunningQueryGuard, Session};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PgConnection {
+    pub id: String,
+    pub name: String,
+    /// Saved connections are Postgres unless they say otherwise
+    #[serde(default)]
+    pub driver: DriverKind,
+    pub host: String,
+    pub port: u16,
+    pub database: String,
+    pub user: String,
+    /// Only ever sent by the webview when saving or testing; never serialized back
+    #[serde(default, skip_serializing)]
+    pub password: String,
+    /// Folder path in the connection list, e.g. `prod/eu`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+    /// Open sessions with `default_transaction_read_only = on` and refuse mutating statements
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default)]
+    pub ssl_mode: SslMode,
+    /// CA certificate (PEM) used to verify the server with `verify-ca` and `verify-full`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssl_root_cert: Option<String>,
+    /// Client certificate (PEM) for mutual TLS; needs `ssl_client_key` as well
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssl_client_cert: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssl_client_key: Option<String>,
+    /// Reach the database through this SSH jump host
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh: Option<SshTunnel>,
+}
+
+/// Same modes and meaning as libpq's `sslmode`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SslMode {
+    Disable,
+    Allow,
+    #[default]
+    Prefer,
+    Require,
+    VerifyCa,
+    VerifyFull,
+}
+
+impl From<SslMode> for PgSslMode {
+    fn from(mode: SslMode) -> Self {
+        match mode {
+            SslMode::Disable => PgSslMode::Disable,
+            SslMode::Allow => PgSslMode::Allow,
+            SslMode::Prefer => PgSslMode::Prefer,
+            SslMode::Require => PgSslMode::Require,
+            SslMode::VerifyCa => PgSslMode::VerifyCa,
+            SslMode::VerifyFull => PgSslMode::VerifyFull,
+        }
+    }
+}
+
+// Row cap applied when the caller doesn't ask for one
+pub(crate) const DEFAULT_MAX_ROWS: usize = 10_000;
+
+// Rows per `pg-query-chunk` event when streaming
+const DEFAULT_CHUNK_SIZE: usize = 500;
+
+#[derive(Debug, Serialize, Default)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    /// Type name of each column as the driver reports it, e.g. `INT4`, `JSONB` or `TEXT[]` on Postgres
+    pub column_types: Vec<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+    pub row_count: usize,
+    /// Rows inserted, updated or deleted (or returned, for SELECT) as reported by the server
+    pub rows_affected: u64,
+    /// The query produced more rows than the row cap; only the first `row_count` were read
+    pub truncated: bool,
+}
+
+/// Per-query knobs shared by `pg_execute_query` and `pg_stream_query`
+#[derive(Debug, Deserialize, Default)]
+pub struct QueryOptions {
+    /// Stop reading after this many rows (default 10 000)
+    pub max_rows: Option<usize>,
+    /// Rows per streamed chunk (default 500)
+    pub chunk_size: Option<usize>,
+    /// Id used to cancel the query with `pg_cancel_query`
+    pub query_id: Option<String>,
+    /// Cancel the query if it runs longer than this
+    pub statement_timeout_ms: Option<u64>,
+}
+
+/// Payload of the `pg-query-chunk` event emitted by `pg_stream_query`
+#[derive(Debug, Serialize, Clone)]
+pub struct QueryChunk {
+    pub query_id: String,
+    pub columns: Vec<String>,
+    pub column_types: Vec<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+    pub done: bool,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TableInfo {
+    pub schema: String,
+    pub name: String,
+    #[serde(default)]
+    pub table_type: String,
+}
+
+/// Build connect options field by field, so no value needs URL escaping
+fn connect_options(connection: &PgConnection) -> Result<PgConnectOptions, String> {
+    let mut options = PgConnectOptions::new_without_pgpass()
+        .host(&connection.host)
+        .port(connection.port)
+        .database(&connection.database)
+        .username(&connection.user)
+        .ssl_mode(connection.ssl_mode.into());
+
+    if !connection.password.is_empty() {
+        options = options.password(&connection.password);
+    }
+
+    let certificates = [&connection.ssl_root_cert, &connection.ssl_client_cert, &connection.ssl_client_key];
+    for path in certificates.into_iter().flatten() {
+        if !std::path::Path::new(path).is_file() {
+            return Err(format!("Certificate file not found: {}", path));
+        }
+    }
+
+    if let Some(path) = &connection.ssl_root_cert {
+        options = options.ssl_root_cert(path);
+    }
+
+    match (&connection.ssl_client_cert, &connection.ssl_client_key) {
+        (Some(cert), Some(key)) => options = options.ssl_client_cert(cert).ssl_client_key(key),
+        (None, None) => {}
+        _ => return Err("A client certificate and its key must be given together".to_string()),
+    }
+
+    if connection.read_only {
+        options = options.options([("default_transaction_read_only", "on")]);
+    }
+
+    Ok(options)
+}
+
+/// Open a single dedicated connection for a saved connection, through its SSH tunnel if it has one
+async fn connect(connection: &PgConnection, tunnel: Option<&Tunnel>) -> Result<sqlx::PgConnection, String> {
+    let mut options = connect_options(connection)?;
+    if let Some(tunnel) = tunnel {
+        options = options.host("127.0.0.1").port(tunnel.local_port);
+    }
+
+    options.connect().await.map_err(|e| e.to_string())
+}
+
+/// Double-quote an identifier for use in generated SQL
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Type to cast a value bound as text to, given its type as `format_type(oid, NULL)` names it.
+/// Without a length `character` and `bit` mean a length of one, so the cast would cut the value;
+/// `bpchar` and `varbit` keep it whole and leave the length check to the column.
+fn cast_type(name: &str) -> String {
+    match name {
+        "character" => "bpchar",
+        "character[]" => "bpchar[]",
+        "bit" => "varbit",
+        "bit[]" => "varbit[]",
+        other => other,
+    }
+    .to_string()
+}
+
+/// Run queries in order reading at most `max_rows` rows in all, handing decoded rows to `on_chunk`
+/// in batches. Reading stops early when `on_chunk` breaks. The returned result holds the column
+/// metadata and counters but no rows. `in_transaction` tells whether the session has a
+/// transaction open, which decides how a capped query's cursor is closed.
+async fn stream_rows<'q, D, E, F>(
+    conn: &mut D,
+    queries: impl IntoIterator<Item = E>,
+    max_rows: usize,
+    chunk_size: usize,
+    in_transaction: bool,
+    mut on_chunk: F,
+) -> Result<QueryResult, sqlx::Error>
+where
+    D: Driver,
+    for<'c> &'c mut D: Executor<'c, Database = D::Database>,
+    E: Execute<'q, D::Database> + 'q,
+    F: FnMut(&QueryResult, Vec<Vec<serde_json::Value>>) -> ControlFlow<()>,
+{
+    let mut result = QueryResult::default();
+    let mut chunk = Vec::new();
+
+    for mut query in queries {
+        // With a cursor the server stops at the cap too, instead of sending rows that are thrown away.
+        // One row past the cap is fetched to tell whether there were more.
+        let fetch = match max_rows {
+            usize::MAX => None,
+            _ => {
+                let rows = max_rows.saturating_sub(result.row_count) + 1;
+                conn.declare_cursor(&mut query, rows, in_transaction).await?
+            }
+        };
+
+        let mut steps = match &fetch {
+            Some(fetch) => conn.fetch_many(D::statement(fetch)),
+            None => conn.fetch_many(query),
+        };
+
+        let mut stopped = false;
+        let outcome = async {
+            while let Some(step) = steps.try_next().await? {
+                let row = match step {
+                    Either::Left(done) => {
+                        result.rows_affected += D::rows_affected(&done);
+                        continue;
+                    }
+                    Either::Right(row) => row,
+                };
+
+                if result.row_count == max_rows {
+                    // Remaining rows are discarded by sqlx without being decoded
+                    result.truncated = true;
+                    break;
+                }
+
+                if result.row_count == 0 {
+                    for column in row.columns() {
+                        result.columns.push(column.name().to_string());
+                        result.column_types.push(D::type_name(column));
+                    }
+                }
+
+                chunk.push(D::row_to_json(&row));
+                result.row_count += 1;
+
+                if chunk.len() >= chunk_size && on_chunk(&result, std::mem::take(&mut chunk)).is_break() {
+                    stopped = true;
+                    break;
+                }
+            }
+            Ok::<_, sqlx::Error>(())
+        }
+        .await;
+        drop(steps);
+
+        if fetch.is_some() {
+            let closed = conn.close_cursor(in_transaction, outcome.is_ok()).await;
+            outcome?;
+            closed?;
+        } else {
+            outcome?;
+        }
+
+        if stopped {
+            return Ok(result);
+        }
+    }
+
+    if !chunk.is_empty() {
+        let _ = on_chunk(&result, chunk);
+    }
+
+    Ok(result)
+}
+
+/// Convert a query error to a message, dropping the session if its connection is broken
+async fn session_error<D: Driver>(sessions: &Sessions<D>, session: &Session<D>, error: sqlx::Error) -> String {
+    if matches!(
+        error,
+        sqlx::Error::Io(_) | sqlx::Error::Tls(_) | sqlx::Error::Protocol(_) | sqlx::Error::WorkerCrashed
+    ) {
+        sessions.discard(session).await;
+    }
+    error.to_string()
+}
+
+/// Like `session_error`, but reports a cancelled query as such
+async fn query_error<D: Driver>(
+    sessions: &Sessions<D>,
+    session: &Session<D>,
+    running: &RunningQueryGuard,
+    error: sqlx::Error,
+) -> String {
+    match running.cancel_error(&error) {
+        Some(message) => message,
+        None => session_error(sessions, session, error).await,
+    }
+}
+
+/// Try connection settings before they are saved. An empty password on a saved
+/// connection means its stored password, as when editing it.
+#[tauri::command]
+pub async fn pg_test_connection(
+    connections: State<'_, SavedConnections>,
+    mut connection: PgConnection,
+) -> Result<String, String> {
+    if connection.password.is_empty() {
+        if let Ok(saved) = connections.get(&connection.id).await {
+            connection.password = saved.password;
+        }
+    }
+
+    let tunnel = Tunnel::open_for(&connection).await?;
+    match connection.driver {
+        DriverKind::Postgres => test_connection::<sqlx::PgConnection>(&connection, tunnel.as_ref()).await?,
+        DriverKind::Mysql => test_connection::<sqlx::MySqlConnection>(&connection, tunnel.as_ref()).await?,
+    }
+
+    Ok("Connection successful!".to_string())
+}
+
+async fn test_connection<D: Driver>(connection: &PgConnection, tunnel: Option<&Tunnel>) -> Result<(), String> {
+    let mut conn = D::open(connection, tunnel).await?;
+    conn.ping().await.map_err(|e| e.to_string())?;
+    let _ = conn.close().await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn pg_open_session(
+    sessions: State<'_, PgSessions>,
+    mysql_sessions: State<'_, MySqlSessions>,
+    connections: State<'_, SavedConnections>,
+    connection_id: String,
+    idle_timeout_secs: Option<u64>,
+) -> Result<PgSessionInfo, String> {
+    let connection = connections.get(&connection_id).await?;
+    let idle_timeout = idle_timeout_secs.map(Duration::from_secs);
+    match connection.driver {
+        DriverKind::Postgres => Ok(sessions.open(&connection, idle_timeout).await?.info()),
+        DriverKind::Mysql => Ok(mysql_sessions.open(&connection, idle_timeout).await?.info()),
+    }
+}
+
+#[tauri::command]
+pub async fn pg_close_session(
+    sessions: State<'_, PgSessions>,
+    mysql_sessions: State<'_, MySqlSessions>,
+    connection_id: String,
+) -> Result<bool, String> {
+    Ok(sessions.close(&connection_id).await || mysql_sessions.close(&connection_id).await)
+}
+
+#[tauri::command]
+pub async fn pg_list_sessions(
+    sessions: State<'_, PgSessions>,
+    mysql_sessions: State<'_, MySqlSessions>,
+) -> Result<Vec<PgSessionInfo>, String> {
+    let mut list = sessions.list().await;
+    list.extend(mysql_sessions.list().await);
+    list.sort_by(|a, b| a.connection_id.cmp(&b.connection_id));
+    Ok(list)
+}
+
+#[tauri::command]
+pub async fn pg_get_tables(
+    sessions: State<'_, PgSessions>,
+    mysql_sessions: State<'_, MySqlSessions>,
+    connections: State<'_, SavedConnections>,
+    connection_id: String,
+) -> Result<Vec<TableInfo>, String> {
+    let connection = connections.get(&connection_id).await?;
+    match connection.driver {
+        DriverKind::Postgres => get_tables(&sessions, &connection).await,
+        DriverKind::Mysql => get_tables(&mysql_sessions, &connection).await,
+    }
+}
+
+async fn get_tables<D: Driver>(sessions: &Sessions<D>, connection: &PgConnection) -> Result<Vec<TableInfo>, String>
+where
+    for<'c> &'c mut D: Executor<'c, Database = D::Database>,
+{
+    let session = sessions.acquire(connection).await?;
+    let mut conn = session.conn.lock().await;
+
+    match conn.list_tables().await {
+        Ok(tables) => Ok(tables),
+        Err(e) => Err(session_error(sessions, &session, e).await),
+    }
+}
+
+#[tauri::command]
+pub async fn pg_execute_query(
+    sessions: State<'_, PgSessions>,
+    mysql_sessions: State<'_, MySqlSessions>,
+    connections: State<'_, SavedConnections>,
+    history: State<'_, QueryHistory>,
+    connection_id: String,
+    query: String,
+    options: Option<QueryOptions>,
+) -> Result<QueryResult, String> {
+    let connection = connections.get(&connection_id).await?;
+    let options = options.unwrap_or_default();
+
+    let started = Instant::now();
+    let result = match connection.driver {
+        DriverKind::Postgres => execute_sql(&sessions, &connection, &query, options).await,
+        DriverKind::Mysql => execute_sql(&mysql_sessions, &connection, &query, options).await,
+    };
+    history.record(&connection.id, &query, None, started, &result).await;
+    result
+}
+
+/// Run plain SQL, one or more statements, and collect its rows up to the row cap
+async fn execute_sql<D: Driver>(
+    sessions: &Sessions<D>,
+    connection: &PgConnection,
+    sql: &str,
+    options: QueryOptions,
+) -> Result<QueryResult, String>
+where
+    for<'c> &'c mut D: Executor<'c, Database = D::Database>,
+{
+    read_only::check(connection, sql)?;
+    execute_query(sessions, connection, D::statements(sql), options).await
+}
+
+/// Run queries in order and collect their rows, up to the row cap. Each query is a
+/// statement of plain SQL or a `sqlx::query_with` with bound parameters.
+async fn execute_query<'q, D, E>(
+    sessions: &Sessions<D>,
+    connection: &PgConnection,
+    queries: Vec<E>,
+    options: QueryOptions,
+) -> Result<QueryResult, String>
+where
+    D: Driver,
+    for<'c> &'c mut D: Executor<'c, Database = D::Database>,
+    E: Execute<'q, D::Database> + 'q,
+{
+    for query in &queries {
+        read_only::check(connection, query.sql())?;
+        transaction::check_statements(query.sql(), connection.driver)?;
+    }
+
+    let session = sessions.acquire(connection).await?;
+    let mut conn = session.conn.lock().await;
+    let running = sessions.track_query(
+        &session,
+        options.query_id,
+        options.statement_timeout_ms.map(Duration::from_millis),
+    );
+
+    let mut rows = Vec::new();
+    let max_rows = options.max_rows.unwrap_or(DEFAULT_MAX_ROWS);
+
+    let collect = |_: &QueryResult, chunk| {
+        rows.extend(chunk);
+        ControlFlow::Continue(())
+    };
+
+    let in_transaction = session.in_transaction();
+    let mut result = match stream_rows(&mut *conn, queries, max_rows, usize::MAX, in_transaction, collect).await {
+        Ok(result) => result,
+        Err(e) => return Err(query_error(sessions, &session, &running, e).await),
+    };
+
+    result.rows = rows;
+    Ok(result)
+}
+
+/// Run a query and push its rows to the webview as `pg-query-chunk` events tagged with `query_id`.
+/// Returns the column metadata and row count once the last chunk has been sent.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn pg_stream_query(
+    app: AppHandle,
+    sessions: State<'_, PgSessions>,
+    mysql_sessions: State<'_, MySqlSessions>,
+    connections: State<'_, SavedConnections>,
+    history: State<'_, QueryHistory>,
+    connection_id: String,
+    query: String,
+    query_id: String,
+    options: Option<QueryOptions>,
+) -> Result<QueryResult, String> {
+    let connection = connections.get(&connection_id).await?;
+    let options = options.unwrap_or_default();
+
+    let started = Instant::now();
+    let result = match connection.driver {
+        DriverKind::Postgres => stream_query(&app, &sessions, &connection, &query, query_id, options).await,
+        DriverKind::Mysql => stream_query(&app, &mysql_sessions, &connection, &query, query_id, options).await,
+    };
+    history.record(&connection.id, &query, None, started, &result).await;
+    result
+}
+
+async fn stream_query<D: Driver>(
+    app: &AppHandle,
+    sessions: &Sessions<D>,
+    connection: &PgConnection,
+    query: &str,
+    query_id: String,
+    options: QueryOptions,
+) -> Result<QueryResult, String>
+where
+    for<'c> &'c mut D: Executor<'c, Database = D::Database>,
+{
+    read_only::check(connection, query)?;
+    transaction::check_statements(query, connection.driver)?;
+
+    let session = sessions.acquire(connection).await?;
+    let mut conn = session.conn.lock().await;
+    let running = sessions.track_query(
+        &session,
+        Some(query_id.clone()),
+        options.statement_timeout_ms.map(Duration::from_millis),
+    );
+
+    let max_rows = options.max_rows.unwrap_or(DEFAULT_MAX_ROWS);
+    let chunk_size = options.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE).max(1);
+
+    let emit_chunk = |result: &QueryResult, rows: Vec<Vec<serde_json::Value>>, done: bool| {
+        let _ = app.emit(
+            "pg-query-chunk",
+            QueryChunk {
+                query_id: query_id.clone(),
+                columns: result.columns.clone(),
+                column_types: result.column_types.clone(),
+                rows,
+                done,
+                truncated: result.truncated,
+            },
+        );
+    };
+
+    let queries = D::statements(query);
+    let in_transaction = session.in_transaction();
+    let result = match stream_rows(&mut *conn, queries, max_rows, chunk_size, in_transaction, |result, rows| {
+        emit_chunk(result, rows, false);
+        ControlFlow::Continue(())
+    })
+    .await
+    {
+        Ok(result) => result,
+        Err(e) => return Err(query_error(sessions, &session, &running, e).await),
+    };
+
+    emit_chunk(&result, vec![], true);
+
+    Ok(result)
+}
+
+/// Cancel a query started with the given `query_id`; it then fails with "Query cancelled"
+#[tauri::command]
+pub async fn pg_cancel_query(
+    sessions: State<'_, PgSessions>,
+    mysql_sessions: State<'_, MySqlSessions>,
+    query_id: String,
+) -> Result<bool, String> {
+    if sessions.cancel_query(&query_id).await? {
+        return Ok(true);
+    }
+    mysql_sessions.cancel_query(&query_id).await
+}
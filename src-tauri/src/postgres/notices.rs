@@ -0,0 +1,70 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tracing::{field::Field, Event, Level, Subscriber};
+use tracing_subscriber::filter::{filter_fn, EnvFilter};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+
+// sqlx reports NOTICE/WARNING messages from the server only as tracing events on this target
+const NOTICE_TARGET: &str = "sqlx::postgres::notice";
+
+tokio::task_local! {
+    static NOTICE_SINK: Arc<Mutex<Vec<String>>>;
+}
+
+/// Install the global subscriber. Server notices go to `capture`; every other event is
+/// logged to stderr as `RUST_LOG` asks (errors only by default). Does nothing if a
+/// subscriber is already installed.
+pub fn init() {
+    let notices = NoticeLayer.with_filter(filter_fn(|metadata| metadata.target() == NOTICE_TARGET));
+    let log = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_filter(EnvFilter::from_default_env());
+    let _ = tracing_subscriber::registry().with(notices).with(log).try_init();
+}
+
+/// Run `fut`, collecting the notices the server sends while it is polled
+pub async fn capture<F: Future>(fut: F) -> (F::Output, Vec<String>) {
+    let sink = Arc::new(Mutex::new(Vec::new()));
+    let output = NOTICE_SINK.scope(sink.clone(), fut).await;
+    let notices = std::mem::take(&mut *sink.lock().unwrap());
+    (output, notices)
+}
+
+struct NoticeLayer;
+
+impl<S: Subscriber> Layer<S> for NoticeLayer {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        // Map the tracing level back to the Postgres severity sqlx derived it from
+        let severity = match *event.metadata().level() {
+            Level::ERROR => "ERROR",
+            Level::WARN => "WARNING",
+            Level::INFO => "NOTICE",
+            Level::DEBUG => "DEBUG",
+            Level::TRACE => "INFO",
+        };
+
+        let mut message = MessageVisitor(String::new());
+        event.record(&mut message);
+
+        let _ = NOTICE_SINK.try_with(|sink| {
+            sink.lock().unwrap().push(format!("{}: {}", severity, message.0));
+        });
+    }
+}
+
+struct MessageVisitor(String);
+
+impl tracing::field::Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0 = value.to_string();
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use super::{script, PgConnection};
+
+// Statements that write through a data-modifying CTE, SELECT INTO or a row lock
+const DATA_MODIFYING: [&str; 5] = ["INSERT", "UPDATE", "DELETE", "MERGE", "INTO"];
+
+// Words that switch the session or a transaction back to read-write
+const READ_WRITE_SWITCHES: [&str; 4] = [
+    "DEFAULT_TRANSACTION_READ_ONLY",
+    "TRANSACTION_READ_ONLY",
+    "CHARACTERISTICS",
+    "SET_CONFIG",
+];
+
+/// Reject mutating statements before they are sent on a read-only connection.
+/// Sessions of read-only connections also run with `default_transaction_read_only = on`,
+/// so anything this misses (e.g. a SELECT calling a function that writes) still fails on the server.
+pub fn check(connection: &PgConnection, sql: &str) -> Result<(), String> {
+    if !connection.read_only {
+        return Ok(());
+    }
+
+    for statement in script::split_statements(sql, connection.driver) {
+        if let Some(keyword) = mutating_keyword(&script::keywords(statement, connection.driver)) {
+            return Err(format!(
+                "Connection \"{}\" is read-only; {} is not allowed",
+                connection.name, keyword
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// The word that makes a statement mutating, if it is one
+fn mutating_keyword(words: &[String]) -> Option<&str> {
+    let first = words.first()?.as_str();
+    let find = |candidates: &[&str]| {
+        words
+            .iter()
+            .map(String::as_str)
+            .find(|word| candidates.contains(word))
+    };
+
+    if first != "SHOW" {
+        if let Some(word) = find(&READ_WRITE_SWITCHES) {
+            return Some(word);
+        }
+        if words.windows(2).any(|pair| pair[0] == "READ" && pair[1] == "WRITE") {
+            return Some("READ WRITE");
+        }
+    }
+
+    match first {
+        "SELECT" | "WITH" | "VALUES" | "TABLE" | "DECLARE" => find(&DATA_MODIFYING),
+        // Plain EXPLAIN only plans the statement; EXPLAIN ANALYZE runs it
+        "EXPLAIN" if find(&["ANALYZE", "ANALYSE"]).is_some() => find(&DATA_MODIFYING),
+        "EXPLAIN" | "SHOW" | "SET" | "RESET" | "BEGIN" | "START" | "COMMIT" | "END" | "ROLLBACK" | "ABORT"
+        | "SAVEPOINT" | "RELEASE" | "FETCH" | "MOVE" | "CLOSE" | "LISTEN" | "UNLISTEN" | "DISCARD"
+        | "DEALLOCATE" => None,
+        // MySQL's ways of looking at the schema and switching databases
+        "DESCRIBE" | "DESC" | "USE" => None,
+        _ => Some(first),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection(driver: &str) -> PgConnection {
+        serde_json::from_value(serde_json::json!({
+            "id": "c1", "name": "prod", "driver": driver, "host": "localhost", "port": 5432,
+            "database": "app", "user": "app", "read_only": true,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn refuses_mutating_postgres_statements() {
+        let postgres = connection("postgres");
+        for sql in [
+            "delete from t",
+            "SELECT 1; UPDATE t SET a = 1",
+            "WITH d AS (DELETE FROM t RETURNING *) SELECT * FROM d",
+            "SELECT * INTO t2 FROM t",
+            "SET default_transaction_read_only = off",
+            "BEGIN READ WRITE",
+            "EXPLAIN ANALYZE DELETE FROM t",
+            "CREATE TABLE t (a int)",
+        ] {
+            assert!(check(&postgres, sql).is_err(), "{}", sql);
+        }
+        assert_eq!(
+            check(&postgres, "TRUNCATE t").unwrap_err(),
+            "Connection \"prod\" is read-only; TRUNCATE is not allowed"
+        );
+    }
+
+    #[test]
+    fn allows_reading_postgres_statements() {
+        let postgres = connection("postgres");
+        for sql in [
+            "SELECT 'delete', \"update\" FROM t",
+            "select $$ drop table t $$",
+            "SELECT E'\\' insert'",
+            "/* delete */ SELECT 1 -- update",
+            "EXPLAIN DELETE FROM t",
+            "SHOW transaction_read_only",
+            "BEGIN; SELECT 1; COMMIT",
+        ] {
+            assert!(check(&postgres, sql).is_ok(), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn lexes_mysql_statements_as_mysql() {
+        let mysql = connection("mysql");
+        for sql in [
+            "SELECT `update` FROM t",
+            "SELECT 'it\\'s; delete' FROM t",
+            "SELECT \"drop\" FROM t",
+            "SELECT 1 # delete\nFROM t",
+            "DESCRIBE t",
+            "USE app",
+        ] {
+            assert!(check(&mysql, sql).is_ok(), "{}", sql);
+        }
+        for sql in [
+            "UPDATE t SET a = 1",
+            "SELECT 'x\\''; DELETE FROM t",
+            "/*!50000 DELETE */ FROM t",
+            "SELECT 1 --no comment\n; DROP TABLE t",
+        ] {
+            assert!(check(&mysql, sql).is_err(), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn allows_everything_on_writable_connections() {
+        let mut postgres = connection("postgres");
+        postgres.read_only = false;
+        assert!(check(&postgres, "DROP TABLE t").is_ok());
+    }
+}
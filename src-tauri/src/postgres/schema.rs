@@ -0,0 +1,546 @@
+use serde::Serialize;
+use sqlx::postgres::PgRow;
+use sqlx::Row;
+use std::collections::HashMap;
+use tauri::State;
+
+use super::{session_error, PgSessions, SavedConnections};
+
+#[derive(Debug, Serialize)]
+pub struct ColumnDetails {
+    pub name: String,
+    /// 1-based position in the table
+    pub position: i32,
+    /// Type as written in DDL, e.g. `character varying(255)` or `integer[]`
+    pub data_type: String,
+    pub nullable: bool,
+    pub default_value: Option<String>,
+    pub is_identity: bool,
+    pub is_generated: bool,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PrimaryKey {
+    pub name: String,
+    pub columns: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ForeignKey {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub referenced_schema: String,
+    pub referenced_table: String,
+    pub referenced_columns: Vec<String>,
+    /// Referential action, e.g. `NO ACTION` or `CASCADE`
+    pub on_update: String,
+    pub on_delete: String,
+}
+
+/// Any table constraint: `PRIMARY KEY`, `FOREIGN KEY`, `UNIQUE`, `CHECK` or `EXCLUDE`
+#[derive(Debug, Serialize)]
+pub struct ConstraintInfo {
+    pub name: String,
+    pub constraint_type: String,
+    pub columns: Vec<String>,
+    /// Definition as reported by `pg_get_constraintdef`, e.g. `CHECK ((price > 0))`
+    pub definition: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IndexInfo {
+    pub name: String,
+    /// Key columns or expressions, followed by INCLUDE columns
+    pub columns: Vec<String>,
+    pub is_unique: bool,
+    pub is_primary: bool,
+    /// Access method, e.g. `btree` or `gin`
+    pub method: String,
+    /// WHERE clause of a partial index
+    pub predicate: Option<String>,
+    pub definition: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableDetails {
+    pub schema: String,
+    pub name: String,
+    /// `BASE TABLE`, `PARTITIONED TABLE`, `VIEW`, `MATERIALIZED VIEW` or `FOREIGN TABLE`
+    pub table_type: String,
+    pub owner: String,
+    pub comment: Option<String>,
+    /// Planner estimate; `None` if the table has never been analyzed
+    pub estimated_rows: Option<i64>,
+    /// Query of a view or materialized view
+    pub view_definition: Option<String>,
+    pub columns: Vec<ColumnDetails>,
+    pub primary_key: Option<PrimaryKey>,
+    pub foreign_keys: Vec<ForeignKey>,
+    pub indexes: Vec<IndexInfo>,
+    pub constraints: Vec<ConstraintInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FunctionInfo {
+    pub name: String,
+    /// `function`, `procedure`, `aggregate` or `window`
+    pub kind: String,
+    /// Argument list as written in DDL, e.g. `a integer, b text DEFAULT 'x'::text`
+    pub arguments: String,
+    /// Arguments that identify the function in `DROP` or `ALTER`, without defaults
+    pub identity_arguments: String,
+    /// `None` for procedures
+    pub result_type: Option<String>,
+    pub language: String,
+    pub comment: Option<String>,
+    /// Full `CREATE OR REPLACE` statement; `None` for aggregates
+    pub definition: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SequenceInfo {
+    pub name: String,
+    pub data_type: String,
+    pub start_value: i64,
+    pub increment_by: i64,
+    pub min_value: i64,
+    pub max_value: i64,
+    pub cycle: bool,
+    /// `None` if the sequence was never used or can't be read by the current user
+    pub last_value: Option<i64>,
+    /// Created implicitly for an identity column
+    pub is_identity: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EnumInfo {
+    pub name: String,
+    /// Labels in sort order
+    pub values: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SchemaDetails {
+    pub name: String,
+    pub tables: Vec<TableDetails>,
+    pub functions: Vec<FunctionInfo>,
+    pub sequences: Vec<SequenceInfo>,
+    pub enums: Vec<EnumInfo>,
+}
+
+// Each relation query takes the schema as $1 and an optional table name as $2
+
+const RELATIONS_QUERY: &str = r#"
+    SELECT
+        c.relname::text AS name,
+        c.relkind::text AS kind,
+        pg_get_userbyid(c.relowner)::text AS owner,
+        obj_description(c.oid, 'pg_class') AS comment,
+        CASE WHEN c.reltuples >= 0 THEN c.reltuples::int8 END AS estimated_rows,
+        CASE WHEN c.relkind IN ('v', 'm') THEN pg_get_viewdef(c.oid, true) END AS view_definition
+    FROM pg_class c
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    WHERE n.nspname = $1
+      AND ($2::text IS NULL OR c.relname = $2)
+      AND c.relkind IN ('r', 'p', 'v', 'm', 'f')
+    ORDER BY c.relname
+"#;
+
+const COLUMNS_QUERY: &str = r#"
+    SELECT
+        c.relname::text AS table_name,
+        a.attname::text AS name,
+        a.attnum::int4 AS position,
+        format_type(a.atttypid, a.atttypmod) AS data_type,
+        NOT a.attnotnull AS nullable,
+        pg_get_expr(d.adbin, d.adrelid) AS default_value,
+        a.attidentity <> '' AS is_identity,
+        a.attgenerated <> '' AS is_generated,
+        col_description(c.oid, a.attnum) AS comment
+    FROM pg_attribute a
+    JOIN pg_class c ON c.oid = a.attrelid
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    LEFT JOIN pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum
+    WHERE n.nspname = $1
+      AND ($2::text IS NULL OR c.relname = $2)
+      AND c.relkind IN ('r', 'p', 'v', 'm', 'f')
+      AND a.attnum > 0
+      AND NOT a.attisdropped
+    ORDER BY c.relname, a.attnum
+"#;
+
+const CONSTRAINTS_QUERY: &str = r#"
+    SELECT
+        c.relname::text AS table_name,
+        con.conname::text AS name,
+        con.contype::text AS kind,
+        ARRAY(
+            SELECT a.attname::text
+            FROM unnest(con.conkey) WITH ORDINALITY AS k(attnum, ord)
+            JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = k.attnum
+            ORDER BY k.ord
+        ) AS columns,
+        fn.nspname::text AS referenced_schema,
+        fc.relname::text AS referenced_table,
+        ARRAY(
+            SELECT a.attname::text
+            FROM unnest(con.confkey) WITH ORDINALITY AS k(attnum, ord)
+            JOIN pg_attribute a ON a.attrelid = con.confrelid AND a.attnum = k.attnum
+            ORDER BY k.ord
+        ) AS referenced_columns,
+        con.confupdtype::text AS on_update,
+        con.confdeltype::text AS on_delete,
+        pg_get_constraintdef(con.oid, true) AS definition
+    FROM pg_constraint con
+    JOIN pg_class c ON c.oid = con.conrelid
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    LEFT JOIN pg_class fc ON fc.oid = con.confrelid
+    LEFT JOIN pg_namespace fn ON fn.oid = fc.relnamespace
+    WHERE n.nspname = $1
+      AND ($2::text IS NULL OR c.relname = $2)
+      AND con.contype IN ('p', 'f', 'u', 'c', 'x')
+    ORDER BY c.relname, con.conname
+"#;
+
+const INDEXES_QUERY: &str = r#"
+    SELECT
+        c.relname::text AS table_name,
+        i.relname::text AS name,
+        ARRAY(
+            SELECT pg_get_indexdef(ix.indexrelid, k, true)
+            FROM generate_series(1, ix.indnatts) AS k
+        ) AS columns,
+        ix.indisunique AS is_unique,
+        ix.indisprimary AS is_primary,
+        am.amname::text AS method,
+        pg_get_expr(ix.indpred, ix.indrelid, true) AS predicate,
+        pg_get_indexdef(ix.indexrelid) AS definition
+    FROM pg_index ix
+    JOIN pg_class i ON i.oid = ix.indexrelid
+    JOIN pg_class c ON c.oid = ix.indrelid
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    JOIN pg_am am ON am.oid = i.relam
+    WHERE n.nspname = $1
+      AND ($2::text IS NULL OR c.relname = $2)
+    ORDER BY c.relname, i.relname
+"#;
+
+const FUNCTIONS_QUERY: &str = r#"
+    SELECT
+        p.proname::text AS name,
+        p.prokind::text AS kind,
+        pg_get_function_arguments(p.oid) AS arguments,
+        pg_get_function_identity_arguments(p.oid) AS identity_arguments,
+        pg_get_function_result(p.oid) AS result_type,
+        l.lanname::text AS language,
+        obj_description(p.oid, 'pg_proc') AS comment,
+        CASE WHEN p.prokind IN ('f', 'p') THEN pg_get_functiondef(p.oid) END AS definition
+    FROM pg_proc p
+    JOIN pg_namespace n ON n.oid = p.pronamespace
+    JOIN pg_language l ON l.oid = p.prolang
+    WHERE n.nspname = $1
+    ORDER BY p.proname, pg_get_function_arguments(p.oid)
+"#;
+
+const SEQUENCES_QUERY: &str = r#"
+    SELECT
+        sequencename::text AS name,
+        data_type::text AS data_type,
+        start_value,
+        increment_by,
+        min_value,
+        max_value,
+        cycle,
+        last_value,
+        EXISTS (
+            SELECT 1
+            FROM pg_depend d
+            WHERE d.classid = 'pg_class'::regclass
+              AND d.objid = format('%I.%I', schemaname, sequencename)::regclass
+              AND d.deptype = 'i'
+        ) AS is_identity
+    FROM pg_sequences
+    WHERE schemaname = $1
+    ORDER BY sequencename
+"#;
+
+const ENUMS_QUERY: &str = r#"
+    SELECT
+        t.typname::text AS name,
+        array_agg(e.enumlabel::text ORDER BY e.enumsortorder) AS values
+    FROM pg_type t
+    JOIN pg_enum e ON e.enumtypid = t.oid
+    JOIN pg_namespace n ON n.oid = t.typnamespace
+    WHERE n.nspname = $1
+    GROUP BY t.typname
+    ORDER BY t.typname
+"#;
+
+/// Load tables and views of a schema (or just `table`) with their columns, keys, indexes and constraints
+pub(crate) async fn load_tables(
+    conn: &mut sqlx::PgConnection,
+    schema: &str,
+    table: Option<&str>,
+) -> Result<Vec<TableDetails>, sqlx::Error> {
+    let fetch = |sql: &'static str| sqlx::query(sql).bind(schema).bind(table);
+
+    let mut tables: Vec<TableDetails> = fetch(RELATIONS_QUERY)
+        .fetch_all(&mut *conn)
+        .await?
+        .iter()
+        .map(|row| TableDetails {
+            schema: schema.to_string(),
+            name: row.get("name"),
+            table_type: relation_type(row.get("kind")).to_string(),
+            owner: row.get("owner"),
+            comment: row.get("comment"),
+            estimated_rows: row.get("estimated_rows"),
+            view_definition: row.get("view_definition"),
+            columns: Vec::new(),
+            primary_key: None,
+            foreign_keys: Vec::new(),
+            indexes: Vec::new(),
+            constraints: Vec::new(),
+        })
+        .collect();
+
+    let positions: HashMap<String, usize> = tables
+        .iter()
+        .enumerate()
+        .map(|(i, t)| (t.name.clone(), i))
+        .collect();
+    let owner = |row: &PgRow| positions.get(&row.get::<String, _>("table_name")).copied();
+
+    for row in fetch(COLUMNS_QUERY).fetch_all(&mut *conn).await? {
+        let Some(i) = owner(&row) else { continue };
+        tables[i].columns.push(ColumnDetails {
+            name: row.get("name"),
+            position: row.get("position"),
+            data_type: row.get("data_type"),
+            nullable: row.get("nullable"),
+            default_value: row.get("default_value"),
+            is_identity: row.get("is_identity"),
+            is_generated: row.get("is_generated"),
+            comment: row.get("comment"),
+        });
+    }
+
+    for row in fetch(CONSTRAINTS_QUERY).fetch_all(&mut *conn).await? {
+        let Some(i) = owner(&row) else { continue };
+        let table = &mut tables[i];
+        let kind: String = row.get("kind");
+        let name: String = row.get("name");
+        let columns: Vec<String> = row.get("columns");
+
+        match kind.as_str() {
+            "p" => {
+                table.primary_key = Some(PrimaryKey {
+                    name: name.clone(),
+                    columns: columns.clone(),
+                })
+            }
+            "f" => table.foreign_keys.push(ForeignKey {
+                name: name.clone(),
+                columns: columns.clone(),
+                referenced_schema: row.get("referenced_schema"),
+                referenced_table: row.get("referenced_table"),
+                referenced_columns: row.get("referenced_columns"),
+                on_update: referential_action(row.get("on_update")).to_string(),
+                on_delete: referential_action(row.get("on_delete")).to_string(),
+            }),
+            _ => {}
+        }
+
+        table.constraints.push(ConstraintInfo {
+            name,
+            constraint_type: constraint_type(&kind).to_string(),
+            columns,
+            definition: row.get("definition"),
+        });
+    }
+
+    for row in fetch(INDEXES_QUERY).fetch_all(&mut *conn).await? {
+        let Some(i) = owner(&row) else { continue };
+        tables[i].indexes.push(IndexInfo {
+            name: row.get("name"),
+            columns: row.get("columns"),
+            is_unique: row.get("is_unique"),
+            is_primary: row.get("is_primary"),
+            method: row.get("method"),
+            predicate: row.get("predicate"),
+            definition: row.get("definition"),
+        });
+    }
+
+    Ok(tables)
+}
+
+pub(crate) async fn load_schema(conn: &mut sqlx::PgConnection, schema: &str) -> Result<SchemaDetails, sqlx::Error> {
+    let tables = load_tables(conn, schema, None).await?;
+
+    let functions = sqlx::query(FUNCTIONS_QUERY)
+        .bind(schema)
+        .fetch_all(&mut *conn)
+        .await?
+        .iter()
+        .map(|row| FunctionInfo {
+            name: row.get("name"),
+            kind: function_kind(row.get("kind")).to_string(),
+            arguments: row.get("arguments"),
+            identity_arguments: row.get("identity_arguments"),
+            result_type: row.get("result_type"),
+            language: row.get("language"),
+            comment: row.get("comment"),
+            definition: row.get("definition"),
+        })
+        .collect();
+
+    let sequences = sqlx::query(SEQUENCES_QUERY)
+        .bind(schema)
+        .fetch_all(&mut *conn)
+        .await?
+        .iter()
+        .map(|row| SequenceInfo {
+            name: row.get("name"),
+            data_type: row.get("data_type"),
+            start_value: row.get("start_value"),
+            increment_by: row.get("increment_by"),
+            min_value: row.get("min_value"),
+            max_value: row.get("max_value"),
+            cycle: row.get("cycle"),
+            last_value: row.get("last_value"),
+            is_identity: row.get("is_identity"),
+        })
+        .collect();
+
+    let enums = sqlx::query(ENUMS_QUERY)
+        .bind(schema)
+        .fetch_all(&mut *conn)
+        .await?
+        .iter()
+        .map(|row| EnumInfo {
+            name: row.get("name"),
+            values: row.get("values"),
+        })
+        .collect();
+
+    Ok(SchemaDetails {
+        name: schema.to_string(),
+        tables,
+        functions,
+        sequences,
+        enums,
+    })
+}
+
+fn relation_type(relkind: &str) -> &'static str {
+    match relkind {
+        "p" => "PARTITIONED TABLE",
+        "v" => "VIEW",
+        "m" => "MATERIALIZED VIEW",
+        "f" => "FOREIGN TABLE",
+        _ => "BASE TABLE",
+    }
+}
+
+fn constraint_type(contype: &str) -> &'static str {
+    match contype {
+        "p" => "PRIMARY KEY",
+        "f" => "FOREIGN KEY",
+        "u" => "UNIQUE",
+        "x" => "EXCLUDE",
+        _ => "CHECK",
+    }
+}
+
+fn referential_action(code: &str) -> &'static str {
+    match code {
+        "r" => "RESTRICT",
+        "c" => "CASCADE",
+        "n" => "SET NULL",
+        "d" => "SET DEFAULT",
+        _ => "NO ACTION",
+    }
+}
+
+fn function_kind(prokind: &str) -> &'static str {
+    match prokind {
+        "p" => "procedure",
+        "a" => "aggregate",
+        "w" => "window",
+        _ => "function",
+    }
+}
+
+/// Names of the schemas visible to the current user, excluding system schemas
+#[tauri::command]
+pub async fn pg_get_schemas(
+    sessions: State<'_, PgSessions>,
+    connections: State<'_, SavedConnections>,
+    connection_id: String,
+) -> Result<Vec<String>, String> {
+    let connection = connections.get(&connection_id).await?;
+    let session = sessions.acquire(&connection).await?;
+    let mut conn = session.conn.lock().await;
+
+    let schemas = sqlx::query_scalar(
+        r#"
+        SELECT nspname::text
+        FROM pg_namespace
+        WHERE nspname NOT IN ('pg_catalog', 'information_schema', 'pg_toast')
+          AND nspname NOT LIKE 'pg_temp_%'
+          AND nspname NOT LIKE 'pg_toast_temp_%'
+        ORDER BY nspname
+        "#,
+    )
+    .fetch_all(&mut *conn)
+    .await;
+
+    match schemas {
+        Ok(schemas) => Ok(schemas),
+        Err(e) => Err(session_error(&sessions, &session, e).await),
+    }
+}
+
+/// Columns, keys, indexes, constraints and (for views) the definition of one table or view
+#[tauri::command]
+pub async fn pg_describe_table(
+    sessions: State<'_, PgSessions>,
+    connections: State<'_, SavedConnections>,
+    connection_id: String,
+    schema: String,
+    table: String,
+) -> Result<TableDetails, String> {
+    let connection = connections.get(&connection_id).await?;
+    let session = sessions.acquire(&connection).await?;
+    let mut conn = session.conn.lock().await;
+
+    let tables = match load_tables(&mut conn, &schema, Some(&table)).await {
+        Ok(tables) => tables,
+        Err(e) => return Err(session_error(&sessions, &session, e).await),
+    };
+
+    tables
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("Table \"{}\".\"{}\" not found", schema, table))
+}
+
+/// Every table and view of a schema with full details, plus its functions, sequences and enums
+#[tauri::command]
+pub async fn pg_describe_schema(
+    sessions: State<'_, PgSessions>,
+    connections: State<'_, SavedConnections>,
+    connection_id: String,
+    schema: String,
+) -> Result<SchemaDetails, String> {
+    let connection = connections.get(&connection_id).await?;
+    let session = sessions.acquire(&connection).await?;
+    let mut conn = session.conn.lock().await;
+
+    match load_schema(&mut conn, &schema).await {
+        Ok(details) => Ok(details),
+        Err(e) => Err(session_error(&sessions, &session, e).await),
+    }
+}
@@ -0,0 +1,548 @@
+use serde::Serialize;
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+use tauri::State;
+
+use super::driver::DriverKind;
+use super::{
+    notices, query_error, read_only, stream_rows, transaction, PgSessions, QueryHistory, QueryOptions, QueryResult,
+    SavedConnections, DEFAULT_MAX_ROWS,
+};
+
+#[derive(Debug, Serialize)]
+pub struct StatementResult {
+    pub statement: String,
+    /// Rows and affected row count; `None` when the statement failed or was skipped
+    pub result: Option<QueryResult>,
+    pub error: Option<String>,
+    /// NOTICE/WARNING messages raised by the server while the statement ran
+    pub notices: Vec<String>,
+    pub duration_ms: f64,
+}
+
+/// Split a SQL script into statements on top-level semicolons.
+/// Semicolons inside quoted strings, quoted identifiers, dollar-quoted bodies, comments and
+/// the `BEGIN ... END` body of a function or procedure are ignored; statements that contain
+/// nothing but comments are dropped.
+pub fn split_statements(sql: &str, dialect: DriverKind) -> Vec<&str> {
+    let bytes = sql.as_bytes();
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut has_content = false;
+    let mut blocks = BlockDepth::default();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if let Some(end) = skip_comment(bytes, i, dialect) {
+            i = end;
+            continue;
+        }
+        if let Some(end) = skip_quoted_token(bytes, i, dialect) {
+            blocks.symbol();
+            i = end;
+            has_content = true;
+            continue;
+        }
+
+        match bytes[i] {
+            b if b.is_ascii_alphabetic() || b == b'_' || b >= 0x80 => {
+                let word_start = i;
+                while i < bytes.len() && is_ident_byte(bytes[i]) {
+                    i += 1;
+                }
+                blocks.word(sql[word_start..i].to_uppercase());
+                has_content = true;
+                continue;
+            }
+            b if b.is_ascii_whitespace() => {}
+            // A semicolon inside a routine body belongs to the body
+            b';' if blocks.symbol() > 0 => has_content = true,
+            b';' => {
+                if has_content {
+                    statements.push(sql[start..i].trim());
+                }
+                start = i + 1;
+                has_content = false;
+                blocks = BlockDepth::default();
+            }
+            b if b.is_ascii_digit() => {
+                blocks.symbol();
+                while i < bytes.len() && is_ident_byte(bytes[i]) {
+                    i += 1;
+                }
+                has_content = true;
+                continue;
+            }
+            _ => {
+                blocks.symbol();
+                has_content = true;
+            }
+        }
+        i += 1;
+    }
+
+    if has_content {
+        statements.push(sql[start..].trim());
+    }
+
+    statements
+}
+
+/// How deep a statement is in `BEGIN`/`CASE ... END` blocks. Only the body of a function,
+/// procedure, trigger or event, or MariaDB's `BEGIN NOT ATOMIC`, can hold statements of its
+/// own, so blocks are only counted there, as psql and the mysql client do.
+#[derive(Default)]
+struct BlockDepth {
+    // Leading words of the statement
+    leading: Vec<String>,
+    depth: usize,
+    // An END was seen; whether it closes a block depends on the word after it
+    pending_end: bool,
+}
+
+impl BlockDepth {
+    fn word(&mut self, word: String) {
+        if std::mem::take(&mut self.pending_end) {
+            // MySQL's END IF, END LOOP, ... close blocks that were never counted
+            if matches!(word.as_str(), "IF" | "LOOP" | "WHILE" | "REPEAT") {
+                return;
+            }
+            self.depth -= 1;
+            if word == "CASE" {
+                return;
+            }
+        }
+
+        if self.in_routine_body() {
+            match word.as_str() {
+                "BEGIN" | "CASE" => self.depth += 1,
+                "END" => self.pending_end = self.depth > 0,
+                _ => {}
+            }
+        }
+
+        if self.leading.len() < 6 {
+            self.leading.push(word);
+            if self.leading == ["BEGIN", "NOT", "ATOMIC"] {
+                self.depth += 1;
+            }
+        }
+    }
+
+    /// Anything but a word; returns the depth after it
+    fn symbol(&mut self) -> usize {
+        if std::mem::take(&mut self.pending_end) {
+            self.depth -= 1;
+        }
+        self.depth
+    }
+
+    fn in_routine_body(&self) -> bool {
+        let words: Vec<&str> = self.leading.iter().map(String::as_str).collect();
+        match words.as_slice() {
+            ["BEGIN", "NOT", "ATOMIC", ..] => true,
+            // MySQL allows a DEFINER and other clauses before the kind of object
+            ["CREATE", rest @ ..] => rest
+                .iter()
+                .any(|word| matches!(*word, "FUNCTION" | "PROCEDURE" | "TRIGGER" | "EVENT")),
+            _ => false,
+        }
+    }
+}
+
+/// Upper-cased bare words of a statement (keywords and unquoted identifiers), in order.
+/// Quoted strings, quoted identifiers, dollar-quoted bodies and comments are skipped.
+pub fn keywords(sql: &str, dialect: DriverKind) -> Vec<String> {
+    let bytes = sql.as_bytes();
+    let mut words = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if let Some(end) = skip_comment(bytes, i, dialect).or_else(|| skip_quoted_token(bytes, i, dialect)) {
+            i = end;
+            continue;
+        }
+
+        match bytes[i] {
+            b if b.is_ascii_alphabetic() || b == b'_' || b >= 0x80 => {
+                let start = i;
+                while i < bytes.len() && is_ident_byte(bytes[i]) {
+                    i += 1;
+                }
+                // The E of an E'...' string isn't a word
+                let escape_prefix = dialect == DriverKind::Postgres
+                    && i - start == 1
+                    && matches!(b, b'e' | b'E')
+                    && bytes.get(i) == Some(&b'\'');
+                if !escape_prefix {
+                    words.push(sql[start..i].to_uppercase());
+                }
+            }
+            b if b.is_ascii_digit() => {
+                while i < bytes.len() && is_ident_byte(bytes[i]) {
+                    i += 1;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+
+    words
+}
+
+/// Whether a statement only reads rows: a SELECT, VALUES, TABLE or WITH query that writes
+/// through neither a data-modifying CTE nor SELECT INTO. Such a statement can be read through a cursor.
+pub fn is_plain_query(sql: &str) -> bool {
+    let words = keywords(sql, DriverKind::Postgres);
+    matches!(words.first().map(String::as_str), Some("SELECT" | "VALUES" | "TABLE" | "WITH"))
+        && !words
+            .iter()
+            .any(|word| matches!(word.as_str(), "INSERT" | "UPDATE" | "DELETE" | "MERGE" | "INTO"))
+}
+
+/// Replace `:name` placeholders with what `placeholder` returns for their 1-based position.
+/// A name used twice gets the same position. Returns the new SQL and the names in position order.
+/// Casts (`::type`), quoted strings and identifiers, dollar-quoted bodies and comments are left alone.
+pub fn replace_named_parameters(sql: &str, mut placeholder: impl FnMut(usize) -> String) -> (String, Vec<String>) {
+    let bytes = sql.as_bytes();
+    let mut names: Vec<String> = Vec::new();
+    let mut output = String::with_capacity(sql.len());
+    let mut copied = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let skipped = skip_comment(bytes, i, DriverKind::Postgres)
+            .or_else(|| skip_quoted_token(bytes, i, DriverKind::Postgres));
+        if let Some(end) = skipped {
+            i = end;
+            continue;
+        }
+
+        match bytes[i] {
+            b':' if bytes.get(i + 1) == Some(&b':') => i += 2,
+            b':' if bytes.get(i + 1).is_some_and(|b| b.is_ascii_alphabetic() || *b == b'_') => {
+                let start = i + 1;
+                let mut end = start;
+                while end < bytes.len() && (bytes[end].is_ascii_alphanumeric() || bytes[end] == b'_') {
+                    end += 1;
+                }
+                let name = &sql[start..end];
+                let position = match names.iter().position(|n| n == name) {
+                    Some(index) => index + 1,
+                    None => {
+                        names.push(name.to_string());
+                        names.len()
+                    }
+                };
+
+                output.push_str(&sql[copied..i]);
+                output.push_str(&placeholder(position));
+                copied = end;
+                i = end;
+            }
+            b if is_ident_byte(b) => {
+                // Skip whole words so `$` inside identifiers isn't taken for a dollar quote
+                while i < bytes.len() && is_ident_byte(bytes[i]) {
+                    i += 1;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+
+    output.push_str(&sql[copied..]);
+    (output, names)
+}
+
+fn is_ident_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'$' || b >= 0x80
+}
+
+/// Index just past a comment starting at `i`, if one does. MySQL also has `#` comments, needs
+/// a space after `--`, and runs the contents of `/*! ... */` comments, so those aren't skipped.
+fn skip_comment(bytes: &[u8], i: usize, dialect: DriverKind) -> Option<usize> {
+    let mysql = dialect == DriverKind::Mysql;
+    let line_comment = match bytes[i] {
+        b'-' if bytes.get(i + 1) == Some(&b'-') => {
+            !mysql || bytes.get(i + 2).is_none_or(|b| b.is_ascii_whitespace() || b.is_ascii_control())
+        }
+        b'#' => mysql,
+        _ => false,
+    };
+    if line_comment {
+        return Some(bytes[i..].iter().position(|b| *b == b'\n').map_or(bytes.len(), |end| i + end));
+    }
+
+    if bytes[i] != b'/' || bytes.get(i + 1) != Some(&b'*') {
+        return None;
+    }
+    match dialect {
+        DriverKind::Postgres => Some(skip_block_comment(bytes, i)),
+        DriverKind::Mysql if bytes.get(i + 2) == Some(&b'!') => None,
+        // MySQL block comments don't nest
+        DriverKind::Mysql => Some(find(bytes, b"*/", i + 2).map_or(bytes.len(), |end| end + 2)),
+    }
+}
+
+/// Index just past a quoted string, quoted identifier or dollar-quoted body starting at `i`, if one does.
+/// MySQL quotes identifiers with backticks, takes `"` for strings and lets backslashes escape in every string.
+fn skip_quoted_token(bytes: &[u8], i: usize, dialect: DriverKind) -> Option<usize> {
+    match (dialect, bytes[i]) {
+        (DriverKind::Postgres, b'\'') => {
+            let escapes = i > 0 && matches!(bytes[i - 1], b'e' | b'E') && (i < 2 || !is_ident_byte(bytes[i - 2]));
+            Some(skip_quoted(bytes, i, b'\'', escapes))
+        }
+        (DriverKind::Postgres, b'"') => Some(skip_quoted(bytes, i, b'"', false)),
+        (DriverKind::Postgres, b'$') if i == 0 || !is_ident_byte(bytes[i - 1]) => {
+            let tag_len = dollar_tag_len(&bytes[i..])?;
+            let tag = &bytes[i..i + tag_len];
+            Some(find(bytes, tag, i + tag_len).map_or(bytes.len(), |end| end + tag_len))
+        }
+        (DriverKind::Mysql, quote @ (b'\'' | b'"')) => Some(skip_quoted(bytes, i, quote, true)),
+        (DriverKind::Mysql, b'`') => Some(skip_quoted(bytes, i, b'`', false)),
+        _ => None,
+    }
+}
+
+/// Index just past the end of a block comment starting at `start`; block comments nest in Postgres
+fn skip_block_comment(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        if bytes[i] == b'/' && bytes.get(i + 1) == Some(&b'*') {
+            depth += 1;
+            i += 2;
+        } else if bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/') {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+/// Index just past the closing quote of a quoted string or identifier starting at `start`
+fn skip_quoted(bytes: &[u8], start: usize, quote: u8, backslash_escapes: bool) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        if backslash_escapes && bytes[i] == b'\\' {
+            i += 2;
+            continue;
+        }
+        if bytes[i] == quote {
+            // A doubled quote is an escaped quote
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+/// Length of a `$tag$` opener at the start of `bytes`, if there is one
+fn dollar_tag_len(bytes: &[u8]) -> Option<usize> {
+    let mut i = 1;
+    while i < bytes.len() && bytes[i] != b'$' {
+        let b = bytes[i];
+        let valid = if i == 1 {
+            b.is_ascii_alphabetic() || b == b'_' || b >= 0x80
+        } else {
+            b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80
+        };
+        if !valid {
+            return None;
+        }
+        i += 1;
+    }
+    (i < bytes.len()).then_some(i + 1)
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack[from..]
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|p| p + from)
+}
+
+/// Run a multi-statement script in order, returning one result per statement.
+/// With `stop_on_error` (the default) the statements after a failing one are not run.
+/// Each statement that runs gets its own history entry.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn pg_execute_script(
+    sessions: State<'_, PgSessions>,
+    connections: State<'_, SavedConnections>,
+    history: State<'_, QueryHistory>,
+    connection_id: String,
+    script: String,
+    stop_on_error: Option<bool>,
+    options: Option<QueryOptions>,
+) -> Result<Vec<StatementResult>, String> {
+    let connection = connections.get(&connection_id).await?;
+    let checked = read_only::check(&connection, &script).and_then(|_| transaction::check_statements(&script, DriverKind::Postgres));
+    if let Err(e) = checked {
+        history.record_outcome(&connection.id, &script, None, Instant::now(), Err(&e)).await;
+        return Err(e);
+    }
+
+    let options = options.unwrap_or_default();
+    let stop_on_error = stop_on_error.unwrap_or(true);
+    let max_rows = options.max_rows.unwrap_or(DEFAULT_MAX_ROWS);
+    let timeout = options.statement_timeout_ms.map(Duration::from_millis);
+
+    let session = sessions.acquire(&connection).await?;
+    let mut conn = session.conn.lock().await;
+    let mut results = Vec::new();
+
+    for statement in split_statements(&script, DriverKind::Postgres) {
+        let running = sessions.track_query(&session, options.query_id.clone(), timeout);
+        let started = Instant::now();
+
+        let mut rows = Vec::new();
+        let queries = [sqlx::query(statement).persistent(false)];
+        let in_transaction = session.in_transaction();
+        let (outcome, notices) = notices::capture(stream_rows(&mut *conn, queries, max_rows, usize::MAX, in_transaction, |_, chunk| {
+            rows.extend(chunk);
+            ControlFlow::Continue(())
+        }))
+        .await;
+        let duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+        let (result, error, cancelled) = match outcome {
+            Ok(result) => (Some(QueryResult { rows, ..result }), None, false),
+            Err(e) => {
+                let cancelled = running.cancel_error(&e).is_some();
+                (None, Some(query_error(&sessions, &session, &running, e).await), cancelled)
+            }
+        };
+
+        let outcome = match (&result, &error) {
+            (Some(result), _) => Ok((result.row_count, result.rows_affected)),
+            (None, error) => Err(error.as_deref().unwrap_or_default()),
+        };
+        history.record_outcome(&connection.id, statement, None, started, outcome).await;
+
+        let failed = error.is_some();
+        results.push(StatementResult {
+            statement: statement.to_string(),
+            result,
+            error,
+            notices,
+            duration_ms,
+        });
+
+        if cancelled || (failed && stop_on_error) {
+            break;
+        }
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_on_top_level_semicolons_only() {
+        let sql = "select 1; select ';' as a -- c;\n; /* x; /* y; */ z; */ select $$a;b$$, $tag$ ; $ $tag$;\
+                   select E'\\';', \"a;\"\"\" from t;  -- only a comment;\n  ; select $1::int";
+        assert_eq!(
+            split_statements(sql, DriverKind::Postgres),
+            vec![
+                "select 1",
+                "select ';' as a -- c;",
+                "/* x; /* y; */ z; */ select $$a;b$$, $tag$ ; $ $tag$",
+                "select E'\\';', \"a;\"\"\" from t",
+                "select $1::int",
+            ]
+        );
+        assert!(split_statements(" ; -- nothing\n /* here */ ;", DriverKind::Postgres).is_empty());
+    }
+
+    #[test]
+    fn keeps_a_begin_atomic_body_in_one_statement() {
+        let sql = "CREATE OR REPLACE FUNCTION f(x int) RETURNS int LANGUAGE sql\n\
+                   BEGIN ATOMIC\n  SELECT CASE WHEN x > 0 THEN 1 ELSE 0 END;\n  SELECT x;\nEND;\nSELECT f(1)";
+        let statements = split_statements(sql, DriverKind::Postgres);
+        assert_eq!(statements.len(), 2);
+        assert!(statements[0].ends_with("SELECT x;\nEND"));
+        assert_eq!(statements[1], "SELECT f(1)");
+
+        // Outside a routine, BEGIN, CASE and END don't hold a statement open
+        assert_eq!(
+            split_statements("BEGIN; SELECT CASE WHEN true THEN 1 END AS begin; END", DriverKind::Postgres),
+            vec!["BEGIN", "SELECT CASE WHEN true THEN 1 END AS begin", "END"]
+        );
+    }
+
+    #[test]
+    fn keywords_skip_literals_identifiers_and_comments() {
+        assert_eq!(
+            keywords("select 'delete', \"update\", $x$ drop $x$, E'\\' insert' /* truncate */ from t1 -- grant\nwhere a1 = 2", DriverKind::Postgres),
+            vec!["SELECT", "FROM", "T1", "WHERE", "A1"]
+        );
+    }
+
+    #[test]
+    fn lexes_mysql_quotes_and_comments() {
+        let sql = "SELECT `a;b`, 'it\\'s; here', \"x;\\\"y\" # not; this\nFROM t; /* c; */ SELECT 1 -- ;\n; SELECT 2--1";
+        assert_eq!(
+            split_statements(sql, DriverKind::Mysql),
+            vec![
+                "SELECT `a;b`, 'it\\'s; here', \"x;\\\"y\" # not; this\nFROM t",
+                "/* c; */ SELECT 1 -- ;",
+                "SELECT 2--1",
+            ]
+        );
+        assert_eq!(
+            keywords("SELECT `update`, 'it\\'s delete' # drop\nFROM t", DriverKind::Mysql),
+            vec!["SELECT", "FROM", "T"]
+        );
+        // The contents of a versioned comment are run
+        assert_eq!(keywords("/*!50000 DELETE */ FROM t", DriverKind::Mysql), vec!["DELETE", "FROM", "T"]);
+    }
+
+    #[test]
+    fn keeps_a_mysql_routine_body_in_one_statement() {
+        let sql = "CREATE DEFINER = root@localhost PROCEDURE p(x int)\nlbl: BEGIN\n  IF x > 0 THEN SELECT 1; END IF;\n  \
+                   CASE x WHEN 1 THEN SELECT CASE WHEN x THEN 2 END; END CASE;\n  WHILE x < 3 DO SET x = x + 1; END WHILE;\n\
+                   END lbl;\nCALL p(1)";
+        let statements = split_statements(sql, DriverKind::Mysql);
+        assert_eq!(statements.len(), 2, "{:?}", statements);
+        assert!(statements[0].ends_with("END lbl"));
+        assert_eq!(statements[1], "CALL p(1)");
+
+        assert_eq!(
+            split_statements("BEGIN NOT ATOMIC SELECT 1; SELECT 2; END; SELECT 3", DriverKind::Mysql),
+            vec!["BEGIN NOT ATOMIC SELECT 1; SELECT 2; END", "SELECT 3"]
+        );
+    }
+
+    #[test]
+    fn plain_queries_only_read() {
+        assert!(is_plain_query("SELECT * FROM t"));
+        assert!(is_plain_query("with x as (select 1) select * from x"));
+        assert!(is_plain_query("VALUES (1), (2)"));
+        assert!(!is_plain_query("SELECT * INTO t2 FROM t"));
+        assert!(!is_plain_query("WITH d AS (DELETE FROM t RETURNING *) SELECT * FROM d"));
+        assert!(!is_plain_query("EXPLAIN SELECT 1"));
+    }
+
+    #[test]
+    fn replaces_named_parameters_in_order_of_first_use() {
+        let (sql, names) = replace_named_parameters(
+            "SELECT :a::int, ':b', \":c\", :b, :a -- :d\n FROM t WHERE $q$ :e $q$ = x",
+            |n| format!("${}", n),
+        );
+        assert_eq!(sql, "SELECT $1::int, ':b', \":c\", $2, $1 -- :d\n FROM t WHERE $q$ :e $q$ = x");
+        assert_eq!(names, vec!["a", "b"]);
+    }
+}
@@ -0,0 +1,289 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Emitter, Manager};
+
+use super::conninfo::{self, Settings};
+use super::{PgConnection, PgSessions, SavedConnections};
+use crate::mysql::MySqlSessions;
+
+// Saved connections imported from pg_service.conf have ids of the form `service:<name>`
+const SERVICE_ID_PREFIX: &str = "service:";
+
+// How often pg_service.conf and the pgpass file are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Result of bringing the imported services in line with pg_service.conf
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ServiceSync {
+    /// Every connection that comes from pg_service.conf, as saved
+    pub connections: Vec<PgConnection>,
+    /// Services that could not be turned into a connection, e.g. because of an invalid sslmode
+    pub errors: Vec<String>,
+    /// Whether any saved connection was added, updated or removed
+    pub changed: bool,
+}
+
+/// The user's service file (`PGSERVICEFILE` or `~/.pg_service.conf`), then the system-wide
+/// one in `PGSYSCONFDIR`. A service defined in both is taken from the user's file, as in libpq.
+fn service_files() -> Vec<PathBuf> {
+    let env = |name: &str| std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+    let user_file = env("PGSERVICEFILE").or_else(|| {
+        if cfg!(windows) {
+            env("APPDATA").map(|dir| dir.join("postgresql").join(".pg_service.conf"))
+        } else {
+            env("HOME").map(|dir| dir.join(".pg_service.conf"))
+        }
+    });
+    let system_file = env("PGSYSCONFDIR").map(|dir| dir.join("pg_service.conf"));
+
+    user_file.into_iter().chain(system_file).collect()
+}
+
+/// Read an INI-style service file: `[name]` sections of `keyword=value` lines
+fn read_service_file(path: &Path) -> Result<Vec<(String, Settings)>, String> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("{}: {}", path.display(), e)),
+    };
+
+    let mut services: Vec<(String, Settings)> = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| format!("{}:{}: missing \"]\"", path.display(), index + 1))?;
+            services.push((name.trim().to_string(), Settings::new()));
+            continue;
+        }
+
+        let (keyword, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("{}:{}: expected keyword=value", path.display(), index + 1))?;
+        let (_, settings) = services
+            .last_mut()
+            .ok_or_else(|| format!("{}:{}: setting outside of a [service] section", path.display(), index + 1))?;
+        settings.push((keyword.trim().to_string(), value.trim().to_string()));
+    }
+
+    Ok(services)
+}
+
+/// All services from the service files, the user's definitions shadowing the system ones
+fn read_services() -> Result<Vec<(String, Settings)>, String> {
+    let mut services: Vec<(String, Settings)> = Vec::new();
+    for path in service_files() {
+        for (name, settings) in read_service_file(&path)? {
+            if !services.iter().any(|(existing, _)| *existing == name) {
+                services.push((name, settings));
+            }
+        }
+    }
+    Ok(services)
+}
+
+/// The settings of one service, for the `service` connection option
+pub(crate) fn service_settings(name: &str) -> Result<Settings, String> {
+    read_services()?
+        .into_iter()
+        .find(|(service, _)| service == name)
+        .map(|(_, settings)| settings)
+        .ok_or_else(|| format!("Service \"{}\" not found in pg_service.conf", name))
+}
+
+/// A service name with its connection, or why it couldn't be built
+type ServiceConnection = (String, Result<PgConnection, String>);
+
+/// Connections for every service, with passwords from the service or the pgpass file
+fn service_connections() -> Result<Vec<ServiceConnection>, String> {
+    Ok(read_services()?
+        .into_iter()
+        .map(|(name, _)| {
+            let connection = conninfo::from_settings(vec![("service".to_string(), name.clone())]).map(|mut connection| {
+                connection.id = format!("{}{}", SERVICE_ID_PREFIX, name);
+                connection.name = name.clone();
+                connection
+            });
+            (name, connection)
+        })
+        .collect())
+}
+
+/// Same server, database, user and TLS settings
+fn same_target(a: &PgConnection, b: &PgConnection) -> bool {
+    a.host == b.host
+        && a.port == b.port
+        && a.database == b.database
+        && a.user == b.user
+        && a.ssl_mode == b.ssl_mode
+        && a.ssl_root_cert == b.ssl_root_cert
+        && a.ssl_client_cert == b.ssl_client_cert
+        && a.ssl_client_key == b.ssl_client_key
+}
+
+/// Update the saved `service:` connections from the service and pgpass files. New services are
+/// only added with `add_new`; services no longer in the files are removed. The name, folder,
+/// read-only flag and SSH tunnel set up in the app are kept.
+async fn sync(app: &AppHandle, add_new: bool) -> Result<ServiceSync, String> {
+    let connections = app.state::<SavedConnections>();
+    let services = service_connections()?;
+    let saved = connections.list().await;
+
+    let mut result = ServiceSync::default();
+    for (name, parsed) in services {
+        let mut connection = match parsed {
+            Ok(connection) => connection,
+            Err(e) => {
+                result.errors.push(format!("{}: {}", name, e));
+                // Keep the saved connection as it was rather than dropping it below
+                if let Some(existing) = saved.iter().find(|c| c.id == format!("{}{}", SERVICE_ID_PREFIX, name)) {
+                    result.connections.push(existing.clone());
+                }
+                continue;
+            }
+        };
+
+        match saved.iter().find(|c| c.id == connection.id) {
+            Some(existing) => {
+                connection.name = existing.name.clone();
+                connection.folder = existing.folder.clone();
+                connection.read_only = existing.read_only;
+                connection.ssh = existing.ssh.clone();
+
+                let current = connections.get(&existing.id).await?;
+                let same_password = connection.password.is_empty() || connection.password == current.password;
+                if same_target(&connection, existing) && same_password {
+                    result.connections.push(existing.clone());
+                    continue;
+                }
+            }
+            None if !add_new => continue,
+            None => {}
+        }
+
+        result.connections.push(connections.save(connection).await?);
+        result.changed = true;
+    }
+
+    let sessions = app.state::<PgSessions>();
+    let mysql_sessions = app.state::<MySqlSessions>();
+    for removed in saved
+        .iter()
+        .filter(|c| c.id.starts_with(SERVICE_ID_PREFIX))
+        .filter(|c| !result.connections.iter().any(|kept| kept.id == c.id))
+    {
+        sessions.close(&removed.id).await;
+        mysql_sessions.close(&removed.id).await;
+        connections.delete(&removed.id).await?;
+        result.changed = true;
+    }
+
+    Ok(result)
+}
+
+/// Modification time and size of each watched file, `None` when it doesn't exist
+fn watched_files_state() -> Vec<Option<(SystemTime, u64)>> {
+    service_files()
+        .into_iter()
+        .chain(conninfo::pgpass_path())
+        .map(|path| {
+            let metadata = std::fs::metadata(path).ok()?;
+            Some((metadata.modified().ok()?, metadata.len()))
+        })
+        .collect()
+}
+
+/// Watch pg_service.conf and the pgpass file, re-syncing the imported services when they change.
+/// Does nothing until services have been imported with `pg_import_service_connections`.
+pub fn spawn_service_watcher(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+        // Starts unknown so the first tick catches changes made while the app was closed
+        let mut last_seen = None;
+        loop {
+            interval.tick().await;
+
+            let seen = watched_files_state();
+            if last_seen.as_ref() == Some(&seen) {
+                continue;
+            }
+            last_seen = Some(seen);
+
+            let imported = app
+                .state::<SavedConnections>()
+                .list()
+                .await
+                .iter()
+                .any(|c| c.id.starts_with(SERVICE_ID_PREFIX));
+            if !imported {
+                continue;
+            }
+
+            let result = sync(&app, false).await.unwrap_or_else(|e| ServiceSync {
+                errors: vec![e],
+                ..Default::default()
+            });
+            if result.changed || !result.errors.is_empty() {
+                let _ = app.emit("pg-connections-changed", result);
+            }
+        }
+    });
+}
+
+/// Save a connection for every service in pg_service.conf, with its password from the service
+/// or the pgpass file. From then on they are kept in sync with both files; deleting all of
+/// them stops that.
+#[tauri::command]
+pub async fn pg_import_service_connections(app: AppHandle) -> Result<ServiceSync, String> {
+    sync(&app, true).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_service_sections() {
+        let path = std::env::temp_dir().join(format!("services-{}.conf", std::process::id()));
+        std::fs::write(
+            &path,
+            "# comment\n[sales]\nhost = db.example.com\nport=6543\n\n[ hr ]\ndbname=hr\npassword=a=b\n",
+        )
+        .unwrap();
+
+        let services = read_service_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let settings = |pairs: &[(&str, &str)]| -> Settings {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        assert_eq!(
+            services,
+            vec![
+                ("sales".to_string(), settings(&[("host", "db.example.com"), ("port", "6543")])),
+                ("hr".to_string(), settings(&[("dbname", "hr"), ("password", "a=b")])),
+            ]
+        );
+        assert!(read_service_file(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_service_files() {
+        let path = std::env::temp_dir().join(format!("services-bad-{}.conf", std::process::id()));
+        let read = |contents: &str| {
+            std::fs::write(&path, contents).unwrap();
+            read_service_file(&path).map_err(|e| e.replace(&path.display().to_string(), "file"))
+        };
+
+        assert_eq!(read("[sales\nhost=db").unwrap_err(), "file:1: missing \"]\"");
+        assert_eq!(read("[sales]\nhost").unwrap_err(), "file:2: expected keyword=value");
+        assert_eq!(read("host=db").unwrap_err(), "file:1: setting outside of a [service] section");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
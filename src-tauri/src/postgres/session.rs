@@ -0,0 +1,541 @@
+use serde::Serialize;
+use sqlx::Executor;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use super::driver::{Driver, DriverKind};
+use super::tunnel::Tunnel;
+use super::PgConnection;
+
+// Sessions that haven't been used for this long are closed by the idle reaper
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+// How often the idle reaper checks for expired sessions
+const REAPER_INTERVAL: Duration = Duration::from_secs(30);
+
+// Error returned by a query that was stopped through `pg_cancel_query` or its statement timeout
+pub const QUERY_CANCELLED: &str = "Query cancelled";
+
+/// Sessions of Postgres connections
+pub type PgSessions = Sessions<sqlx::PgConnection>;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PgSessionInfo {
+    pub connection_id: String,
+    pub driver: DriverKind,
+    /// Server-side connection id: the backend pid on Postgres, `CONNECTION_ID()` on MySQL
+    pub backend_pid: u64,
+    pub server_version: String,
+    pub idle_timeout_secs: u64,
+    pub in_transaction: bool,
+    /// Unix time (ms) at which the open transaction was started
+    pub transaction_started_at: Option<u64>,
+}
+
+/// A single long-lived connection bound to a saved `PgConnection`.
+/// Session state (search_path, temp tables, prepared statements) survives between commands.
+pub struct Session<D: Driver> {
+    pub conn: Mutex<D>,
+    pub connection: Arc<PgConnection>,
+    pub connection_id: String,
+    pub backend_pid: u64,
+    pub server_version: String,
+    // Kept open for as long as the session (and any cancel request on it) lives
+    tunnel: Option<Arc<Tunnel>>,
+    fingerprint: String,
+    // (last used, idle timeout)
+    activity: std::sync::Mutex<(Instant, Duration)>,
+    // Set while a transaction started with `pg_begin_transaction` is open
+    transaction_started_at: std::sync::Mutex<Option<u64>>,
+}
+
+impl<D: Driver> Session<D> {
+    pub fn info(&self) -> PgSessionInfo {
+        let (_, idle_timeout) = *self.activity.lock().unwrap();
+        let transaction_started_at = *self.transaction_started_at.lock().unwrap();
+        PgSessionInfo {
+            connection_id: self.connection_id.clone(),
+            driver: D::KIND,
+            backend_pid: self.backend_pid,
+            server_version: self.server_version.clone(),
+            idle_timeout_secs: idle_timeout.as_secs(),
+            in_transaction: transaction_started_at.is_some(),
+            transaction_started_at,
+        }
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.transaction_started_at.lock().unwrap().is_some()
+    }
+
+    pub fn set_in_transaction(&self, open: bool) {
+        let started_at = open.then(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64
+        });
+        *self.transaction_started_at.lock().unwrap() = started_at;
+    }
+
+    fn touch(&self, idle_timeout: Option<Duration>) {
+        let mut activity = self.activity.lock().unwrap();
+        activity.0 = Instant::now();
+        if let Some(timeout) = idle_timeout {
+            activity.1 = timeout;
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        // Never drop uncommitted work behind the user's back
+        if self.in_transaction() {
+            return false;
+        }
+
+        let (last_used, idle_timeout) = *self.activity.lock().unwrap();
+        last_used.elapsed() > idle_timeout
+    }
+}
+
+#[derive(Clone, Copy)]
+enum CancelReason {
+    User,
+    Timeout(Duration),
+}
+
+struct RunningQuery {
+    // Tells this registration apart from a later one that reuses its query id
+    token: u64,
+    connection: Arc<PgConnection>,
+    tunnel: Option<Arc<Tunnel>>,
+    backend_pid: u64,
+    cancel_reason: Option<CancelReason>,
+}
+
+type RunningQueries = Arc<std::sync::Mutex<HashMap<String, RunningQuery>>>;
+
+static NEXT_QUERY_TOKEN: AtomicU64 = AtomicU64::new(1);
+
+/// Registry of open sessions keyed by `PgConnection.id`, kept in Tauri managed state.
+/// There is one registry per driver; each only takes connections of its own driver.
+pub struct Sessions<D: Driver> {
+    // Only ever locked briefly; connecting and closing happen outside of it
+    sessions: Mutex<HashMap<String, Arc<Session<D>>>>,
+    // Per connection id, held while its session is opened or replaced
+    opening: std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    running: RunningQueries,
+}
+
+impl<D: Driver> Default for Sessions<D> {
+    fn default() -> Self {
+        Sessions {
+            sessions: Mutex::new(HashMap::new()),
+            opening: std::sync::Mutex::new(HashMap::new()),
+            running: RunningQueries::default(),
+        }
+    }
+}
+
+impl<D: Driver> Sessions<D> {
+    /// Return the open session for this connection, opening one if needed.
+    /// A session whose connection settings changed since it was opened is replaced.
+    pub async fn acquire(&self, connection: &PgConnection) -> Result<Arc<Session<D>>, String>
+    where
+        for<'c> &'c mut D: Executor<'c, Database = D::Database>,
+    {
+        self.open(connection, None).await
+    }
+
+    /// Like `acquire`, but also updates the idle timeout when one is given
+    pub async fn open(
+        &self,
+        connection: &PgConnection,
+        idle_timeout: Option<Duration>,
+    ) -> Result<Arc<Session<D>>, String>
+    where
+        for<'c> &'c mut D: Executor<'c, Database = D::Database>,
+    {
+        if connection.driver != D::KIND {
+            return Err(format!(
+                "\"{}\" is a {} connection; this is only available for {}",
+                connection.name,
+                connection.driver.label(),
+                D::KIND.label()
+            ));
+        }
+
+        let fingerprint = connection_fingerprint(connection);
+        if let Some(session) = self.current(&connection.id, &fingerprint, idle_timeout).await {
+            return Ok(session);
+        }
+
+        // One opener per connection; whoever waited here finds the session the first one opened
+        let gate = self.opening.lock().unwrap().entry(connection.id.clone()).or_default().clone();
+        let _opening = gate.lock().await;
+
+        if let Some(session) = self.current(&connection.id, &fingerprint, idle_timeout).await {
+            return Ok(session);
+        }
+
+        let stale = self.sessions.lock().await.remove(&connection.id);
+        if let Some(stale) = stale {
+            close_session(stale).await;
+        }
+
+        let tunnel = Tunnel::open_for(connection).await?.map(Arc::new);
+        let mut conn = D::open(connection, tunnel.as_deref()).await?;
+        let (backend_pid, server_version) = conn.server_info().await.map_err(|e| e.to_string())?;
+
+        let session = Arc::new(Session {
+            conn: Mutex::new(conn),
+            connection: Arc::new(connection.clone()),
+            connection_id: connection.id.clone(),
+            backend_pid,
+            server_version,
+            tunnel,
+            fingerprint,
+            activity: std::sync::Mutex::new((
+                Instant::now(),
+                idle_timeout.unwrap_or(DEFAULT_IDLE_TIMEOUT),
+            )),
+            transaction_started_at: std::sync::Mutex::new(None),
+        });
+
+        self.sessions.lock().await.insert(connection.id.clone(), session.clone());
+        Ok(session)
+    }
+
+    /// The open session for this connection if it still matches the connection settings
+    async fn current(
+        &self,
+        connection_id: &str,
+        fingerprint: &str,
+        idle_timeout: Option<Duration>,
+    ) -> Option<Arc<Session<D>>> {
+        let sessions = self.sessions.lock().await;
+        let session = sessions.get(connection_id).filter(|s| s.fingerprint == fingerprint)?;
+        session.touch(idle_timeout);
+        Some(session.clone())
+    }
+
+    /// The open session for this connection id, without opening one
+    pub async fn get(&self, connection_id: &str) -> Option<Arc<Session<D>>> {
+        self.sessions.lock().await.get(connection_id).cloned()
+    }
+
+    pub async fn close(&self, connection_id: &str) -> bool
+    where
+        for<'c> &'c mut D: Executor<'c, Database = D::Database>,
+    {
+        let session = self.sessions.lock().await.remove(connection_id);
+        match session {
+            Some(session) => {
+                close_session(session).await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drop a session without waiting for a graceful shutdown, e.g. after an I/O error.
+    /// Nothing happens if the connection has been given a new session since.
+    pub async fn discard(&self, session: &Session<D>) {
+        let mut sessions = self.sessions.lock().await;
+        if sessions
+            .get(&session.connection_id)
+            .is_some_and(|current| std::ptr::eq(Arc::as_ptr(current), session))
+        {
+            sessions.remove(&session.connection_id);
+        }
+    }
+
+    pub async fn list(&self) -> Vec<PgSessionInfo> {
+        let sessions = self.sessions.lock().await;
+        let mut list: Vec<PgSessionInfo> = sessions.values().map(|s| s.info()).collect();
+        list.sort_by(|a, b| a.connection_id.cmp(&b.connection_id));
+        list
+    }
+
+    pub async fn close_idle(&self)
+    where
+        for<'c> &'c mut D: Executor<'c, Database = D::Database>,
+    {
+        let expired: Vec<Arc<Session<D>>> = {
+            let mut sessions = self.sessions.lock().await;
+            let ids: Vec<String> = sessions
+                .iter()
+                .filter(|(_, s)| s.is_expired())
+                .map(|(id, _)| id.clone())
+                .collect();
+            ids.iter().filter_map(|id| sessions.remove(id)).collect()
+        };
+
+        for session in expired {
+            close_session(session).await;
+        }
+    }
+
+    /// Register a query running on `session` so it can be cancelled by id.
+    /// With a timeout, the query is cancelled once it runs longer than that; the timer
+    /// stops when the returned guard is dropped.
+    pub fn track_query(
+        &self,
+        session: &Session<D>,
+        query_id: Option<String>,
+        timeout: Option<Duration>,
+    ) -> RunningQueryGuard {
+        let query_id = query_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let token = NEXT_QUERY_TOKEN.fetch_add(1, Ordering::Relaxed);
+
+        self.running.lock().unwrap().insert(
+            query_id.clone(),
+            RunningQuery {
+                token,
+                connection: session.connection.clone(),
+                tunnel: session.tunnel.clone(),
+                backend_pid: session.backend_pid,
+                cancel_reason: None,
+            },
+        );
+
+        let timer = timeout.map(|timeout| {
+            let running = self.running.clone();
+            let query_id = query_id.clone();
+            tauri::async_runtime::spawn(async move {
+                tokio::time::sleep(timeout).await;
+                let _ = cancel_running::<D>(&running, &query_id, Some(token), CancelReason::Timeout(timeout)).await;
+            })
+        });
+
+        RunningQueryGuard {
+            running: self.running.clone(),
+            query_id,
+            token,
+            timer,
+            is_cancelled: D::is_cancelled,
+        }
+    }
+
+    /// Ask the server to cancel a running query. Returns false if no such query is running.
+    pub async fn cancel_query(&self, query_id: &str) -> Result<bool, String> {
+        cancel_running::<D>(&self.running, query_id, None, CancelReason::User).await
+    }
+
+    pub async fn close_all(&self)
+    where
+        for<'c> &'c mut D: Executor<'c, Database = D::Database>,
+    {
+        let all: Vec<Arc<Session<D>>> = self.sessions.lock().await.drain().map(|(_, s)| s).collect();
+        for session in all {
+            close_session(session).await;
+        }
+    }
+}
+
+async fn close_session<D: Driver>(session: Arc<Session<D>>)
+where
+    for<'c> &'c mut D: Executor<'c, Database = D::Database>,
+{
+    // Wait for any in-flight query to finish before terminating the connection
+    let mut conn = session.conn.lock().await;
+    if session.in_transaction() {
+        let _ = conn.execute("ROLLBACK").await;
+        session.set_in_transaction(false);
+    }
+    drop(conn);
+
+    if let Ok(session) = Arc::try_unwrap(session) {
+        let _ = session.conn.into_inner().close().await;
+    }
+}
+
+/// Removes the query from the running registry and stops its timeout when dropped
+pub struct RunningQueryGuard {
+    running: RunningQueries,
+    query_id: String,
+    token: u64,
+    timer: Option<tauri::async_runtime::JoinHandle<()>>,
+    is_cancelled: fn(&sqlx::Error) -> bool,
+}
+
+impl RunningQueryGuard {
+    /// Whether a cancel was requested, for work the client drives itself (e.g. COPY FROM STDIN)
+    pub fn is_cancelled(&self) -> bool {
+        self.running
+            .lock()
+            .unwrap()
+            .get(&self.query_id)
+            .is_some_and(|query| query.token == self.token && query.cancel_reason.is_some())
+    }
+
+    /// The error to report if `error` is the result of this query being cancelled
+    pub fn cancel_error(&self, error: &sqlx::Error) -> Option<String> {
+        let reason = self
+            .running
+            .lock()
+            .unwrap()
+            .get(&self.query_id)
+            .filter(|query| query.token == self.token)?
+            .cancel_reason?;
+
+        if !(self.is_cancelled)(error) {
+            return None;
+        }
+
+        Some(match reason {
+            CancelReason::User => QUERY_CANCELLED.to_string(),
+            CancelReason::Timeout(timeout) => format!(
+                "{} (statement timeout of {} ms exceeded)",
+                QUERY_CANCELLED,
+                timeout.as_millis()
+            ),
+        })
+    }
+}
+
+impl Drop for RunningQueryGuard {
+    fn drop(&mut self) {
+        if let Some(timer) = &self.timer {
+            timer.abort();
+        }
+
+        let mut running = self.running.lock().unwrap();
+        if running.get(&self.query_id).is_some_and(|query| query.token == self.token) {
+            running.remove(&self.query_id);
+        }
+    }
+}
+
+/// Cancel through a separate connection, since the session's own connection is busy.
+/// With a `token`, only that registration of the query id is cancelled.
+async fn cancel_running<D: Driver>(
+    running: &RunningQueries,
+    query_id: &str,
+    token: Option<u64>,
+    reason: CancelReason,
+) -> Result<bool, String> {
+    let (connection, tunnel, backend_pid) = {
+        let mut running = running.lock().unwrap();
+        match running.get_mut(query_id) {
+            Some(query) if token.is_none_or(|token| token == query.token) => {
+                query.cancel_reason.get_or_insert(reason);
+                (query.connection.clone(), query.tunnel.clone(), query.backend_pid)
+            }
+            _ => return Ok(false),
+        }
+    };
+
+    let mut conn = D::open(&connection, tunnel.as_deref()).await?;
+    let cancelled = conn.cancel_backend(backend_pid).await.map_err(|e| e.to_string())?;
+
+    let _ = conn.close().await;
+
+    Ok(cancelled)
+}
+
+fn connection_fingerprint(connection: &PgConnection) -> String {
+    format!(
+        "{:?}\u{0}{}\u{0}{}\u{0}{}\u{0}{}\u{0}{}\u{0}{}\u{0}{:?}\u{0}{:?}\u{0}{:?}\u{0}{:?}\u{0}{:?}",
+        connection.driver,
+        connection.host,
+        connection.port,
+        connection.database,
+        connection.user,
+        connection.password,
+        connection.read_only,
+        connection.ssl_mode,
+        connection.ssl_root_cert,
+        connection.ssl_client_cert,
+        connection.ssl_client_key,
+        connection.ssh
+    )
+}
+
+/// Periodically close sessions of one driver that exceeded their idle timeout
+pub fn spawn_idle_reaper<D: Driver>(app: &tauri::AppHandle)
+where
+    for<'c> &'c mut D: Executor<'c, Database = D::Database>,
+{
+    use tauri::Manager;
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(REAPER_INTERVAL);
+        loop {
+            interval.tick().await;
+            app.state::<Sessions<D>>().close_idle().await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register(running: &RunningQueries, query_id: &str, token: u64) -> RunningQueryGuard {
+        let connection: PgConnection = serde_json::from_value(serde_json::json!({
+            "id": "c", "name": "c", "host": "localhost", "port": 5432, "database": "postgres", "user": "postgres"
+        }))
+        .unwrap();
+        running.lock().unwrap().insert(
+            query_id.to_string(),
+            RunningQuery {
+                token,
+                connection: Arc::new(connection),
+                tunnel: None,
+                backend_pid: 1,
+                cancel_reason: None,
+            },
+        );
+        RunningQueryGuard {
+            running: running.clone(),
+            query_id: query_id.to_string(),
+            token,
+            timer: None,
+            is_cancelled: |_| true,
+        }
+    }
+
+    #[test]
+    fn a_finished_query_leaves_a_reused_id_alone() {
+        let running = RunningQueries::default();
+        let first = register(&running, "q", 1);
+        let second = register(&running, "q", 2);
+
+        drop(first);
+        assert_eq!(running.lock().unwrap().get("q").map(|query| query.token), Some(2));
+
+        drop(second);
+        assert!(running.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_stale_timeout_does_not_cancel_a_newer_registration() {
+        let running = RunningQueries::default();
+        let guard = register(&running, "q", 2);
+
+        let cancelled = cancel_running::<sqlx::PgConnection>(&running, "q", Some(1), CancelReason::User).await;
+        assert_eq!(cancelled, Ok(false));
+        assert!(!guard.is_cancelled());
+        assert!(guard.cancel_error(&sqlx::Error::PoolClosed).is_none());
+    }
+
+    #[tokio::test]
+    async fn dropping_the_guard_stops_its_timer() {
+        let running = RunningQueries::default();
+        let mut guard = register(&running, "q", 1);
+        let (alive, stopped) = tokio::sync::oneshot::channel::<()>();
+        guard.timer = Some(tauri::async_runtime::spawn(async move {
+            std::future::pending::<()>().await;
+            drop(alive);
+        }));
+
+        drop(guard);
+        // The sender goes away with the aborted task without sending
+        let outcome = tokio::time::timeout(Duration::from_secs(5), stopped).await;
+        assert!(matches!(outcome, Ok(Err(_))));
+    }
+}
@@ -0,0 +1,298 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::postgres::PgArguments;
+use sqlx::{Arguments, Row};
+use std::path::PathBuf;
+use std::time::Instant;
+use tauri::State;
+use tokio::sync::Mutex;
+
+use super::{
+    cast_type, execute_query, execute_sql, script, session_error, PgConnection, PgSessions, QueryHistory, QueryOptions,
+    QueryResult, SavedConnections,
+};
+
+// Saved snippets in the app data directory
+const SNIPPETS_FILE: &str = "pg-snippets.json";
+
+// Prepared statement that describes the parameters of a snippet, dropped again right away
+const DESCRIBE_STATEMENT: &str = "devtools_describe_parameters";
+
+/// Values for `:name` placeholders, by name
+pub type Parameters = serde_json::Map<String, Value>;
+
+/// A named SQL statement with `:name` placeholders
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Snippet {
+    pub id: String,
+    pub name: String,
+    pub sql: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+    /// Placeholder names in order of first use; filled in by the backend on save
+    #[serde(default)]
+    pub parameters: Vec<String>,
+}
+
+/// Saved snippets, kept in Tauri managed state
+pub struct SnippetLibrary {
+    dir: PathBuf,
+    snippets: Mutex<Vec<Snippet>>,
+}
+
+impl SnippetLibrary {
+    /// Read the saved snippets from `dir`, starting empty if there are none yet
+    pub fn load(dir: PathBuf) -> Self {
+        let snippets = std::fs::read_to_string(dir.join(SNIPPETS_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+
+        SnippetLibrary {
+            dir,
+            snippets: Mutex::new(snippets),
+        }
+    }
+
+    pub async fn get(&self, snippet_id: &str) -> Result<Snippet, String> {
+        self.snippets
+            .lock()
+            .await
+            .iter()
+            .find(|s| s.id == snippet_id)
+            .cloned()
+            .ok_or_else(|| format!("No snippet with id {}", snippet_id))
+    }
+
+    pub async fn list(&self) -> Vec<Snippet> {
+        self.snippets.lock().await.clone()
+    }
+
+    /// Add or update a snippet, working out its parameters from the SQL
+    pub async fn save(&self, mut snippet: Snippet) -> Result<Snippet, String> {
+        if snippet.name.trim().is_empty() {
+            return Err("A snippet needs a name".to_string());
+        }
+        if snippet.id.is_empty() {
+            snippet.id = uuid::Uuid::new_v4().to_string();
+        }
+        snippet.parameters = script::replace_named_parameters(&snippet.sql, |n| format!("${}", n)).1;
+
+        let mut snippets = self.snippets.lock().await;
+        match snippets.iter_mut().find(|s| s.id == snippet.id) {
+            Some(existing) => *existing = snippet.clone(),
+            None => snippets.push(snippet.clone()),
+        }
+        self.write(&snippets)?;
+
+        Ok(snippet)
+    }
+
+    pub async fn delete(&self, snippet_id: &str) -> Result<bool, String> {
+        let mut snippets = self.snippets.lock().await;
+
+        let before = snippets.len();
+        snippets.retain(|s| s.id != snippet_id);
+        if snippets.len() == before {
+            return Ok(false);
+        }
+
+        self.write(&snippets)?;
+        Ok(true)
+    }
+
+    fn write(&self, snippets: &[Snippet]) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(snippets).map_err(|e| e.to_string())?;
+        std::fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        std::fs::write(self.dir.join(SNIPPETS_FILE), json).map_err(|e| e.to_string())
+    }
+}
+
+/// Text form of a parameter value; `None` binds NULL
+pub(crate) fn parameter_text(value: &Value, sql_type: &str) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(text) => Some(text.clone()),
+        Value::Array(items) if sql_type.ends_with("[]") => Some(array_literal(items)),
+        other => Some(other.to_string()),
+    }
+}
+
+/// Postgres array literal for a JSON array, e.g. `{1,"a b",NULL}`
+pub(crate) fn array_literal(items: &[Value]) -> String {
+    let elements: Vec<String> = items
+        .iter()
+        .map(|item| match item {
+            Value::Null => "NULL".to_string(),
+            Value::Array(nested) => array_literal(nested),
+            Value::String(s) => quote_element(s),
+            Value::Object(_) => quote_element(&item.to_string()),
+            other => other.to_string(),
+        })
+        .collect();
+    format!("{{{}}}", elements.join(","))
+}
+
+/// Double-quote an element of an array or range literal
+pub(crate) fn quote_element(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Run SQL with `:name` placeholders, binding `parameters` as query arguments.
+/// Each value is sent as text and cast to the type Postgres expects at its position,
+/// so numbers, dates, UUIDs or enums can all be given as JSON strings.
+pub(crate) async fn execute_with_parameters(
+    sessions: &PgSessions,
+    connection: &PgConnection,
+    sql: &str,
+    parameters: &Parameters,
+    options: QueryOptions,
+) -> Result<QueryResult, String> {
+    let (positional, names) = script::replace_named_parameters(sql, |n| format!("${}", n));
+    if names.is_empty() {
+        return execute_sql(sessions, connection, sql, options).await;
+    }
+
+    let values = names
+        .iter()
+        .map(|name| {
+            parameters
+                .get(name)
+                .ok_or_else(|| format!("No value given for parameter :{}", name))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Ask the server which type each placeholder is. `prepare` would keep the statement in the
+    // session's statement cache, where it breaks once DDL changes the tables it reads.
+    let types: Vec<String> = {
+        let session = sessions.acquire(connection).await?;
+        let mut conn = session.conn.lock().await;
+        let prepare = format!("PREPARE {} AS {}", DESCRIBE_STATEMENT, positional);
+        if let Err(e) = sqlx::query(&prepare).persistent(false).execute(&mut *conn).await {
+            return Err(session_error(sessions, &session, e).await);
+        }
+        let types = sqlx::query(
+            r#"
+            SELECT format_type(p.t, NULL)
+            FROM pg_prepared_statements, unnest(parameter_types) WITH ORDINALITY AS p(t, n)
+            WHERE name = $1
+            ORDER BY p.n
+            "#,
+        )
+        .bind(DESCRIBE_STATEMENT)
+        .persistent(false)
+        .fetch_all(&mut *conn)
+        .await;
+        let deallocate = format!("DEALLOCATE {}", DESCRIBE_STATEMENT);
+        let _ = sqlx::query(&deallocate).persistent(false).execute(&mut *conn).await;
+
+        types
+            .map_err(|e| e.to_string())?
+            .iter()
+            .map(|row| cast_type(&row.get::<String, _>(0)))
+            .collect()
+    };
+
+    let cast_sql = script::replace_named_parameters(sql, |n| {
+        let sql_type = types.get(n - 1).map_or("text", String::as_str);
+        format!("CAST(${}::text AS {})", n, sql_type)
+    })
+    .0;
+
+    let mut arguments = PgArguments::default();
+    for (value, sql_type) in values.iter().zip(&types) {
+        arguments
+            .add(parameter_text(value, sql_type))
+            .map_err(|e| e.to_string())?;
+    }
+
+    execute_query(sessions, connection, vec![sqlx::query_with(&cast_sql, arguments).persistent(false)], options).await
+}
+
+#[tauri::command]
+pub async fn pg_list_snippets(snippets: State<'_, SnippetLibrary>) -> Result<Vec<Snippet>, String> {
+    Ok(snippets.list().await)
+}
+
+/// Add or update a snippet. An empty `id` creates a new one. Returns the snippet as saved,
+/// with its `parameters` filled in.
+#[tauri::command]
+pub async fn pg_save_snippet(snippets: State<'_, SnippetLibrary>, snippet: Snippet) -> Result<Snippet, String> {
+    snippets.save(snippet).await
+}
+
+#[tauri::command]
+pub async fn pg_delete_snippet(snippets: State<'_, SnippetLibrary>, snippet_id: String) -> Result<bool, String> {
+    snippets.delete(&snippet_id).await
+}
+
+/// Run a saved snippet with a value for each of its parameters
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn pg_execute_snippet(
+    sessions: State<'_, PgSessions>,
+    connections: State<'_, SavedConnections>,
+    history: State<'_, QueryHistory>,
+    snippets: State<'_, SnippetLibrary>,
+    connection_id: String,
+    snippet_id: String,
+    parameters: Option<Parameters>,
+    options: Option<QueryOptions>,
+) -> Result<QueryResult, String> {
+    let connection = connections.get(&connection_id).await?;
+    let snippet = snippets.get(&snippet_id).await?;
+    let parameters = parameters.unwrap_or_default();
+
+    let started = Instant::now();
+    let result = execute_with_parameters(
+        &sessions,
+        &connection,
+        &snippet.sql,
+        &parameters,
+        options.unwrap_or_default(),
+    )
+    .await;
+    history.record(&connection.id, &snippet.sql, Some(&parameters), started, &result).await;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn binds_scalars_as_their_text() {
+        assert_eq!(parameter_text(&Value::Null, "integer"), None);
+        assert_eq!(parameter_text(&json!("O'Brien"), "text").as_deref(), Some("O'Brien"));
+        assert_eq!(parameter_text(&json!(1.5), "numeric").as_deref(), Some("1.5"));
+        assert_eq!(parameter_text(&json!(true), "boolean").as_deref(), Some("true"));
+        assert_eq!(parameter_text(&json!({"k": [1, "a"]}), "jsonb").as_deref(), Some(r#"{"k":[1,"a"]}"#));
+        // Arrays are only array literals where the server expects an array
+        assert_eq!(parameter_text(&json!([1, 2]), "jsonb").as_deref(), Some("[1,2]"));
+    }
+
+    #[test]
+    fn binds_json_arrays_as_array_literals() {
+        assert_eq!(parameter_text(&json!([1, 2]), "integer[]").as_deref(), Some("{1,2}"));
+        assert_eq!(parameter_text(&json!([]), "text[]").as_deref(), Some("{}"));
+        assert_eq!(
+            parameter_text(&json!([[1, null], [3, 4]]), "integer[]").as_deref(),
+            Some("{{1,NULL},{3,4}}")
+        );
+        assert_eq!(
+            parameter_text(&json!(["a b", null, "NULL", ""]), "text[]").as_deref(),
+            Some(r#"{"a b",NULL,"NULL",""}"#)
+        );
+        assert_eq!(
+            parameter_text(&json!([r#"say "hi""#, r"C:\dir", "{x,y}"]), "text[]").as_deref(),
+            Some(r#"{"say \"hi\"","C:\\dir","{x,y}"}"#)
+        );
+        assert_eq!(
+            parameter_text(&json!([{"a": "b\\c"}, true]), "jsonb[]").as_deref(),
+            Some(r#"{"{\"a\":\"b\\\\c\"}",true}"#)
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use tauri::State;
+
+use super::driver::DriverKind;
+use super::{read_only, script, session_error, PgSessionInfo, PgSessions, SavedConnections};
+
+const ISOLATION_LEVELS: [&str; 4] = ["READ UNCOMMITTED", "READ COMMITTED", "REPEATABLE READ", "SERIALIZABLE"];
+
+/// Refuse statements that open or end a transaction block. Only the transaction commands may
+/// do that, so the session always knows whether a transaction is open: the idle reaper must
+/// not close one, and the UI shows it.
+pub fn check_statements(sql: &str, dialect: DriverKind) -> Result<(), String> {
+    for statement in script::split_statements(sql, dialect) {
+        if let Some(keyword) = transaction_control(&script::keywords(statement, dialect)) {
+            return Err(format!(
+                "{} is not allowed in a query; open and end transactions with the transaction controls",
+                keyword
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The statement's words that open or end a transaction, if it does
+fn transaction_control(words: &[String]) -> Option<String> {
+    let first = words.first()?.as_str();
+    match (first, words.get(1).map(String::as_str)) {
+        // Savepoints and two-phase commits leave the transaction block as it is; MariaDB's
+        // BEGIN NOT ATOMIC starts a compound statement
+        ("ROLLBACK", Some("TO" | "PREPARED")) | ("COMMIT", Some("PREPARED")) | ("BEGIN", Some("NOT")) => None,
+        ("BEGIN" | "COMMIT" | "END" | "ROLLBACK" | "ABORT" | "XA", _) => Some(first.to_string()),
+        ("START" | "PREPARE", Some("TRANSACTION")) => Some(format!("{} TRANSACTION", first)),
+        // MySQL keeps a transaction open after every statement with autocommit off
+        ("SET", _) if words.iter().any(|word| word == "AUTOCOMMIT") => Some("SET autocommit".to_string()),
+        _ => None,
+    }
+}
+
+/// Start a transaction on the connection's session; later queries on the session run inside it
+/// until `pg_commit_transaction` or `pg_rollback_transaction`. Closing the session rolls it back.
+#[tauri::command]
+pub async fn pg_begin_transaction(
+    sessions: State<'_, PgSessions>,
+    connections: State<'_, SavedConnections>,
+    connection_id: String,
+    isolation_level: Option<String>,
+    read_only: Option<bool>,
+) -> Result<PgSessionInfo, String> {
+    let connection = connections.get(&connection_id).await?;
+    let mut statement = String::from("BEGIN");
+
+    if let Some(level) = isolation_level {
+        let level = level.trim().replace('_', " ").to_uppercase();
+        if !ISOLATION_LEVELS.contains(&level.as_str()) {
+            return Err(format!("Unknown isolation level: {}", level));
+        }
+        statement.push_str(" ISOLATION LEVEL ");
+        statement.push_str(&level);
+    }
+
+    match read_only {
+        Some(true) => statement.push_str(" READ ONLY"),
+        Some(false) => statement.push_str(" READ WRITE"),
+        None => {}
+    }
+
+    read_only::check(&connection, &statement)?;
+
+    let session = sessions.acquire(&connection).await?;
+    let mut conn = session.conn.lock().await;
+
+    if session.in_transaction() {
+        return Err("A transaction is already open on this connection".to_string());
+    }
+
+    if let Err(e) = sqlx::query(&statement).execute(&mut *conn).await {
+        return Err(session_error(&sessions, &session, e).await);
+    }
+
+    session.set_in_transaction(true);
+    Ok(session.info())
+}
+
+#[tauri::command]
+pub async fn pg_commit_transaction(
+    sessions: State<'_, PgSessions>,
+    connection_id: String,
+) -> Result<PgSessionInfo, String> {
+    end_transaction(&sessions, &connection_id, "COMMIT").await
+}
+
+#[tauri::command]
+pub async fn pg_rollback_transaction(
+    sessions: State<'_, PgSessions>,
+    connection_id: String,
+) -> Result<PgSessionInfo, String> {
+    end_transaction(&sessions, &connection_id, "ROLLBACK").await
+}
+
+/// Run COMMIT or ROLLBACK on an open transaction.
+/// A transaction that hit an error can only be rolled back; committing it also rolls it back.
+async fn end_transaction(sessions: &PgSessions, connection_id: &str, statement: &str) -> Result<PgSessionInfo, String> {
+    let session = sessions
+        .get(connection_id)
+        .await
+        .ok_or_else(|| "No open session for this connection".to_string())?;
+    let mut conn = session.conn.lock().await;
+
+    if !session.in_transaction() {
+        return Err("No transaction is open on this connection".to_string());
+    }
+
+    if let Err(e) = sqlx::query(statement).execute(&mut *conn).await {
+        return Err(session_error(sessions, &session, e).await);
+    }
+
+    session.set_in_transaction(false);
+    Ok(session.info())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_statements_that_open_or_end_a_transaction() {
+        for sql in [
+            "BEGIN",
+            "begin isolation level serializable",
+            "START TRANSACTION READ ONLY",
+            "select 1; COMMIT",
+            "end",
+            "ROLLBACK",
+            "abort",
+            "PREPARE TRANSACTION 'x'",
+            "SET autocommit = 0",
+            "XA START 'x'",
+        ] {
+            assert!(check_statements(sql, DriverKind::Postgres).is_err(), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn allows_statements_inside_a_transaction() {
+        for sql in [
+            "SELECT 'BEGIN'",
+            "SAVEPOINT a; ROLLBACK TO SAVEPOINT a; RELEASE a",
+            "COMMIT PREPARED 'x'",
+            "DO $$ BEGIN RAISE NOTICE 'x'; END $$",
+            "SELECT CASE WHEN true THEN 1 END",
+            "SET TRANSACTION ISOLATION LEVEL SERIALIZABLE",
+            "-- COMMIT\nSELECT 1",
+        ] {
+            assert!(check_statements(sql, DriverKind::Postgres).is_ok(), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn lexes_mysql_statements_as_mysql() {
+        for sql in [
+            "SELECT `commit`",
+            "SELECT 'it\\'s; COMMIT'",
+            "# COMMIT\nSELECT 1",
+            "CREATE PROCEDURE p() BEGIN UPDATE t SET a = 1; IF a THEN SELECT 1; END IF; END",
+            "BEGIN NOT ATOMIC SELECT 1; END",
+        ] {
+            assert!(check_statements(sql, DriverKind::Mysql).is_ok(), "{}", sql);
+        }
+        for sql in ["SELECT 1; COMMIT", "START TRANSACTION", "SET autocommit = 0", "/*!40101 COMMIT */"] {
+            assert!(check_statements(sql, DriverKind::Mysql).is_err(), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn names_the_refused_statement() {
+        let error = check_statements("start transaction", DriverKind::Postgres).unwrap_err();
+        assert!(error.starts_with("START TRANSACTION is not allowed"), "{}", error);
+    }
+}
@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+use tokio::process::{Child, Command};
+
+use super::{PgConnection, SslMode};
+
+// How long to wait for ssh to authenticate and start listening on the local port
+const TUNNEL_START_TIMEOUT: Duration = Duration::from_secs(20);
+
+// Interval between checks for the local port while the tunnel starts
+const TUNNEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// SSH jump host the database is reached through
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SshTunnel {
+    pub host: String,
+    #[serde(default = "default_ssh_port")]
+    pub port: u16,
+    pub user: String,
+    /// Private key file; without one, keys from the SSH agent and the user's ssh config are tried.
+    /// Passphrase-protected keys have to be loaded into the agent, since ssh can't prompt here.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<String>,
+}
+
+fn default_ssh_port() -> u16 {
+    22
+}
+
+/// A running `ssh -L` port-forward from a local port to the database.
+/// The ssh process is killed when the tunnel is dropped.
+pub struct Tunnel {
+    pub local_port: u16,
+    _child: Child,
+}
+
+impl Tunnel {
+    /// Start the tunnel for a connection, if it has one
+    pub async fn open_for(connection: &PgConnection) -> Result<Option<Tunnel>, String> {
+        let Some(ssh) = &connection.ssh else {
+            return Ok(None);
+        };
+
+        // The TLS host name check would run against 127.0.0.1 rather than the database host
+        if connection.ssl_mode == SslMode::VerifyFull {
+            return Err("verify-full can't check the server name through an SSH tunnel; use verify-ca".to_string());
+        }
+
+        Tunnel::open(ssh, &connection.host, connection.port).await.map(Some)
+    }
+
+    async fn open(ssh: &SshTunnel, target_host: &str, target_port: u16) -> Result<Tunnel, String> {
+        if let Some(key_file) = &ssh.key_file {
+            if !std::path::Path::new(key_file).is_file() {
+                return Err(format!("SSH key file not found: {}", key_file));
+            }
+        }
+
+        let local_port = free_local_port()?;
+        // IPv6 addresses need brackets in a forward spec
+        let target_host = if target_host.contains(':') {
+            format!("[{}]", target_host)
+        } else {
+            target_host.to_string()
+        };
+
+        let mut command = Command::new("ssh");
+        command
+            .arg("-N")
+            .arg("-L")
+            .arg(format!("127.0.0.1:{}:{}:{}", local_port, target_host, target_port))
+            .arg("-p")
+            .arg(ssh.port.to_string())
+            .arg("-l")
+            .arg(&ssh.user)
+            // Fail instead of prompting: there is no terminal to answer on
+            .args(["-o", "BatchMode=yes"])
+            .args(["-o", "ExitOnForwardFailure=yes"])
+            .args(["-o", "StrictHostKeyChecking=accept-new"])
+            .args(["-o", "ServerAliveInterval=30"]);
+
+        if let Some(key_file) = &ssh.key_file {
+            command.arg("-i").arg(key_file).args(["-o", "IdentitiesOnly=yes"]);
+        }
+
+        command
+            .arg("--")
+            .arg(&ssh.host)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        #[cfg(windows)]
+        command.creation_flags(0x0800_0000); // CREATE_NO_WINDOW
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("Failed to start ssh: {}", e))?;
+
+        let started = Instant::now();
+        loop {
+            if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+                let mut stderr = String::new();
+                if let Some(mut pipe) = child.stderr.take() {
+                    let _ = pipe.read_to_string(&mut stderr).await;
+                }
+                let reason = match stderr.trim() {
+                    "" => status.to_string(),
+                    message => message.to_string(),
+                };
+                return Err(format!("SSH tunnel through {} failed: {}", ssh.host, reason));
+            }
+
+            if tokio::net::TcpStream::connect(("127.0.0.1", local_port)).await.is_ok() {
+                return Ok(Tunnel {
+                    local_port,
+                    _child: child,
+                });
+            }
+
+            if started.elapsed() > TUNNEL_START_TIMEOUT {
+                return Err(format!("SSH tunnel through {} did not start in time", ssh.host));
+            }
+
+            tokio::time::sleep(TUNNEL_POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Ask the OS for a port nothing is listening on, for ssh to bind
+fn free_local_port() -> Result<u16, String> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").map_err(|e| e.to_string())?;
+    listener.local_addr().map(|addr| addr.port()).map_err(|e| e.to_string())
+}
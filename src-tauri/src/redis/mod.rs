@@ -0,0 +1,589 @@
+use ::redis::aio::MultiplexedConnection;
+use ::redis::{
+    from_redis_value, AsyncConnectionConfig, Client, ClientTlsConfig, ConnectionAddr, ConnectionInfo, ProtocolVersion,
+    RedisConnectionInfo, RedisError, TlsCertificates, Value as Reply,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tauri::State;
+
+use crate::postgres::{ConnectionStore, StoredConnection};
+
+mod value;
+
+use value::bytes_to_string;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Longest a single command may take before the console gives up on it
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Keys returned by one `redis_scan_keys` call unless the caller asks for another number
+const DEFAULT_SCAN_COUNT: usize = 200;
+
+// SCAN calls made for one page, so a pattern matching nothing doesn't walk a huge keyspace at once
+const MAX_SCAN_CALLS: usize = 100;
+
+// Entries of a list, hash, set, sorted set or stream read by `redis_get_value` by default
+const DEFAULT_VALUE_LIMIT: usize = 1_000;
+
+// Bytes of a string value read by `redis_get_value`
+const MAX_STRING_BYTES: usize = 1 << 20;
+
+/// A saved Redis server, stored the same way as a `PgConnection`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RedisConnection {
+    pub id: String,
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    /// ACL user; the `default` user when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Only ever sent by the webview when saving or testing; never serialized back
+    #[serde(default, skip_serializing)]
+    pub password: String,
+    /// Database index selected on connect
+    #[serde(default)]
+    pub db: i64,
+    /// Folder path in the connection list, e.g. `prod/eu`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub tls: bool,
+    /// CA certificate (PEM) to verify the server with instead of the system roots
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_root_cert: Option<String>,
+    /// Client certificate (PEM) for mutual TLS; needs `tls_client_key` as well
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_client_cert: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_client_key: Option<String>,
+}
+
+impl StoredConnection for RedisConnection {
+    const KEYRING_SERVICE: &'static str = "devtoolssuite-redis";
+    const CONNECTIONS_FILE: &'static str = "redis-connections.json";
+    const SECRETS_FILE: &'static str = "redis-secrets.enc";
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn password_mut(&mut self) -> &mut String {
+        &mut self.password
+    }
+}
+
+/// Saved Redis connections
+pub type SavedRedisConnections = ConnectionStore<RedisConnection>;
+
+/// Open connections keyed by `RedisConnection.id`, kept in Tauri managed state.
+/// Commands are pipelined over one multiplexed connection per saved connection.
+#[derive(Default)]
+pub struct RedisSessions {
+    // (connection fingerprint, connection)
+    connections: std::sync::Mutex<HashMap<String, (String, MultiplexedConnection)>>,
+}
+
+impl RedisSessions {
+    /// The open connection for `connection`, connecting first if there is none or its settings changed
+    async fn acquire(&self, connection: &RedisConnection) -> Result<MultiplexedConnection, String> {
+        let fingerprint = connection_fingerprint(connection);
+        if let Some((open_fingerprint, conn)) = self.connections.lock().unwrap().get(&connection.id) {
+            if *open_fingerprint == fingerprint {
+                return Ok(conn.clone());
+            }
+        }
+
+        let conn = connect(connection).await?;
+        self.connections
+            .lock()
+            .unwrap()
+            .insert(connection.id.clone(), (fingerprint, conn.clone()));
+        Ok(conn)
+    }
+
+    pub fn close(&self, connection_id: &str) {
+        self.connections.lock().unwrap().remove(connection_id);
+    }
+
+    /// Drop every connection; each closes once the commands still using it finish
+    pub fn close_all(&self) {
+        self.connections.lock().unwrap().clear();
+    }
+
+    /// Message for a failed command, dropping the connection if it can't be used anymore
+    /// so the next command reconnects
+    fn error(&self, connection_id: &str, error: RedisError) -> String {
+        if error.is_unrecoverable_error() || error.is_connection_dropped() {
+            self.close(connection_id);
+        }
+        error.to_string()
+    }
+}
+
+fn connection_fingerprint(connection: &RedisConnection) -> String {
+    format!(
+        "{}\u{0}{}\u{0}{:?}\u{0}{}\u{0}{}\u{0}{}\u{0}{:?}\u{0}{:?}\u{0}{:?}",
+        connection.host,
+        connection.port,
+        connection.username,
+        connection.password,
+        connection.db,
+        connection.tls,
+        connection.tls_root_cert,
+        connection.tls_client_cert,
+        connection.tls_client_key,
+    )
+}
+
+/// Build the client field by field, so no value needs URL escaping
+fn client(connection: &RedisConnection) -> Result<Client, String> {
+    let redis = RedisConnectionInfo {
+        db: connection.db,
+        username: connection.username.clone().filter(|u| !u.is_empty()),
+        password: (!connection.password.is_empty()).then(|| connection.password.clone()),
+        protocol: ProtocolVersion::RESP2,
+    };
+
+    if !connection.tls {
+        let addr = ConnectionAddr::Tcp(connection.host.clone(), connection.port);
+        return Client::open(ConnectionInfo { addr, redis }).map_err(|e| e.to_string());
+    }
+
+    let certificates = [&connection.tls_root_cert, &connection.tls_client_cert, &connection.tls_client_key];
+    for path in certificates.into_iter().flatten() {
+        if !std::path::Path::new(path).is_file() {
+            return Err(format!("Certificate file not found: {}", path));
+        }
+    }
+
+    let read = |path: &String| std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e));
+    let root_cert = connection.tls_root_cert.as_ref().map(read).transpose()?;
+    let client_tls = match (&connection.tls_client_cert, &connection.tls_client_key) {
+        (Some(cert), Some(key)) => Some(ClientTlsConfig {
+            client_cert: read(cert)?,
+            client_key: read(key)?,
+        }),
+        (None, None) => None,
+        _ => return Err("A client certificate and its key must be given together".to_string()),
+    };
+
+    let addr = ConnectionAddr::TcpTls {
+        host: connection.host.clone(),
+        port: connection.port,
+        insecure: false,
+        tls_params: None,
+    };
+    Client::build_with_tls(ConnectionInfo { addr, redis }, TlsCertificates { client_tls, root_cert })
+        .map_err(|e| e.to_string())
+}
+
+/// Open a new connection for a saved connection
+async fn connect(connection: &RedisConnection) -> Result<MultiplexedConnection, String> {
+    let config = AsyncConnectionConfig::new()
+        .set_connection_timeout(CONNECT_TIMEOUT)
+        .set_response_timeout(RESPONSE_TIMEOUT);
+    client(connection)?
+        .get_multiplexed_async_connection_with_config(&config)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// The saved connection's open connection
+async fn session(
+    sessions: &RedisSessions,
+    connections: &SavedRedisConnections,
+    connection_id: &str,
+) -> Result<MultiplexedConnection, String> {
+    let connection = connections.get(connection_id).await?;
+    sessions.acquire(&connection).await
+}
+
+#[derive(Debug, Serialize)]
+pub struct RedisKey {
+    pub key: String,
+    /// `string`, `hash`, `list`, `set`, `zset`, `stream` or a module type such as `ReJSON-RL`
+    pub key_type: String,
+    /// Milliseconds until the key expires; `None` when it never does
+    pub ttl_ms: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RedisKeyPage {
+    pub keys: Vec<RedisKey>,
+    /// Cursor to pass to the next call; 0 once the whole keyspace has been scanned
+    pub cursor: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RedisStreamEntry {
+    pub id: String,
+    pub fields: Vec<(String, String)>,
+}
+
+/// Contents of a key, by type. Members and values that are not UTF-8 are sent as `\x` hex.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum RedisValue {
+    String(String),
+    Hash(Vec<(String, String)>),
+    List(Vec<String>),
+    Set(Vec<String>),
+    /// Members with their scores, lowest score first
+    Zset(Vec<(String, f64)>),
+    Stream(Vec<RedisStreamEntry>),
+}
+
+#[derive(Debug, Serialize)]
+pub struct RedisKeyValue {
+    pub key: String,
+    pub ttl_ms: Option<i64>,
+    /// Length as Redis counts it: bytes of a string, entries of the other types
+    pub length: u64,
+    /// Only part of the value was read: the first `limit` entries, or the first MiB of a string
+    pub truncated: bool,
+    #[serde(flatten)]
+    pub value: RedisValue,
+}
+
+/// PTTL's reply as an optional TTL; -1 means the key has no expiry
+fn ttl(pttl: i64) -> Option<i64> {
+    (pttl >= 0).then_some(pttl)
+}
+
+#[tauri::command]
+pub async fn redis_list_connections(
+    connections: State<'_, SavedRedisConnections>,
+) -> Result<Vec<RedisConnection>, String> {
+    Ok(connections.list().await)
+}
+
+/// Add or update a saved connection. An empty `password` keeps the stored one.
+/// Returns the connection as saved, without its password. An open connection is
+/// replaced on next use if its settings changed.
+#[tauri::command]
+pub async fn redis_save_connection(
+    connections: State<'_, SavedRedisConnections>,
+    connection: RedisConnection,
+) -> Result<RedisConnection, String> {
+    connections.save(connection).await
+}
+
+/// Forget a saved connection and its password, closing its connection
+#[tauri::command]
+pub async fn redis_delete_connection(
+    sessions: State<'_, RedisSessions>,
+    connections: State<'_, SavedRedisConnections>,
+    connection_id: String,
+) -> Result<bool, String> {
+    sessions.close(&connection_id);
+    connections.delete(&connection_id).await
+}
+
+/// Connect with unsaved settings and PING. An empty `password` uses the saved one.
+#[tauri::command]
+pub async fn redis_test_connection(
+    connections: State<'_, SavedRedisConnections>,
+    mut connection: RedisConnection,
+) -> Result<String, String> {
+    if connection.password.is_empty() {
+        if let Ok(saved) = connections.get(&connection.id).await {
+            connection.password = saved.password;
+        }
+    }
+
+    let mut conn = connect(&connection).await?;
+    ::redis::cmd("PING")
+        .query_async::<()>(&mut conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok("Connection successful!".to_string())
+}
+
+/// One page of keys matching `pattern` (a glob, all keys by default), optionally of one
+/// `key_type`, with their type and TTL. Start with cursor 0 and pass back the returned
+/// cursor until it is 0 again. Keys can repeat across pages, as with SCAN itself.
+#[tauri::command]
+pub async fn redis_scan_keys(
+    sessions: State<'_, RedisSessions>,
+    connections: State<'_, SavedRedisConnections>,
+    connection_id: String,
+    pattern: Option<String>,
+    cursor: u64,
+    count: Option<usize>,
+    key_type: Option<String>,
+) -> Result<RedisKeyPage, String> {
+    let mut conn = session(&sessions, &connections, &connection_id).await?;
+    let pattern = pattern.filter(|p| !p.is_empty()).unwrap_or_else(|| "*".to_string());
+    let count = count.unwrap_or(DEFAULT_SCAN_COUNT).max(1);
+
+    let mut names: Vec<Vec<u8>> = Vec::new();
+    let mut cursor = cursor;
+    for _ in 0..MAX_SCAN_CALLS {
+        let mut scan = ::redis::cmd("SCAN");
+        scan.arg(cursor).arg("MATCH").arg(&pattern).arg("COUNT").arg(count);
+        if let Some(key_type) = key_type.as_deref().filter(|t| !t.is_empty()) {
+            scan.arg("TYPE").arg(key_type);
+        }
+
+        let (next, batch): (u64, Vec<Vec<u8>>) = scan
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| sessions.error(&connection_id, e))?;
+        names.extend(batch);
+        cursor = next;
+        if cursor == 0 || names.len() >= count {
+            break;
+        }
+    }
+
+    if names.is_empty() {
+        return Ok(RedisKeyPage { keys: Vec::new(), cursor });
+    }
+
+    let mut pipe = ::redis::pipe();
+    for name in &names {
+        pipe.cmd("TYPE").arg(name).cmd("PTTL").arg(name);
+    }
+    let replies: Vec<Reply> = pipe
+        .query_async(&mut conn)
+        .await
+        .map_err(|e| sessions.error(&connection_id, e))?;
+
+    let mut keys = Vec::with_capacity(names.len());
+    for (name, reply) in names.iter().zip(replies.chunks_exact(2)) {
+        let key_type: String = from_redis_value(&reply[0]).map_err(|e| e.to_string())?;
+        let pttl: i64 = from_redis_value(&reply[1]).map_err(|e| e.to_string())?;
+        // Expired or deleted since it was scanned
+        if key_type == "none" || pttl == -2 {
+            continue;
+        }
+
+        keys.push(RedisKey {
+            key: bytes_to_string(name),
+            key_type,
+            ttl_ms: ttl(pttl),
+        });
+    }
+
+    Ok(RedisKeyPage { keys, cursor })
+}
+
+/// The value of a key, read according to its type. Collections are read up to `limit`
+/// entries: lists, sorted sets and streams from the start, hashes and sets in SCAN order.
+#[tauri::command]
+pub async fn redis_get_value(
+    sessions: State<'_, RedisSessions>,
+    connections: State<'_, SavedRedisConnections>,
+    connection_id: String,
+    key: String,
+    limit: Option<usize>,
+) -> Result<RedisKeyValue, String> {
+    let mut conn = session(&sessions, &connections, &connection_id).await?;
+    let limit = limit.unwrap_or(DEFAULT_VALUE_LIMIT).max(1);
+
+    let (key_type, pttl): (String, i64) = ::redis::pipe()
+        .cmd("TYPE")
+        .arg(&key)
+        .cmd("PTTL")
+        .arg(&key)
+        .query_async(&mut conn)
+        .await
+        .map_err(|e| sessions.error(&connection_id, e))?;
+
+    let read = read_value(&mut conn, &key, &key_type, limit).await;
+    let (length, value) = read.map_err(|e| sessions.error(&connection_id, e))?.ok_or_else(|| {
+        match key_type.as_str() {
+            "none" => format!("Key not found: {}", key),
+            other => format!("Viewing {} keys is not supported", other),
+        }
+    })?;
+
+    let read_count = match &value {
+        RedisValue::String(_) => MAX_STRING_BYTES,
+        _ => limit,
+    };
+
+    Ok(RedisKeyValue {
+        key,
+        ttl_ms: ttl(pttl),
+        length,
+        truncated: length > read_count as u64,
+        value,
+    })
+}
+
+// XRANGE's reply: entry ids, each with its fields and values alternating
+type StreamRange = Vec<(String, Vec<Vec<u8>>)>;
+
+/// Length and value of a key of a type that can be viewed; `None` for other types
+async fn read_value(
+    conn: &mut MultiplexedConnection,
+    key: &str,
+    key_type: &str,
+    limit: usize,
+) -> Result<Option<(u64, RedisValue)>, RedisError> {
+    let last = limit as i64 - 1;
+    let read = match key_type {
+        "string" => {
+            let (length, bytes): (u64, Vec<u8>) = ::redis::pipe()
+                .cmd("STRLEN")
+                .arg(key)
+                .cmd("GETRANGE")
+                .arg(key)
+                .arg(0)
+                .arg(MAX_STRING_BYTES - 1)
+                .query_async(conn)
+                .await?;
+            (length, RedisValue::String(bytes_to_string(&bytes)))
+        }
+        "list" => {
+            let (length, items): (u64, Vec<Vec<u8>>) = ::redis::pipe()
+                .cmd("LLEN")
+                .arg(key)
+                .cmd("LRANGE")
+                .arg(key)
+                .arg(0)
+                .arg(last)
+                .query_async(conn)
+                .await?;
+            (length, RedisValue::List(items.iter().map(|i| bytes_to_string(i)).collect()))
+        }
+        "hash" => {
+            let length: u64 = ::redis::cmd("HLEN").arg(key).query_async(conn).await?;
+            let flat = scan_collection(conn, "HSCAN", key, limit * 2).await?;
+            (length, RedisValue::Hash(pairs(&flat)))
+        }
+        "set" => {
+            let length: u64 = ::redis::cmd("SCARD").arg(key).query_async(conn).await?;
+            let members = scan_collection(conn, "SSCAN", key, limit).await?;
+            (length, RedisValue::Set(members.iter().map(|m| bytes_to_string(m)).collect()))
+        }
+        "zset" => {
+            let (length, members): (u64, Vec<(Vec<u8>, f64)>) = ::redis::pipe()
+                .cmd("ZCARD")
+                .arg(key)
+                .cmd("ZRANGE")
+                .arg(key)
+                .arg(0)
+                .arg(last)
+                .arg("WITHSCORES")
+                .query_async(conn)
+                .await?;
+            let members = members.iter().map(|(m, score)| (bytes_to_string(m), *score)).collect();
+            (length, RedisValue::Zset(members))
+        }
+        "stream" => {
+            let (length, entries): (u64, StreamRange) = ::redis::pipe()
+                .cmd("XLEN")
+                .arg(key)
+                .cmd("XRANGE")
+                .arg(key)
+                .arg("-")
+                .arg("+")
+                .arg("COUNT")
+                .arg(limit)
+                .query_async(conn)
+                .await?;
+            let entries = entries
+                .into_iter()
+                .map(|(id, fields)| RedisStreamEntry { id, fields: pairs(&fields) })
+                .collect();
+            (length, RedisValue::Stream(entries))
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(read))
+}
+
+/// Elements of a hash (fields and values alternating) or set, read with HSCAN or SSCAN
+/// until at least `limit` have come back
+async fn scan_collection(
+    conn: &mut MultiplexedConnection,
+    command: &str,
+    key: &str,
+    limit: usize,
+) -> Result<Vec<Vec<u8>>, RedisError> {
+    let mut elements = Vec::new();
+    let mut cursor = 0u64;
+    loop {
+        let (next, batch): (u64, Vec<Vec<u8>>) = ::redis::cmd(command)
+            .arg(key)
+            .arg(cursor)
+            .arg("COUNT")
+            .arg(limit.min(DEFAULT_VALUE_LIMIT))
+            .query_async(conn)
+            .await?;
+        elements.extend(batch);
+        cursor = next;
+        if cursor == 0 || elements.len() >= limit {
+            break;
+        }
+    }
+
+    elements.truncate(limit);
+    Ok(elements)
+}
+
+fn pairs(flat: &[Vec<u8>]) -> Vec<(String, String)> {
+    flat.chunks_exact(2)
+        .map(|pair| (bytes_to_string(&pair[0]), bytes_to_string(&pair[1])))
+        .collect()
+}
+
+/// Set a key to expire after `ttl_ms` milliseconds, or never when `ttl_ms` is not given.
+/// Returns false if the key doesn't exist (or, when clearing, had no expiry).
+#[tauri::command]
+pub async fn redis_set_ttl(
+    sessions: State<'_, RedisSessions>,
+    connections: State<'_, SavedRedisConnections>,
+    connection_id: String,
+    key: String,
+    ttl_ms: Option<i64>,
+) -> Result<bool, String> {
+    let command = match ttl_ms {
+        None => ::redis::cmd("PERSIST").arg(&key).clone(),
+        Some(ms) if ms > 0 => ::redis::cmd("PEXPIRE").arg(&key).arg(ms).clone(),
+        Some(_) => return Err("TTL must be positive; delete the key to remove it now".to_string()),
+    };
+
+    let mut conn = session(&sessions, &connections, &connection_id).await?;
+    let changed: i64 = command
+        .query_async(&mut conn)
+        .await
+        .map_err(|e| sessions.error(&connection_id, e))?;
+    Ok(changed == 1)
+}
+
+/// Run one console line, e.g. `HSET user:1 name "Ada Lovelace"`, and return its reply as JSON.
+/// Arguments are quoted as in redis-cli.
+#[tauri::command]
+pub async fn redis_execute_command(
+    sessions: State<'_, RedisSessions>,
+    connections: State<'_, SavedRedisConnections>,
+    connection_id: String,
+    command: String,
+) -> Result<serde_json::Value, String> {
+    let args = value::split_command(&command)?;
+    if args.is_empty() {
+        return Err("No command given".to_string());
+    }
+    value::check_console_command(&args)?;
+
+    let mut cmd = ::redis::Cmd::new();
+    for arg in &args {
+        cmd.arg(arg);
+    }
+
+    let mut conn = session(&sessions, &connections, &connection_id).await?;
+    let reply: Reply = cmd
+        .query_async(&mut conn)
+        .await
+        .map_err(|e| sessions.error(&connection_id, e))?;
+    Ok(value::reply_to_json(&reply))
+}
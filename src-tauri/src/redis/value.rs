@@ -0,0 +1,307 @@
+//! Redis console input and replies.
+//!
+//! Commands are split into arguments the way redis-cli does it, and replies of either RESP
+//! version are turned into JSON. Redis strings are binary-safe, so bytes that are not UTF-8
+//! are shown as `\x`-prefixed hex, as the Postgres client shows `bytea`.
+
+use ::redis::Value as Reply;
+use serde_json::Value;
+use std::fmt::Write;
+
+// Integers outside this range lose precision in JavaScript, so they are sent as strings
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+// Commands that take over the connection, which every command of a saved connection shares
+const TAKEOVER_COMMANDS: &[&str] = &["SUBSCRIBE", "PSUBSCRIBE", "SSUBSCRIBE", "MONITOR", "SYNC", "PSYNC"];
+
+// Commands that change the shared connection's database, protocol, user or transaction state
+const CONNECTION_STATE_COMMANDS: &[&str] =
+    &["SELECT", "HELLO", "AUTH", "MULTI", "EXEC", "DISCARD", "WATCH", "UNWATCH", "RESET", "QUIT"];
+
+// Commands that wait on the server, holding up every command pipelined behind them
+const BLOCKING_COMMANDS: &[&str] = &[
+    "BLPOP", "BRPOP", "BLMOVE", "BRPOPLPUSH", "BZPOPMIN", "BZPOPMAX", "BLMPOP", "BZMPOP", "WAIT", "WAITAOF",
+];
+
+/// Split a console line into arguments. Double-quoted arguments take redis-cli's escapes
+/// (`\n`, `\r`, `\t`, `\b`, `\a`, `\\`, `\"` and `\xHH`); single-quoted ones only `\'`.
+pub fn split_command(line: &str) -> Result<Vec<Vec<u8>>, String> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(first) = chars.next() else {
+            return Ok(args);
+        };
+
+        let mut arg = Vec::new();
+        match first {
+            '"' => loop {
+                match chars.next() {
+                    None => return Err("Unbalanced quotes in command".to_string()),
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => arg.push(b'\n'),
+                        Some('r') => arg.push(b'\r'),
+                        Some('t') => arg.push(b'\t'),
+                        Some('b') => arg.push(0x08),
+                        Some('a') => arg.push(0x07),
+                        Some('x') => {
+                            let hex: String = chars.by_ref().take(2).collect();
+                            match u8::from_str_radix(&hex, 16) {
+                                Ok(byte) if hex.len() == 2 => arg.push(byte),
+                                _ => return Err(format!("Invalid escape \\x{} in command", hex)),
+                            }
+                        }
+                        Some(c) => push_char(&mut arg, c),
+                        None => return Err("Unbalanced quotes in command".to_string()),
+                    },
+                    Some(c) => push_char(&mut arg, c),
+                }
+            },
+            '\'' => loop {
+                match chars.next() {
+                    None => return Err("Unbalanced quotes in command".to_string()),
+                    Some('\'') => break,
+                    Some('\\') if chars.peek() == Some(&'\'') => {
+                        chars.next();
+                        arg.push(b'\'');
+                    }
+                    Some(c) => push_char(&mut arg, c),
+                }
+            },
+            c => {
+                push_char(&mut arg, c);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    push_char(&mut arg, c);
+                }
+            }
+        }
+
+        // A closing quote must end the argument, as in redis-cli
+        if matches!(first, '"' | '\'') && chars.peek().is_some_and(|c| !c.is_whitespace()) {
+            return Err("Closing quote must be followed by a space".to_string());
+        }
+        args.push(arg);
+    }
+}
+
+/// Refuse commands the console can't run on the connection it shares with the rest of the app
+pub fn check_console_command(args: &[Vec<u8>]) -> Result<(), String> {
+    let words: Vec<String> = args.iter().map(|arg| String::from_utf8_lossy(arg).to_uppercase()).collect();
+    let Some(name) = words.first() else {
+        return Ok(());
+    };
+
+    let blocking = match name.as_str() {
+        name if BLOCKING_COMMANDS.contains(&name) => true,
+        // Options come before STREAMS; XREADGROUP's first three arguments name the group and consumer
+        "XREAD" | "XREADGROUP" => {
+            let skip = if name == "XREAD" { 1 } else { 4 };
+            words.iter().skip(skip).take_while(|word| *word != "STREAMS").any(|word| word == "BLOCK")
+        }
+        _ => false,
+    };
+    let subcommand = words.get(1).map_or("", String::as_str);
+
+    if TAKEOVER_COMMANDS.contains(&name.as_str()) {
+        Err(format!("{} is not supported in the console", name))
+    } else if CONNECTION_STATE_COMMANDS.contains(&name.as_str())
+        || (name == "CLIENT" && matches!(subcommand, "REPLY" | "TRACKING"))
+    {
+        let name = if name == "CLIENT" { format!("CLIENT {}", subcommand) } else { name.clone() };
+        Err(format!(
+            "{} is not supported in the console; it would change the connection every command shares",
+            name
+        ))
+    } else if blocking {
+        Err(format!(
+            "{} is not supported in the console; it would hold up every command on the connection while it blocks",
+            name
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn push_char(arg: &mut Vec<u8>, c: char) {
+    let mut buf = [0; 4];
+    arg.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+}
+
+/// A Redis string as text: UTF-8 as is, anything else as hex
+pub fn bytes_to_string(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => hex_bytes(bytes),
+    }
+}
+
+/// A reply as JSON. Maps become objects when all their keys are strings and arrays of
+/// `[key, value]` pairs otherwise; errors nested in a reply become `{"error": "..."}`.
+pub fn reply_to_json(reply: &Reply) -> Value {
+    match reply {
+        Reply::Nil => Value::Null,
+        Reply::Int(n) => int_value(*n),
+        Reply::BulkString(bytes) => Value::String(bytes_to_string(bytes)),
+        Reply::SimpleString(text) => Value::String(text.clone()),
+        Reply::Okay => Value::String("OK".to_string()),
+        Reply::Array(items) | Reply::Set(items) | Reply::Push { data: items, .. } => {
+            Value::Array(items.iter().map(reply_to_json).collect())
+        }
+        Reply::Map(entries) => {
+            let keys: Option<Vec<String>> = entries.iter().map(|(key, _)| reply_text(key)).collect();
+            match keys {
+                Some(keys) => Value::Object(
+                    keys.into_iter()
+                        .zip(entries.iter().map(|(_, value)| reply_to_json(value)))
+                        .collect(),
+                ),
+                None => Value::Array(
+                    entries
+                        .iter()
+                        .map(|(key, value)| Value::Array(vec![reply_to_json(key), reply_to_json(value)]))
+                        .collect(),
+                ),
+            }
+        }
+        Reply::Attribute { data, .. } => reply_to_json(data),
+        Reply::Double(v) => {
+            serde_json::Number::from_f64(*v).map_or_else(|| Value::String(v.to_string()), Value::Number)
+        }
+        Reply::Boolean(b) => Value::Bool(*b),
+        Reply::VerbatimString { text, .. } => Value::String(text.clone()),
+        Reply::BigNumber(n) => Value::String(n.to_string()),
+        Reply::ServerError(error) => {
+            let message = match error.details() {
+                Some(details) => format!("{} {}", error.code(), details),
+                None => error.code().to_string(),
+            };
+            serde_json::json!({ "error": message })
+        }
+    }
+}
+
+/// Text of a string-like reply, used for map keys
+fn reply_text(reply: &Reply) -> Option<String> {
+    match reply {
+        Reply::BulkString(bytes) => Some(bytes_to_string(bytes)),
+        Reply::SimpleString(text) | Reply::VerbatimString { text, .. } => Some(text.clone()),
+        _ => None,
+    }
+}
+
+fn int_value(v: i64) -> Value {
+    if (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&v) {
+        Value::from(v)
+    } else {
+        Value::String(v.to_string())
+    }
+}
+
+/// `\x`-prefixed hex, as the Postgres client shows `bytea`
+fn hex_bytes(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(2 + bytes.len() * 2);
+    s.push_str("\\x");
+    for b in bytes {
+        let _ = write!(s, "{:02x}", b);
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn args(line: &str) -> Vec<String> {
+        split_command(line)
+            .unwrap()
+            .into_iter()
+            .map(|arg| String::from_utf8(arg).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn splits_commands_like_redis_cli() {
+        assert_eq!(args("  HSET user:1   name \"Ada Lovelace\" "), vec!["HSET", "user:1", "name", "Ada Lovelace"]);
+        assert_eq!(args(r#"SET k "a\"b\n\t\\""#), vec!["SET", "k", "a\"b\n\t\\"]);
+        assert_eq!(args(r"SET k 'it\'s \n'"), vec!["SET", "k", "it's \\n"]);
+        assert_eq!(args(r#"SET k """#), vec!["SET", "k", ""]);
+        assert_eq!(split_command(r#"SET k "\xff\x00""#).unwrap()[2], vec![0xff, 0x00]);
+        assert!(split_command("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_quoting() {
+        assert_eq!(split_command("GET \"k").unwrap_err(), "Unbalanced quotes in command");
+        assert_eq!(split_command("GET 'k").unwrap_err(), "Unbalanced quotes in command");
+        assert_eq!(split_command("GET \"k\"x").unwrap_err(), "Closing quote must be followed by a space");
+        assert_eq!(split_command(r#"GET "\xZZ""#).unwrap_err(), "Invalid escape \\xZZ in command");
+    }
+
+    #[test]
+    fn turns_replies_into_json() {
+        assert_eq!(reply_to_json(&Reply::Nil), Value::Null);
+        assert_eq!(reply_to_json(&Reply::Okay), json!("OK"));
+        assert_eq!(reply_to_json(&Reply::Int(42)), json!(42));
+        assert_eq!(reply_to_json(&Reply::Int(i64::MAX)), json!(i64::MAX.to_string()));
+        assert_eq!(reply_to_json(&Reply::BulkString(vec![0xff, 0x00])), json!("\\xff00"));
+        assert_eq!(reply_to_json(&Reply::Double(1.5)), json!(1.5));
+        assert_eq!(reply_to_json(&Reply::Double(f64::INFINITY)), json!("inf"));
+        assert_eq!(reply_to_json(&Reply::Boolean(true)), json!(true));
+        assert_eq!(
+            reply_to_json(&Reply::Array(vec![Reply::BulkString(b"a".to_vec()), Reply::Nil])),
+            json!(["a", null])
+        );
+    }
+
+    #[test]
+    fn turns_maps_into_objects_when_keys_are_strings() {
+        let by_name = Reply::Map(vec![
+            (Reply::BulkString(b"name".to_vec()), Reply::BulkString(b"Ada".to_vec())),
+            (Reply::SimpleString("age".to_string()), Reply::Int(36)),
+        ]);
+        assert_eq!(reply_to_json(&by_name), json!({ "name": "Ada", "age": 36 }));
+
+        let by_number = Reply::Map(vec![(Reply::Int(1), Reply::Okay)]);
+        assert_eq!(reply_to_json(&by_number), json!([[1, "OK"]]));
+    }
+
+    #[test]
+    fn refuses_commands_that_would_disturb_the_shared_connection() {
+        let check = |line: &str| check_console_command(&split_command(line).unwrap());
+        for line in [
+            "subscribe news",
+            "SELECT 2",
+            "hello 3",
+            "MULTI",
+            "exec",
+            "WATCH k",
+            "CLIENT REPLY OFF",
+            "RESET",
+            "QUIT",
+            "BLPOP q 0",
+            "bzpopmin z 1",
+            "WAIT 1 0",
+            "XREAD COUNT 1 BLOCK 0 STREAMS s $",
+            "XREADGROUP GROUP g c BLOCK 100 STREAMS s >",
+        ] {
+            assert!(check(line).is_err(), "{}", line);
+        }
+        for line in [
+            "GET k",
+            "LPOP q",
+            "CLIENT LIST",
+            "XREAD COUNT 1 STREAMS block 0",
+            "XREADGROUP GROUP block block STREAMS s >",
+        ] {
+            assert!(check(line).is_ok(), "{}", line);
+        }
+        assert_eq!(
+            check("select 1").unwrap_err(),
+            "SELECT is not supported in the console; it would change the connection every command shares"
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Decoding of SQLite values into JSON.
+//!
+//! SQLite columns have no fixed type, so each value is decoded by its own storage class
+//! (NULL, INTEGER, REAL, TEXT or BLOB) rather than by the declared column type.
+
+use serde_json::Value;
+use sqlx::sqlite::{SqliteRow, SqliteValueRef};
+use sqlx::{Column, Decode, Row, Sqlite, TypeInfo, ValueRef};
+use std::fmt::Write;
+
+// Integers outside this range lose precision in JavaScript, so they are sent as strings
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+/// Declared type of a column, e.g. `INTEGER` or `VARCHAR(20)`; `NULL` for expressions
+pub fn type_name(column: &sqlx::sqlite::SqliteColumn) -> String {
+    column.type_info().name().to_string()
+}
+
+pub fn row_to_json(row: &SqliteRow) -> Vec<Value> {
+    (0..row.columns().len())
+        .map(|i| match row.try_get_raw(i) {
+            Ok(value) => value_to_json(value),
+            Err(_) => Value::Null,
+        })
+        .collect()
+}
+
+fn value_to_json(value: SqliteValueRef<'_>) -> Value {
+    if value.is_null() {
+        return Value::Null;
+    }
+
+    let storage_class = value.type_info().name().to_string();
+    match storage_class.as_str() {
+        "INTEGER" => match <i64 as Decode<Sqlite>>::decode(value) {
+            Ok(n) if (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&n) => Value::from(n),
+            Ok(n) => Value::String(n.to_string()),
+            Err(_) => Value::Null,
+        },
+        "REAL" => <f64 as Decode<Sqlite>>::decode(value)
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map_or(Value::Null, Value::Number),
+        "BLOB" => <Vec<u8> as Decode<Sqlite>>::decode(value)
+            .map(|bytes| Value::String(hex_bytes(&bytes)))
+            .unwrap_or(Value::Null),
+        _ => <String as Decode<Sqlite>>::decode(value)
+            .map(Value::String)
+            .unwrap_or(Value::Null),
+    }
+}
+
+/// `\x`-prefixed hex, as the Postgres client shows `bytea`
+fn hex_bytes(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(2 + bytes.len() * 2);
+    s.push_str("\\x");
+    for byte in bytes {
+        let _ = write!(s, "{:02x}", byte);
+    }
+    s
+}
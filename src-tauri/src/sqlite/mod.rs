@@ -0,0 +1,580 @@
+use futures_util::TryStreamExt;
+use serde::Serialize;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteRow};
+use sqlx::{Column, ConnectOptions, Either, Executor, Row};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::State;
+use tokio::sync::Mutex;
+
+use crate::postgres::driver::DriverKind;
+use crate::postgres::script::split_statements;
+use crate::postgres::{QueryOptions, QueryResult, TableInfo, DEFAULT_MAX_ROWS};
+
+mod decode;
+
+/// An open database file
+pub struct SqliteDatabase {
+    pub id: String,
+    pub path: PathBuf,
+    pub read_only: bool,
+    conn: Mutex<sqlx::SqliteConnection>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SqliteDatabaseInfo {
+    pub id: String,
+    pub path: String,
+    /// File name without its directory, for display
+    pub name: String,
+    pub read_only: bool,
+}
+
+impl SqliteDatabase {
+    fn info(&self) -> SqliteDatabaseInfo {
+        SqliteDatabaseInfo {
+            id: self.id.clone(),
+            path: self.path.display().to_string(),
+            name: self
+                .path
+                .file_name()
+                .map_or_else(|| self.path.display().to_string(), |name| name.to_string_lossy().into_owned()),
+            read_only: self.read_only,
+        }
+    }
+}
+
+/// Open SQLite databases by id, kept in Tauri managed state
+#[derive(Default)]
+pub struct SqliteDatabases {
+    databases: Mutex<HashMap<String, Arc<SqliteDatabase>>>,
+}
+
+impl SqliteDatabases {
+    async fn get(&self, database_id: &str) -> Result<Arc<SqliteDatabase>, String> {
+        self.databases
+            .lock()
+            .await
+            .get(database_id)
+            .cloned()
+            .ok_or_else(|| format!("No open database with id {}", database_id))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SqliteColumn {
+    pub name: String,
+    /// Declared type, which may be empty; SQLite doesn't enforce it unless the table is STRICT
+    pub data_type: String,
+    pub nullable: bool,
+    pub default_value: Option<String>,
+    /// 1-based position in the primary key, 0 if not part of it
+    pub primary_key_position: i64,
+    /// Generated or hidden virtual table column
+    pub hidden: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SqliteForeignKey {
+    pub columns: Vec<String>,
+    pub referenced_table: String,
+    /// Empty when the key references the parent's primary key implicitly
+    pub referenced_columns: Vec<String>,
+    pub on_update: String,
+    pub on_delete: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SqliteIndex {
+    pub name: String,
+    pub table: String,
+    pub columns: Vec<String>,
+    pub is_unique: bool,
+    /// `c` for CREATE INDEX, `u` for a UNIQUE constraint, `pk` for the primary key
+    pub origin: String,
+    pub is_partial: bool,
+    /// `CREATE INDEX` statement; `None` for indexes SQLite creates for constraints
+    pub definition: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SqliteTableDetails {
+    pub schema: String,
+    pub name: String,
+    pub table_type: String,
+    /// `CREATE` statement as stored in `sqlite_schema`
+    pub definition: Option<String>,
+    pub columns: Vec<SqliteColumn>,
+    pub foreign_keys: Vec<SqliteForeignKey>,
+    pub indexes: Vec<SqliteIndex>,
+}
+
+/// Quote an identifier for use in generated SQL
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn quote_literal(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// Same names as Postgres' information_schema uses, so the table list looks alike
+fn table_type(kind: &str, sql: Option<&str>) -> &'static str {
+    let is_virtual = sql.is_some_and(|sql| sql.trim_start().to_ascii_uppercase().starts_with("CREATE VIRTUAL"));
+    match kind {
+        "view" => "VIEW",
+        _ if is_virtual => "VIRTUAL TABLE",
+        _ => "BASE TABLE",
+    }
+}
+
+/// Run every statement of a query, keeping at most `max_rows` of the rows they return
+async fn fetch_rows(conn: &mut sqlx::SqliteConnection, query: &str, max_rows: usize) -> Result<QueryResult, String> {
+    let mut result = QueryResult::default();
+    let mut changed_before = total_changes(conn).await?;
+
+    // Statements run one at a time: SQLite reports the last write's change count for every
+    // statement, so only a move of `total_changes()` tells that a statement wrote
+    for statement in split_statements(query, DriverKind::Postgres) {
+        let mut statement_rows = 0;
+        let mut changes = 0;
+        let mut steps = conn.fetch_many(statement);
+
+        while let Some(step) = steps.try_next().await.map_err(|e| e.to_string())? {
+            let row: SqliteRow = match step {
+                Either::Left(done) => {
+                    changes = done.rows_affected();
+                    continue;
+                }
+                Either::Right(row) => row,
+            };
+            statement_rows += 1;
+
+            if result.row_count == max_rows {
+                // Keep stepping without decoding, so the statement runs to its end
+                result.truncated = true;
+                continue;
+            }
+
+            if result.row_count == 0 {
+                for column in row.columns() {
+                    result.columns.push(column.name().to_string());
+                    result.column_types.push(decode::type_name(column));
+                }
+            }
+
+            result.rows.push(decode::row_to_json(&row));
+            result.row_count += 1;
+        }
+        drop(steps);
+
+        let changed = total_changes(conn).await?;
+        // Statements that return rows count those
+        result.rows_affected += if statement_rows > 0 {
+            statement_rows
+        } else if changed != changed_before {
+            changes
+        } else {
+            0
+        };
+        changed_before = changed;
+    }
+
+    Ok(result)
+}
+
+/// Rows changed since the connection was opened, including by triggers
+async fn total_changes(conn: &mut sqlx::SqliteConnection) -> Result<i64, String> {
+    sqlx::query_scalar("SELECT total_changes()")
+        .fetch_one(conn)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Indexes of one table, with their columns in key order
+async fn load_indexes(conn: &mut sqlx::SqliteConnection, schema: &str, table: &str) -> Result<Vec<SqliteIndex>, sqlx::Error> {
+    let list = sqlx::query("SELECT name, \"unique\", origin, partial FROM pragma_index_list(?1, ?2) ORDER BY seq")
+        .bind(table)
+        .bind(schema)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let mut indexes = Vec::with_capacity(list.len());
+    for row in list {
+        let name: String = row.get("name");
+        let columns = sqlx::query_scalar::<_, Option<String>>(
+            "SELECT name FROM pragma_index_info(?1, ?2) ORDER BY seqno",
+        )
+        .bind(&name)
+        .bind(schema)
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        // Expression columns have no name
+        .map(|column| column.unwrap_or_else(|| "<expression>".to_string()))
+        .collect();
+        let definition = sqlx::query_scalar::<_, Option<String>>(&format!(
+            "SELECT sql FROM {}.sqlite_schema WHERE type = 'index' AND name = ?1",
+            quote_identifier(schema)
+        ))
+        .bind(&name)
+        .fetch_optional(&mut *conn)
+        .await?
+        .flatten();
+
+        indexes.push(SqliteIndex {
+            name,
+            table: table.to_string(),
+            columns,
+            is_unique: row.get::<i64, _>("unique") != 0,
+            origin: row.get("origin"),
+            is_partial: row.get::<i64, _>("partial") != 0,
+            definition,
+        });
+    }
+    Ok(indexes)
+}
+
+/// Open a database file. Read-only databases are opened with `SQLITE_OPEN_READONLY`, so
+/// SQLite itself refuses every write. A missing file is only created with `create`.
+#[tauri::command]
+pub async fn sqlite_open(
+    databases: State<'_, SqliteDatabases>,
+    path: String,
+    read_only: Option<bool>,
+    create: Option<bool>,
+) -> Result<SqliteDatabaseInfo, String> {
+    let read_only = read_only.unwrap_or(false);
+    let create = create.unwrap_or(false) && !read_only;
+    let path = PathBuf::from(path);
+    if !create && !path.is_file() {
+        return Err(format!("{} does not exist", path.display()));
+    }
+
+    let conn = SqliteConnectOptions::new()
+        .filename(&path)
+        .read_only(read_only)
+        .create_if_missing(create)
+        .connect()
+        .await
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+
+    let database = Arc::new(SqliteDatabase {
+        id: uuid::Uuid::new_v4().to_string(),
+        path,
+        read_only,
+        conn: Mutex::new(conn),
+    });
+    let info = database.info();
+    databases.databases.lock().await.insert(database.id.clone(), database);
+
+    Ok(info)
+}
+
+#[tauri::command]
+pub async fn sqlite_close(databases: State<'_, SqliteDatabases>, database_id: String) -> Result<bool, String> {
+    let Some(database) = databases.databases.lock().await.remove(&database_id) else {
+        return Ok(false);
+    };
+
+    // The connection closes once a running query finishes and drops the last reference
+    drop(database);
+    Ok(true)
+}
+
+#[tauri::command]
+pub async fn sqlite_list_databases(databases: State<'_, SqliteDatabases>) -> Result<Vec<SqliteDatabaseInfo>, String> {
+    let mut list: Vec<SqliteDatabaseInfo> = databases.databases.lock().await.values().map(|d| d.info()).collect();
+    list.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(list)
+}
+
+/// Tables and views of the main database and any attached ones, without SQLite's own tables
+#[tauri::command]
+pub async fn sqlite_get_tables(
+    databases: State<'_, SqliteDatabases>,
+    database_id: String,
+) -> Result<Vec<TableInfo>, String> {
+    let database = databases.get(&database_id).await?;
+    let mut conn = database.conn.lock().await;
+
+    let schemas: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_database_list ORDER BY seq")
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut tables = Vec::new();
+    for schema in schemas {
+        let rows = sqlx::query(&format!(
+            "SELECT name, type, sql FROM {}.sqlite_schema \
+             WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' \
+             ORDER BY name",
+            quote_identifier(&schema)
+        ))
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        tables.extend(rows.iter().map(|row| TableInfo {
+            schema: schema.clone(),
+            name: row.get("name"),
+            table_type: table_type(row.get("type"), row.get("sql")).to_string(),
+        }));
+    }
+
+    Ok(tables)
+}
+
+/// Indexes of one table, or of every table in `schema` (default `main`)
+#[tauri::command]
+pub async fn sqlite_list_indexes(
+    databases: State<'_, SqliteDatabases>,
+    database_id: String,
+    schema: Option<String>,
+    table: Option<String>,
+) -> Result<Vec<SqliteIndex>, String> {
+    let database = databases.get(&database_id).await?;
+    let mut conn = database.conn.lock().await;
+    let schema = schema.unwrap_or_else(|| "main".to_string());
+
+    let tables = match table {
+        Some(table) => vec![table],
+        None => sqlx::query_scalar(&format!(
+            "SELECT name FROM {}.sqlite_schema WHERE type = 'table' ORDER BY name",
+            quote_identifier(&schema)
+        ))
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?,
+    };
+
+    let mut indexes = Vec::new();
+    for table in tables {
+        indexes.extend(load_indexes(&mut conn, &schema, &table).await.map_err(|e| e.to_string())?);
+    }
+    Ok(indexes)
+}
+
+/// Columns, foreign keys, indexes and the `CREATE` statement of a table or view
+#[tauri::command]
+pub async fn sqlite_describe_table(
+    databases: State<'_, SqliteDatabases>,
+    database_id: String,
+    schema: Option<String>,
+    table: String,
+) -> Result<SqliteTableDetails, String> {
+    let database = databases.get(&database_id).await?;
+    let mut conn = database.conn.lock().await;
+    let schema = schema.unwrap_or_else(|| "main".to_string());
+
+    let entry = sqlx::query(&format!(
+        "SELECT type, sql FROM {}.sqlite_schema WHERE type IN ('table', 'view') AND name = ?1",
+        quote_identifier(&schema)
+    ))
+    .bind(&table)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Table \"{}\".\"{}\" not found", schema, table))?;
+    let definition: Option<String> = entry.get("sql");
+
+    let columns = sqlx::query(
+        "SELECT name, type, \"notnull\", dflt_value, pk, hidden FROM pragma_table_xinfo(?1, ?2) ORDER BY cid",
+    )
+    .bind(&table)
+    .bind(&schema)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?
+    .iter()
+    .map(|row| SqliteColumn {
+        name: row.get("name"),
+        data_type: row.get("type"),
+        nullable: row.get::<i64, _>("notnull") == 0,
+        default_value: row.get("dflt_value"),
+        primary_key_position: row.get("pk"),
+        hidden: row.get::<i64, _>("hidden") != 0,
+    })
+    .collect();
+
+    let mut foreign_keys: Vec<(i64, SqliteForeignKey)> = Vec::new();
+    let key_rows = sqlx::query(
+        "SELECT id, \"table\", \"from\", \"to\", on_update, on_delete FROM pragma_foreign_key_list(?1, ?2) ORDER BY id, seq",
+    )
+    .bind(&table)
+    .bind(&schema)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+    for row in key_rows {
+        let id: i64 = row.get("id");
+        if foreign_keys.last().is_none_or(|(last, _)| *last != id) {
+            foreign_keys.push((
+                id,
+                SqliteForeignKey {
+                    columns: Vec::new(),
+                    referenced_table: row.get("table"),
+                    referenced_columns: Vec::new(),
+                    on_update: row.get("on_update"),
+                    on_delete: row.get("on_delete"),
+                },
+            ));
+        }
+        let (_, key) = foreign_keys.last_mut().expect("pushed above");
+        key.columns.push(row.get("from"));
+        if let Some(to) = row.get::<Option<String>, _>("to") {
+            key.referenced_columns.push(to);
+        }
+    }
+
+    let indexes = load_indexes(&mut conn, &schema, &table).await.map_err(|e| e.to_string())?;
+
+    Ok(SqliteTableDetails {
+        table_type: table_type(entry.get("type"), definition.as_deref()).to_string(),
+        schema,
+        name: table,
+        definition,
+        columns,
+        foreign_keys: foreign_keys.into_iter().map(|(_, key)| key).collect(),
+        indexes,
+    })
+}
+
+/// Run SQL (several statements are allowed) and collect its rows, up to the row cap.
+/// Of the options only `max_rows` applies.
+#[tauri::command]
+pub async fn sqlite_execute_query(
+    databases: State<'_, SqliteDatabases>,
+    database_id: String,
+    query: String,
+    options: Option<QueryOptions>,
+) -> Result<QueryResult, String> {
+    let database = databases.get(&database_id).await?;
+    let mut conn = database.conn.lock().await;
+
+    let max_rows = options.and_then(|o| o.max_rows).unwrap_or(DEFAULT_MAX_ROWS);
+    fetch_rows(&mut conn, &query, max_rows).await
+}
+
+/// Run `PRAGMA name` or `PRAGMA name(argument)`, e.g. `integrity_check`, `table_info('users')`
+/// or `journal_mode`. The argument is passed as a string literal.
+#[tauri::command]
+pub async fn sqlite_pragma(
+    databases: State<'_, SqliteDatabases>,
+    database_id: String,
+    pragma: String,
+    argument: Option<String>,
+    schema: Option<String>,
+) -> Result<QueryResult, String> {
+    let statement = pragma_statement(&pragma, argument.as_deref(), schema.as_deref())?;
+
+    let database = databases.get(&database_id).await?;
+    let mut conn = database.conn.lock().await;
+
+    fetch_rows(&mut conn, &statement, DEFAULT_MAX_ROWS).await
+}
+
+/// The `PRAGMA` statement for `sqlite_pragma`. The name is written into the SQL as is,
+/// so only letters, digits and `_` are allowed in it.
+fn pragma_statement(pragma: &str, argument: Option<&str>, schema: Option<&str>) -> Result<String, String> {
+    if pragma.is_empty() || !pragma.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("Invalid pragma name: {}", pragma));
+    }
+
+    let mut statement = String::from("PRAGMA ");
+    if let Some(schema) = schema {
+        statement.push_str(&quote_identifier(schema));
+        statement.push('.');
+    }
+    statement.push_str(pragma);
+    if let Some(argument) = argument {
+        statement.push_str(&format!("({})", quote_literal(argument)));
+    }
+    Ok(statement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    async fn memory() -> sqlx::SqliteConnection {
+        let mut conn = SqliteConnectOptions::from_str("sqlite::memory:").unwrap().connect().await.unwrap();
+        conn.execute("CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (1), (2), (3)").await.unwrap();
+        conn
+    }
+
+    async fn count(conn: &mut sqlx::SqliteConnection) -> i64 {
+        sqlx::query_scalar("SELECT count(*) FROM t").fetch_one(conn).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn runs_statements_after_a_truncated_one() {
+        let mut conn = memory().await;
+
+        let result = fetch_rows(&mut conn, "SELECT x FROM t ORDER BY x; INSERT INTO t VALUES (4)", 2).await.unwrap();
+        assert!(result.truncated);
+        assert_eq!(result.row_count, 2);
+        assert_eq!(result.rows, vec![vec![serde_json::json!(1)], vec![serde_json::json!(2)]]);
+        assert_eq!(result.columns, ["x"]);
+        assert_eq!(count(&mut conn).await, 4);
+
+        let result = fetch_rows(&mut conn, "SELECT x FROM t", 10).await.unwrap();
+        assert!(!result.truncated);
+        assert_eq!(result.row_count, 4);
+    }
+
+    #[tokio::test]
+    async fn counts_rows_affected_per_statement() {
+        let mut conn = memory().await;
+
+        let result = fetch_rows(&mut conn, "INSERT INTO t VALUES (4), (5)", 10).await.unwrap();
+        assert_eq!(result.rows_affected, 2);
+        assert_eq!(result.row_count, 0);
+
+        // Rows returned count for the statements that return them, changes for the others
+        let result = fetch_rows(
+            &mut conn,
+            "UPDATE t SET x = x + 10 WHERE x > 3; SELECT x FROM t WHERE x > 10; DELETE FROM t WHERE x = 1",
+            10,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.rows_affected, 2 + 2 + 1);
+        assert_eq!(result.row_count, 2);
+        assert_eq!(count(&mut conn).await, 4);
+
+        // A query returning no rows doesn't repeat the change count of the write before it
+        let result = fetch_rows(&mut conn, "INSERT INTO t VALUES (6); SELECT x FROM t WHERE x < 0", 10).await.unwrap();
+        assert_eq!(result.rows_affected, 1);
+
+        // Statements are split like SQLite does, keeping a trigger body whole
+        let result = fetch_rows(
+            &mut conn,
+            "CREATE TABLE log (x INTEGER); \
+             CREATE TRIGGER logged AFTER INSERT ON t BEGIN INSERT INTO log VALUES (new.x); END; \
+             INSERT INTO t VALUES ('a;b'); SELECT count(*) FROM log",
+            10,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.rows_affected, 1 + 1);
+        assert_eq!(result.rows, vec![vec![serde_json::json!(1)]]);
+
+        let error = fetch_rows(&mut conn, "DELETE FROM t; SELECT * FROM missing", 10).await.unwrap_err();
+        assert!(error.contains("no such table: missing"), "{}", error);
+    }
+
+    #[test]
+    fn builds_pragma_statements() {
+        assert_eq!(pragma_statement("journal_mode", None, None).unwrap(), "PRAGMA journal_mode");
+        assert_eq!(
+            pragma_statement("table_info", Some("it's"), Some("aux")).unwrap(),
+            "PRAGMA \"aux\".table_info('it''s')"
+        );
+        for name in ["", "journal_mode; DROP TABLE t", "table_info('t')", "a-b"] {
+            assert_eq!(pragma_statement(name, None, None).unwrap_err(), format!("Invalid pragma name: {}", name));
+        }
+    }
+}